@@ -5,7 +5,7 @@ use libretro_rs::c_utf8::c_utf8;
 use libretro_rs::retro::env::{Init, UnloadGame};
 use libretro_rs::retro::pixel::{Format, XRGB8888};
 use libretro_rs::retro::*;
-use libretro_rs::{ext, libretro_core};
+use libretro_rs::{ext, libretro_core, system_info};
 use std::error::Error;
 
 pub struct LibretroCore {
@@ -76,11 +76,7 @@ impl<'a> Core<'a> for LibretroCore {
   type Init = ();
 
   fn get_system_info() -> SystemInfo {
-    SystemInfo::new(
-      c_utf8!("chip8.rs"),
-      c_utf8!(env!("CARGO_PKG_VERSION")),
-      ext!["png"],
-    )
+    system_info!("chip8.rs", ext!["png"])
   }
 
   fn init(_env: &mut impl Init) -> Self::Init {
@@ -22,11 +22,29 @@ pub type non_null_retro_environment_t = unsafe extern "C" fn(cmd: c_uint, data:
 pub type non_null_retro_input_poll_t = unsafe extern "C" fn();
 pub type non_null_retro_input_state_t = unsafe extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
 pub type non_null_retro_video_refresh_t = unsafe extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type non_null_retro_audio_buffer_status_callback_t = unsafe extern "C" fn(active: bool, occupancy: c_uint, underrun_likely: bool);
+
+pub type non_null_retro_set_rumble_state_t = unsafe extern "C" fn(port: c_uint, effect: retro_rumble_effect, strength: u16) -> bool;
+
+pub type non_null_retro_open_mic_t = unsafe extern "C" fn(params: *const retro_microphone_params_t) -> *mut retro_microphone_t;
+pub type non_null_retro_close_mic_t = unsafe extern "C" fn(microphone: *mut retro_microphone_t);
+pub type non_null_retro_get_mic_params_t = unsafe extern "C" fn(microphone: *const retro_microphone_t, params: *mut retro_microphone_params_t) -> bool;
+pub type non_null_retro_set_mic_state_t = unsafe extern "C" fn(microphone: *mut retro_microphone_t, state: bool) -> bool;
+pub type non_null_retro_get_mic_state_t = unsafe extern "C" fn(microphone: *const retro_microphone_t) -> bool;
+pub type non_null_retro_read_mic_t = unsafe extern "C" fn(microphone: *mut retro_microphone_t, samples: *mut i16, num_samples: usize) -> c_int;
 
 pub type non_null_retro_hw_get_current_framebuffer_t = unsafe extern "C" fn() -> usize;
 pub type non_null_retro_hw_get_proc_address_t = unsafe extern "C" fn(sym: *const c_char) -> retro_proc_address_t;
 pub type non_null_retro_hw_context_reset_t = unsafe extern "C" fn();
 
+pub type non_null_retro_netpacket_send_t = unsafe extern "C" fn(flags: c_int, buf: *const c_void, len: usize, client_id: u16);
+pub type non_null_retro_netpacket_start_t = unsafe extern "C" fn(client_id: u16, send_fn: retro_netpacket_send_t, poll_receive_fn: retro_netpacket_poll_receive_t);
+pub type non_null_retro_netpacket_receive_t = unsafe extern "C" fn(buf: *const c_void, len: usize, client_id: u16);
+pub type non_null_retro_netpacket_stop_t = unsafe extern "C" fn();
+pub type non_null_retro_netpacket_poll_t = unsafe extern "C" fn();
+pub type non_null_retro_netpacket_connected_t = unsafe extern "C" fn(client_id: u16) -> bool;
+pub type non_null_retro_netpacket_disconnected_t = unsafe extern "C" fn(client_id: u16);
+
 pub const RETRO_HW_FRAME_BUFFER_VALID: *const c_void = sptr::invalid(usize::MAX);
 
 #[cfg(test)]
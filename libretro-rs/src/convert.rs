@@ -57,3 +57,9 @@ impl UnsafeFrom<retro_log_callback> for PlatformLogger {
     PlatformLogger::new(cb.log.unwrap())
   }
 }
+
+impl UnsafeFrom<retro_rumble_interface> for RumbleInterface {
+  unsafe fn unsafe_from(rumble: retro_rumble_interface) -> Self {
+    RumbleInterface::new(rumble.set_rumble_state.unwrap())
+  }
+}
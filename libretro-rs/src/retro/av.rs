@@ -14,6 +14,18 @@ pub enum Region {
   PAL = 1,
 }
 
+impl Region {
+  /// Guesses the region from a refresh rate: rates within 1 Hz of 50 are [`Region::PAL`], and
+  /// everything else, including rates near 60 and ambiguous ones, is [`Region::NTSC`].
+  pub fn from_fps(fps: f64) -> Region {
+    if (fps - 50.0).abs() < 1.0 {
+      Region::PAL
+    } else {
+      Region::NTSC
+    }
+  }
+}
+
 impl From<Region> for c_uint {
   fn from(region: Region) -> Self {
     region as c_uint
@@ -23,6 +35,11 @@ impl From<Region> for c_uint {
 /// Rust interface for [`retro_system_av_info`].
 #[repr(transparent)]
 #[derive(Clone, Debug)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(from = "SystemAVInfoRepr", into = "SystemAVInfoRepr")
+)]
 pub struct SystemAVInfo(retro_system_av_info);
 
 impl SystemAVInfo {
@@ -39,6 +56,26 @@ impl SystemAVInfo {
     Self::new(geometry, SystemTiming::default())
   }
 
+  /// Returns a [`SystemAVInfo`] with a fixed resolution and the given timing.
+  pub fn fixed(width: u16, height: u16, fps: f64, sample_rate: f64) -> Self {
+    Self::new(
+      GameGeometry::fixed(width, height),
+      SystemTiming::new(fps, sample_rate),
+    )
+  }
+
+  /// Returns a [`SystemAVInfo`] with a fixed resolution, [`SystemTiming::NTSC_FPS`] and a 44.1khz
+  /// sample rate.
+  pub fn ntsc(width: u16, height: u16) -> Self {
+    Self::fixed(width, height, SystemTiming::NTSC_FPS, 44_100.0)
+  }
+
+  /// Returns a [`SystemAVInfo`] with a fixed resolution, [`SystemTiming::PAL_FPS`] and a 44.1khz
+  /// sample rate.
+  pub fn pal(width: u16, height: u16) -> Self {
+    Self::fixed(width, height, SystemTiming::PAL_FPS, 44_100.0)
+  }
+
   pub fn geometry(&self) -> GameGeometry {
     GameGeometry(self.0.geometry)
   }
@@ -67,6 +104,11 @@ impl From<SystemAVInfo> for retro_system_av_info {
 /// Rust interface for [`retro_game_geometry`].
 #[repr(transparent)]
 #[derive(Clone, Debug)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(from = "GameGeometryRepr", into = "GameGeometryRepr")
+)]
 pub struct GameGeometry(retro_game_geometry);
 
 impl GameGeometry {
@@ -119,6 +161,23 @@ impl GameGeometry {
     self.0.aspect_ratio
   }
 
+  /// Sets the base width and height, e.g. before [`crate::retro::env::Run::set_geometry`] when
+  /// the resolution changes. They should stay within the max dimensions.
+  pub fn set_base_dimensions(&mut self, width: u16, height: u16) {
+    self.0.base_width = width.into();
+    self.0.base_height = height.into();
+  }
+
+  pub fn set_max_dimensions(&mut self, width: u16, height: u16) {
+    self.0.max_width = width.into();
+    self.0.max_height = height.into();
+  }
+
+  /// Sets the aspect ratio. `0.0` or less derives it from the base dimensions.
+  pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+    self.0.aspect_ratio = aspect_ratio;
+  }
+
   pub fn into_inner(self) -> retro_game_geometry {
     self.0
   }
@@ -145,9 +204,20 @@ impl From<GameGeometry> for retro_game_geometry {
 /// Rust interface for [`retro_system_timing`].
 #[repr(transparent)]
 #[derive(Clone, Debug)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(from = "SystemTimingRepr", into = "SystemTimingRepr")
+)]
 pub struct SystemTiming(retro_system_timing);
 
 impl SystemTiming {
+  /// The field rate of NTSC video, 60000/1001 or about 59.94 fields per second.
+  pub const NTSC_FPS: f64 = 60_000.0 / 1_001.0;
+
+  /// The field rate of PAL video.
+  pub const PAL_FPS: f64 = 50.0;
+
   /// Main constructor.
   pub fn new(fps: f64, sample_rate: f64) -> Self {
     Self(retro_system_timing { fps, sample_rate })
@@ -194,8 +264,97 @@ impl From<SystemTiming> for retro_system_timing {
   }
 }
 
+// The wrapped C structs can't derive serde's traits, so they're serialized through these
+// plain structs instead.
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SystemAVInfoRepr {
+  geometry: GameGeometry,
+  timing: SystemTiming,
+}
+
+#[cfg(feature = "serde")]
+impl From<SystemAVInfoRepr> for SystemAVInfo {
+  fn from(repr: SystemAVInfoRepr) -> Self {
+    Self::new(repr.geometry, repr.timing)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<SystemAVInfo> for SystemAVInfoRepr {
+  fn from(av_info: SystemAVInfo) -> Self {
+    Self {
+      geometry: av_info.geometry(),
+      timing: av_info.timing(),
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct GameGeometryRepr {
+  base_width: c_uint,
+  base_height: c_uint,
+  max_width: c_uint,
+  max_height: c_uint,
+  aspect_ratio: f32,
+}
+
+#[cfg(feature = "serde")]
+impl From<GameGeometryRepr> for GameGeometry {
+  fn from(repr: GameGeometryRepr) -> Self {
+    Self(retro_game_geometry {
+      base_width: repr.base_width,
+      base_height: repr.base_height,
+      max_width: repr.max_width,
+      max_height: repr.max_height,
+      aspect_ratio: repr.aspect_ratio,
+    })
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<GameGeometry> for GameGeometryRepr {
+  fn from(geometry: GameGeometry) -> Self {
+    let geometry = geometry.into_inner();
+    Self {
+      base_width: geometry.base_width,
+      base_height: geometry.base_height,
+      max_width: geometry.max_width,
+      max_height: geometry.max_height,
+      aspect_ratio: geometry.aspect_ratio,
+    }
+  }
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SystemTimingRepr {
+  fps: f64,
+  sample_rate: f64,
+}
+
+#[cfg(feature = "serde")]
+impl From<SystemTimingRepr> for SystemTiming {
+  fn from(repr: SystemTimingRepr) -> Self {
+    Self::new(repr.fps, repr.sample_rate)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<SystemTiming> for SystemTimingRepr {
+  fn from(timing: SystemTiming) -> Self {
+    Self {
+      fps: timing.fps(),
+      sample_rate: timing.sample_rate(),
+    }
+  }
+}
+
 #[non_exhaustive]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PixelFormat {
   #[default]
   RGB1555 = 0,
@@ -203,12 +362,38 @@ pub enum PixelFormat {
   RGB565 = 2,
 }
 
+impl PixelFormat {
+  /// The formats to try when negotiating with the frontend, from most to least preferred.
+  /// See [`crate::retro::env::GetAvInfo::set_best_pixel_format`].
+  pub const FALLBACK_ORDER: [PixelFormat; 3] = [
+    PixelFormat::XRGB8888,
+    PixelFormat::RGB565,
+    PixelFormat::RGB1555,
+  ];
+
+  /// The size of a pixel in this format, in bytes.
+  pub fn bytes_per_pixel(&self) -> usize {
+    match self {
+      PixelFormat::RGB1555 | PixelFormat::RGB565 => 2,
+      PixelFormat::XRGB8888 => 4,
+    }
+  }
+
+  /// The pitch of a tightly packed line of `width` pixels in this format, in bytes.
+  pub fn pitch(&self, width: u32) -> usize {
+    width as usize * self.bytes_per_pixel()
+  }
+}
+
 impl From<PixelFormat> for c_int {
   fn from(value: PixelFormat) -> Self {
     value as c_int
   }
 }
 
+/// Counter-clockwise screen rotation, for [`Environment::set_rotation`].
+///
+/// [`Environment::set_rotation`]: crate::retro::env::Environment::set_rotation
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ScreenRotation {
   #[default]
@@ -236,6 +421,17 @@ impl Message {
     })
   }
 
+  /// A message shown for `seconds` at `fps` frames per second, e.g. the rate from
+  /// [`SystemTiming::fps`]. The message is always shown for at least one frame.
+  pub fn for_seconds<'a>(msg: impl Into<&'a CStr>, seconds: f32, fps: f64) -> Self {
+    // `max` before `min` so a NaN duration also shows the message for one frame.
+    let frames = (f64::from(seconds) * fps)
+      .round()
+      .max(1.0)
+      .min(c_uint::MAX.into());
+    Self::new(msg, frames as c_uint)
+  }
+
   pub fn msg(&self) -> &CStr {
     unsafe { CStr::from_ptr(self.0.msg) }
   }
@@ -251,6 +447,141 @@ impl From<Message> for retro_message {
   }
 }
 
+/// A notification for [`crate::retro::env::Environment::set_message_ext`], which lasts a number
+/// of milliseconds rather than frames. When several messages are on screen, frontends show the
+/// ones with the highest priority first.
+#[repr(transparent)]
+#[derive(Clone, Debug)]
+pub struct MessageExt(retro_message_ext);
+
+impl MessageExt {
+  pub fn new<'a>(msg: impl Into<&'a CStr>, duration_ms: c_uint, priority: c_uint) -> Self {
+    Self(retro_message_ext {
+      msg: msg.into().as_ptr(),
+      duration: duration_ms,
+      priority,
+      level: retro_log_level::RETRO_LOG_INFO,
+      target: retro_message_target::RETRO_MESSAGE_TARGET_ALL,
+      type_: retro_message_type::RETRO_MESSAGE_TYPE_NOTIFICATION,
+      progress: -1,
+    })
+  }
+
+  pub fn msg(&self) -> &CStr {
+    unsafe { CStr::from_ptr(self.0.msg) }
+  }
+
+  pub fn duration_ms(&self) -> c_uint {
+    self.0.duration
+  }
+
+  pub fn priority(&self) -> c_uint {
+    self.0.priority
+  }
+}
+
+impl From<MessageExt> for retro_message_ext {
+  fn from(value: MessageExt) -> Self {
+    value.0
+  }
+}
+
+/// Shows several messages one after the other, since each call to
+/// [`crate::retro::env::Environment::set_message`] replaces the message on screen.
+///
+/// Messages are copied when pushed. When the frontend supports
+/// [`crate::retro::env::Environment::set_message_ext`], [`MessageQueue::flush`] sends every
+/// pending message at once, with priorities that keep them in order. Otherwise, it shows the next
+/// one once the previous one has been on screen for its number of frames.
+#[derive(Clone, Debug)]
+pub struct MessageQueue {
+  pending: std::collections::VecDeque<(std::ffi::CString, c_uint)>,
+  remaining_frames: c_uint,
+  fps: f64,
+}
+
+impl Default for MessageQueue {
+  fn default() -> Self {
+    Self {
+      pending: Default::default(),
+      remaining_frames: 0,
+      fps: 60.0,
+    }
+  }
+}
+
+impl MessageQueue {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the frame rate used to convert message durations to milliseconds for
+  /// [`crate::retro::env::Environment::set_message_ext`], e.g. the rate from
+  /// [`SystemTiming::fps`]. Defaults to 60.
+  pub fn with_fps(mut self, fps: f64) -> Self {
+    self.fps = fps;
+    self
+  }
+
+  /// Queues a copy of `message`, to be shown after the messages already queued.
+  pub fn push(&mut self, message: &Message) {
+    self
+      .pending
+      .push_back((message.msg().to_owned(), message.frames()));
+  }
+
+  /// Returns true if no message is waiting to be shown.
+  pub fn is_empty(&self) -> bool {
+    self.pending.is_empty()
+  }
+
+  /// Shows the next message if the previous one is done. Call it once per
+  /// [`crate::retro::Core::run`].
+  ///
+  /// A message the frontend rejects is dropped, so the following ones aren't held up.
+  pub fn flush(
+    &mut self,
+    env: &mut impl crate::retro::env::Environment,
+  ) -> crate::retro::env::Result<()> {
+    if !self.pending.is_empty() && env.get_message_interface_version() >= 1 {
+      return self.flush_ext(env);
+    }
+    self.remaining_frames = self.remaining_frames.saturating_sub(1);
+    if self.remaining_frames > 0 {
+      return Ok(());
+    }
+    let Some((msg, frames)) = self.pending.pop_front() else {
+      return Ok(());
+    };
+    let result = env.set_message(&Message::new(msg.as_c_str(), frames));
+    self.remaining_frames = if result.is_ok() { frames } else { 0 };
+    result
+  }
+
+  /// Sends every pending message, giving the earlier ones higher priorities so the frontend
+  /// shows them first.
+  fn flush_ext(
+    &mut self,
+    env: &mut impl crate::retro::env::Environment,
+  ) -> crate::retro::env::Result<()> {
+    let count = self.pending.len();
+    let mut result = Ok(());
+    for (index, (msg, frames)) in self.pending.drain(..).enumerate() {
+      let priority = c_uint::try_from(count - index).unwrap_or(c_uint::MAX);
+      let duration_ms = (f64::from(frames) * 1000.0 / self.fps)
+        .round()
+        .min(c_uint::MAX.into()) as c_uint;
+      let message = MessageExt::new(msg.as_c_str(), duration_ms, priority);
+      result = result.and(env.set_message_ext(&message));
+    }
+    result
+  }
+}
+
+/// A video frame of `T` pixels, for [`crate::retro::Callbacks::upload_video_frame`].
+///
+/// The constructors check that `data` covers every line of the frame, so the frontend never
+/// reads out of bounds. The pitch is the distance between the start of two lines, in pixels.
 pub struct Frame<'a, T> {
   data: &'a [T],
   width: u32,
@@ -297,11 +628,51 @@ impl<'a, T> Frame<'a, T> {
     }
   }
 
+  /// A frame whose lines are stored right after each other in `data`.
+  ///
+  /// # Panics
+  /// Panics if `data` is shorter than `width * height`.
+  pub fn from_packed(data: &'a [T], width: u32, height: u32) -> Self {
+    Self::from_rows(data, width, height, width)
+  }
+
+  /// A frame whose lines start every `pitch` pixels in `data`, e.g. a region of a larger buffer.
+  ///
+  /// # Panics
+  /// Panics if `pitch` is smaller than `width`, or if `data` doesn't cover the last line.
+  pub fn from_rows(data: &'a [T], width: u32, height: u32, pitch: u32) -> Self {
+    let frame = Self {
+      data,
+      width,
+      height,
+      pitch,
+    };
+    frame.check_bounds();
+    frame
+  }
+
+  /// Changes the pitch of the frame.
+  ///
+  /// # Panics
+  /// Panics if `pitch` is smaller than the width, or if the data doesn't cover the last line.
   pub fn with_pitch(mut self, pitch: u32) -> Self {
     self.pitch = pitch;
+    self.check_bounds();
     self
   }
 
+  fn check_bounds(&self) {
+    assert!(self.pitch >= self.width, "pitch should be at least width");
+    let required = match self.height {
+      0 => 0,
+      height => self.pitch as usize * (height as usize - 1) + self.width as usize,
+    };
+    assert!(
+      self.data.len() >= required,
+      "data should cover every line of the frame"
+    );
+  }
+
   pub fn data(&self) -> &'a [T] {
     self.data
   }
@@ -319,6 +690,267 @@ impl<'a, T> Frame<'a, T> {
   }
 }
 
+/// Whether the frontend wants the core to produce video and audio.
+/// See [`Environment::get_audio_video_enable`].
+///
+/// [`Environment::get_audio_video_enable`]: crate::retro::env::Environment::get_audio_video_enable
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AudioVideoEnable(c_int);
+
+impl AudioVideoEnable {
+  pub fn new(flags: c_int) -> Self {
+    Self(flags)
+  }
+
+  pub fn into_inner(self) -> c_int {
+    self.0
+  }
+
+  /// If false, the frontend discards video, so the core may skip rendering.
+  pub fn video(self) -> bool {
+    self.0 & 1 != 0
+  }
+
+  /// If false, the frontend discards audio, so the core may skip generating it.
+  pub fn audio(self) -> bool {
+    self.0 & 2 != 0
+  }
+
+  /// If true, savestates are only used within the current session, e.g. for runahead, so the
+  /// core may skip saving data that doesn't affect emulation.
+  pub fn fast_savestates(self) -> bool {
+    self.0 & 4 != 0
+  }
+
+  /// If true, the core must not produce audio at all, as opposed to [`AudioVideoEnable::audio`]
+  /// where it still may.
+  pub fn hard_disable_audio(self) -> bool {
+    self.0 & 8 != 0
+  }
+}
+
+impl Default for AudioVideoEnable {
+  /// Video and audio enabled.
+  fn default() -> Self {
+    Self(1 | 2)
+  }
+}
+
+/// How the core will access a [`SoftwareFramebuffer`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FramebufferAccess(c_uint);
+
+impl FramebufferAccess {
+  /// The core will write to the framebuffer.
+  pub const WRITE: Self = Self(RETRO_MEMORY_ACCESS_WRITE);
+
+  /// The core will read from the framebuffer.
+  pub const READ: Self = Self(RETRO_MEMORY_ACCESS_READ);
+
+  pub fn new(flags: c_uint) -> Self {
+    Self(flags)
+  }
+
+  pub fn into_inner(self) -> c_uint {
+    self.0
+  }
+
+  /// Returns true if all the flags in `other` are set.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for FramebufferAccess {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for FramebufferAccess {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+/// A framebuffer owned by the frontend, which the core can render into directly to avoid copying
+/// a frame. See [`Run::get_current_software_framebuffer`].
+///
+/// The framebuffer is only valid until the end of the current [`Core::run`], and must be
+/// uploaded with [`Callbacks::upload_software_framebuffer`].
+///
+/// [`Run::get_current_software_framebuffer`]: crate::retro::env::Run::get_current_software_framebuffer
+/// [`Core::run`]: crate::retro::Core::run
+/// [`Callbacks::upload_software_framebuffer`]: crate::retro::Callbacks::upload_software_framebuffer
+#[derive(Debug)]
+pub struct SoftwareFramebuffer<'a> {
+  data: &'a mut [u8],
+  width: c_uint,
+  height: c_uint,
+  pitch: usize,
+  format: PixelFormat,
+  memory_flags: c_uint,
+}
+
+impl<'a> SoftwareFramebuffer<'a> {
+  /// # Safety
+  /// `framebuffer` must have been filled in by `RETRO_ENVIRONMENT_GET_CURRENT_SOFTWARE_FRAMEBUFFER`
+  /// and must not be used after the current `retro_run` returns.
+  pub(crate) unsafe fn from_raw(framebuffer: retro_framebuffer) -> Option<Self> {
+    use retro_pixel_format::*;
+    let format = match framebuffer.format {
+      RETRO_PIXEL_FORMAT_0RGB1555 => PixelFormat::RGB1555,
+      RETRO_PIXEL_FORMAT_XRGB8888 => PixelFormat::XRGB8888,
+      RETRO_PIXEL_FORMAT_RGB565 => PixelFormat::RGB565,
+      _ => return None,
+    };
+    if framebuffer.data.is_null() {
+      return None;
+    }
+    let len = framebuffer.pitch * framebuffer.height as usize;
+    Some(Self {
+      data: core::slice::from_raw_parts_mut(framebuffer.data as *mut u8, len),
+      width: framebuffer.width,
+      height: framebuffer.height,
+      pitch: framebuffer.pitch,
+      format,
+      memory_flags: framebuffer.memory_flags,
+    })
+  }
+
+  /// The contents of the framebuffer, `pitch() * height()` bytes long. The initial contents are
+  /// unspecified.
+  pub fn data(&self) -> &[u8] {
+    self.data
+  }
+
+  pub fn data_mut(&mut self) -> &mut [u8] {
+    self.data
+  }
+
+  pub fn width(&self) -> c_uint {
+    self.width
+  }
+
+  pub fn height(&self) -> c_uint {
+    self.height
+  }
+
+  /// The number of bytes between the start of a line and the start of the next one.
+  pub fn pitch(&self) -> usize {
+    self.pitch
+  }
+
+  /// The pixel format to render in, which may differ from the format the core requested.
+  pub fn format(&self) -> PixelFormat {
+    self.format
+  }
+
+  /// The `RETRO_MEMORY_TYPE_*` flags describing how the framebuffer is mapped.
+  pub fn memory_flags(&self) -> c_uint {
+    self.memory_flags
+  }
+
+  /// Returns false if the framebuffer is uncached, so random writes and reads are very slow.
+  pub fn is_cached(&self) -> bool {
+    self.memory_flags & RETRO_MEMORY_TYPE_CACHED != 0
+  }
+}
+
+/// An owned frame buffer, allocated with at least the given alignment, for cores that need
+/// aligned video memory; see [`crate::retro::Core::VIDEO_ALIGNMENT`].
+pub struct Framebuffer<T> {
+  ptr: core::ptr::NonNull<T>,
+  width: u32,
+  height: u32,
+  layout: std::alloc::Layout,
+}
+
+impl<T: Copy + Default> Framebuffer<T> {
+  /// Allocates a `width * height` frame buffer filled with `T::default()`, aligned to at least
+  /// `alignment` bytes.
+  ///
+  /// # Panics
+  /// If `alignment` isn't a power of two.
+  pub fn new(width: u32, height: u32, alignment: usize) -> Self {
+    let len = width as usize * height as usize;
+    let align = alignment.max(core::mem::align_of::<T>());
+    let layout = std::alloc::Layout::array::<T>(len)
+      .and_then(|layout| layout.align_to(align))
+      .expect("alignment should be a power of two");
+    let ptr = if layout.size() == 0 {
+      // Zero-sized allocations aren't allowed, but a dangling pointer must still be aligned.
+      core::ptr::NonNull::new(align as *mut T).unwrap()
+    } else {
+      let ptr = unsafe { std::alloc::alloc(layout) } as *mut T;
+      let Some(ptr) = core::ptr::NonNull::new(ptr) else {
+        std::alloc::handle_alloc_error(layout)
+      };
+      for i in 0..len {
+        unsafe { ptr.as_ptr().add(i).write(T::default()) };
+      }
+      ptr
+    };
+    Self {
+      ptr,
+      width,
+      height,
+      layout,
+    }
+  }
+}
+
+impl<T> Framebuffer<T> {
+  pub fn data(&self) -> &[T] {
+    let len = self.width as usize * self.height as usize;
+    unsafe { core::slice::from_raw_parts(self.ptr.as_ptr(), len) }
+  }
+
+  pub fn data_mut(&mut self) -> &mut [T] {
+    let len = self.width as usize * self.height as usize;
+    unsafe { core::slice::from_raw_parts_mut(self.ptr.as_ptr(), len) }
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// The [`Frame`] to pass to [`crate::retro::Callbacks::upload_video_frame`].
+  pub fn frame(&self) -> Frame<'_, T> {
+    Frame::new(self.data(), self.width, self.height)
+  }
+}
+
+impl<T> Drop for Framebuffer<T> {
+  fn drop(&mut self) {
+    if self.layout.size() != 0 {
+      unsafe { std::alloc::dealloc(self.ptr.as_ptr() as *mut u8, self.layout) }
+    }
+  }
+}
+
+impl<T: core::fmt::Debug> core::fmt::Debug for Framebuffer<T> {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    f.debug_struct("Framebuffer")
+      .field("width", &self.width)
+      .field("height", &self.height)
+      .field("align", &self.layout.align())
+      .finish_non_exhaustive()
+  }
+}
+
+// The buffer is uniquely owned, like a `Box<[T]>`.
+unsafe impl<T: Send> Send for Framebuffer<T> {}
+unsafe impl<T: Sync> Sync for Framebuffer<T> {}
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct SoftwareRenderEnabled(pub(crate) ());
 
@@ -329,12 +961,33 @@ pub trait HWRenderEnabled: private::Sealed {}
 
 impl HWRenderEnabled for GLRenderEnabled {}
 
+/// The frontend's OpenGL callbacks, passed to [`crate::retro::OpenGLCore::context_reset`].
+///
+/// The symbols they return are only valid while the context is alive, i.e. from a context reset
+/// until the next [`crate::retro::OpenGLCore::context_destroy`], so GL function pointers must be
+/// loaded again on every context reset.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct GLContextCallbacks {
   pub get_proc_address_cb: non_null_retro_hw_get_proc_address_t,
   pub get_current_framebuffer_cb: non_null_retro_hw_get_current_framebuffer_t,
 }
 
+impl GLContextCallbacks {
+  /// Looks up an OpenGL function, or returns `None` if the frontend doesn't provide it.
+  pub fn get_proc_address(&self, name: &CStr) -> Option<*const c_void> {
+    unsafe { (self.get_proc_address_cb)(name.as_ptr()) }.map(|f| f as *const c_void)
+  }
+
+  /// A loader function for the `gl` and `glow` crates, e.g. `gl::load_with(callbacks.loader())`,
+  /// which returns a null pointer for functions the frontend doesn't provide.
+  pub fn loader(self) -> impl Fn(&str) -> *const c_void {
+    move |name| match std::ffi::CString::new(name) {
+      Ok(name) => self.get_proc_address(&name).unwrap_or(core::ptr::null()),
+      Err(_) => core::ptr::null(),
+    }
+  }
+}
+
 mod private {
   use crate::retro::GLRenderEnabled;
 
@@ -446,6 +1099,113 @@ impl From<GLOptions> for retro_hw_render_callback {
   }
 }
 
+/// A hardware rendering API, as reported by
+/// [`crate::retro::env::Environment::get_preferred_hw_render`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HwContextType {
+  /// `RETRO_HW_CONTEXT_NONE`: the frontend prefers software rendering.
+  Software,
+  OpenGL,
+  OpenGLES2,
+  OpenGLCore,
+  OpenGLES3,
+  /// OpenGL ES, with the version set in [`retro_hw_render_callback`].
+  OpenGLESVersion,
+  Vulkan,
+  /// Direct3D, with the version set in [`retro_hw_render_callback`].
+  Direct3D,
+  /// A context type this crate doesn't know about.
+  Unknown(c_uint),
+}
+
+impl From<c_uint> for HwContextType {
+  fn from(value: c_uint) -> Self {
+    use retro_hw_context_type::*;
+    match value {
+      x if x == RETRO_HW_CONTEXT_NONE as c_uint => Self::Software,
+      x if x == RETRO_HW_CONTEXT_OPENGL as c_uint => Self::OpenGL,
+      x if x == RETRO_HW_CONTEXT_OPENGLES2 as c_uint => Self::OpenGLES2,
+      x if x == RETRO_HW_CONTEXT_OPENGL_CORE as c_uint => Self::OpenGLCore,
+      x if x == RETRO_HW_CONTEXT_OPENGLES3 as c_uint => Self::OpenGLES3,
+      x if x == RETRO_HW_CONTEXT_OPENGLES_VERSION as c_uint => Self::OpenGLESVersion,
+      x if x == RETRO_HW_CONTEXT_VULKAN as c_uint => Self::Vulkan,
+      x if x == RETRO_HW_CONTEXT_DIRECT3D as c_uint => Self::Direct3D,
+      _ => Self::Unknown(value),
+    }
+  }
+}
+
+/// The rendering API of a [`HwRenderInterface`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HwRenderInterfaceType {
+  Vulkan,
+  D3D9,
+  D3D10,
+  D3D11,
+  D3D12,
+  GsKitPS2,
+  /// An interface type this crate doesn't know about.
+  Unknown(c_int),
+}
+
+impl From<c_int> for HwRenderInterfaceType {
+  fn from(value: c_int) -> Self {
+    match value {
+      0 => Self::Vulkan,
+      1 => Self::D3D9,
+      2 => Self::D3D10,
+      3 => Self::D3D11,
+      4 => Self::D3D12,
+      5 => Self::GsKitPS2,
+      _ => Self::Unknown(value),
+    }
+  }
+}
+
+/// The API-specific rendering interface returned by
+/// [`crate::retro::env::Environment::get_hw_render_interface`], e.g.
+/// `retro_hw_render_interface_vulkan` for Vulkan.
+///
+/// The interface is only valid between the frontend resetting the hardware context and destroying
+/// it.
+#[derive(Clone, Copy, Debug)]
+pub struct HwRenderInterface(core::ptr::NonNull<retro_hw_render_interface>);
+
+impl HwRenderInterface {
+  /// # Safety
+  /// `iface` must point to an interface that starts with a [`retro_hw_render_interface`] header.
+  pub unsafe fn new(iface: core::ptr::NonNull<retro_hw_render_interface>) -> Self {
+    Self(iface)
+  }
+
+  /// The rendering API of the interface, which tells the core what to cast it to.
+  pub fn interface_type(&self) -> HwRenderInterfaceType {
+    // The type is read as an integer, since frontends may return types missing from the bindings.
+    unsafe { *self.0.as_ptr().cast::<c_int>() }.into()
+  }
+
+  /// The version of the API-specific interface.
+  pub fn interface_version(&self) -> c_uint {
+    unsafe { self.0.as_ref().interface_version }
+  }
+
+  pub fn as_ptr(&self) -> *const retro_hw_render_interface {
+    self.0.as_ptr()
+  }
+
+  /// Reinterprets the interface as the API-specific interface `T`.
+  ///
+  /// # Safety
+  /// `T` must match [`HwRenderInterface::interface_type`] and
+  /// [`HwRenderInterface::interface_version`], and the reference must not outlive the hardware
+  /// context.
+  pub unsafe fn cast<T>(&self) -> &T {
+    self.0.cast::<T>().as_ref()
+  }
+}
+
 /// Pixel formats.
 pub mod pixel {
   use arbitrary_int::{u5, u6};
@@ -455,6 +1215,34 @@ pub mod pixel {
   #[derive(Debug, PartialEq, Eq, Hash)]
   pub struct Format<T>(pub(crate) PhantomData<T>);
 
+  /// The [`Format`] token of whichever pixel format the frontend accepted, as returned by
+  /// [`crate::retro::env::GetAvInfo::set_best_pixel_format`].
+  #[derive(Debug, PartialEq, Eq, Hash)]
+  pub enum AnyFormat {
+    ORGB1555(Format<ORGB1555>),
+    XRGB8888(Format<XRGB8888>),
+    RGB565(Format<RGB565>),
+  }
+
+  impl AnyFormat {
+    pub(crate) fn new(format: super::PixelFormat) -> Self {
+      match format {
+        super::PixelFormat::RGB1555 => AnyFormat::ORGB1555(Format(PhantomData)),
+        super::PixelFormat::XRGB8888 => AnyFormat::XRGB8888(Format(PhantomData)),
+        super::PixelFormat::RGB565 => AnyFormat::RGB565(Format(PhantomData)),
+      }
+    }
+
+    /// The pixel format this token stands for.
+    pub fn pixel_format(&self) -> super::PixelFormat {
+      match self {
+        AnyFormat::ORGB1555(_) => super::PixelFormat::RGB1555,
+        AnyFormat::XRGB8888(_) => super::PixelFormat::XRGB8888,
+        AnyFormat::RGB565(_) => super::PixelFormat::RGB565,
+      }
+    }
+  }
+
   #[bitfield(u16, default: 0)]
   #[derive(Debug, PartialEq, Eq, Hash)]
   pub struct ORGB1555 {
@@ -489,4 +1277,366 @@ pub mod pixel {
     #[bits(0..=4, rw)]
     b: u5,
   }
+
+  macro_rules! impl_from_raw_slice {
+    ($pixel:ident, $raw:ty) => {
+      impl $pixel {
+        #[doc = concat!("Reinterprets native-endian `", stringify!($raw), "` pixels as `", stringify!($pixel), "`, e.g. to build a [`crate::retro::av::Frame`] from a core's raw framebuffer.")]
+        pub fn from_raw_slice(raw: &[$raw]) -> &[Self] {
+          // SAFETY: bitfields are `repr(C)` around their raw integer, so they share its layout.
+          unsafe { std::slice::from_raw_parts(raw.as_ptr().cast(), raw.len()) }
+        }
+      }
+    };
+  }
+
+  impl_from_raw_slice!(ORGB1555, u16);
+  impl_from_raw_slice!(RGB565, u16);
+  impl_from_raw_slice!(XRGB8888, u32);
+
+  /// Widens a 5 or 6 bit channel to 8 bits, so the maximum value maps to `0xFF`.
+  fn widen(value: u16, bits: u32) -> u32 {
+    let value = u32::from(value);
+    (value << (8 - bits)) | (value >> (2 * bits - 8))
+  }
+
+  /// Converts RGB565 pixels to XRGB8888, for cores that render in RGB565 but set XRGB8888.
+  ///
+  /// # Panics
+  /// Panics if `src` and `dst` don't have the same length.
+  pub fn convert_rgb565_to_xrgb8888(src: &[u16], dst: &mut [u32]) {
+    assert_eq!(
+      src.len(),
+      dst.len(),
+      "src and dst should have the same length"
+    );
+    for (dst, &src) in dst.iter_mut().zip(src) {
+      let (r, g, b) = (src >> 11, (src >> 5) & 0x3F, src & 0x1F);
+      *dst = (widen(r, 5) << 16) | (widen(g, 6) << 8) | widen(b, 5);
+    }
+  }
+
+  /// Converts 0RGB1555 pixels to XRGB8888, for cores that render in 0RGB1555 but set XRGB8888.
+  ///
+  /// # Panics
+  /// Panics if `src` and `dst` don't have the same length.
+  pub fn convert_0rgb1555_to_xrgb8888(src: &[u16], dst: &mut [u32]) {
+    assert_eq!(
+      src.len(),
+      dst.len(),
+      "src and dst should have the same length"
+    );
+    for (dst, &src) in dst.iter_mut().zip(src) {
+      let (r, g, b) = ((src >> 10) & 0x1F, (src >> 5) & 0x1F, src & 0x1F);
+      *dst = (widen(r, 5) << 16) | (widen(g, 5) << 8) | widen(b, 5);
+    }
+  }
+
+  /// Converts 0RGB1555 pixels to RGB565, for cores that render in 0RGB1555 but set RGB565.
+  ///
+  /// # Panics
+  /// Panics if `src` and `dst` don't have the same length.
+  pub fn convert_0rgb1555_to_rgb565(src: &[u16], dst: &mut [u16]) {
+    assert_eq!(
+      src.len(),
+      dst.len(),
+      "src and dst should have the same length"
+    );
+    for (dst, &src) in dst.iter_mut().zip(src) {
+      let g = (src >> 5) & 0x1F;
+      *dst = ((src & 0x7C00) << 1) | (((g << 1) | (g >> 4)) << 5) | (src & 0x1F);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use pixel::XRGB8888;
+
+  #[test]
+  fn test_av_info_getters() {
+    let geometry = GameGeometry::new(256..=512, 224..=448, 4.0 / 3.0);
+    let info = SystemAVInfo::new(geometry, SystemTiming::new(60.0988, 32_040.5));
+    let geometry = info.geometry();
+    assert_eq!((geometry.base_width(), geometry.base_height()), (256, 224));
+    assert_eq!((geometry.max_width(), geometry.max_height()), (512, 448));
+    assert_eq!(geometry.aspect_ratio(), 4.0 / 3.0);
+    assert_eq!(info.timing().fps(), 60.0988);
+    assert_eq!(info.timing().sample_rate(), 32_040.5);
+  }
+
+  #[test]
+  fn test_av_info_constructors() {
+    let info = SystemAVInfo::fixed(320, 240, 60.0988, 32_040.5);
+    assert_eq!(
+      (info.geometry().max_width(), info.geometry().max_height()),
+      (320, 240)
+    );
+    assert_eq!(info.timing().fps(), 60.0988);
+    assert_eq!(info.timing().sample_rate(), 32_040.5);
+
+    let info = SystemAVInfo::ntsc(256, 224);
+    assert_eq!(
+      (info.geometry().base_width(), info.geometry().base_height()),
+      (256, 224)
+    );
+    assert!((info.timing().fps() - 59.94).abs() < 0.001);
+    assert_eq!(info.timing().sample_rate(), 44_100.0);
+    assert_eq!(Region::from_fps(info.timing().fps()), Region::NTSC);
+
+    let info = SystemAVInfo::pal(256, 240);
+    assert_eq!(info.timing().fps(), 50.0);
+    assert_eq!(info.timing().sample_rate(), 44_100.0);
+    assert_eq!(Region::from_fps(info.timing().fps()), Region::PAL);
+  }
+
+  #[test]
+  fn test_geometry_setters() {
+    let mut geometry = GameGeometry::fixed(256, 224);
+    geometry.set_max_dimensions(512, 480);
+    geometry.set_base_dimensions(512, 448);
+    geometry.set_aspect_ratio(8.0 / 7.0);
+    assert_eq!((geometry.base_width(), geometry.base_height()), (512, 448));
+    assert_eq!((geometry.max_width(), geometry.max_height()), (512, 480));
+    assert_eq!(geometry.aspect_ratio(), 8.0 / 7.0);
+  }
+
+  #[test]
+  fn test_region_from_fps() {
+    assert_eq!(Region::from_fps(59.94), Region::NTSC);
+    assert_eq!(Region::from_fps(60.0988), Region::NTSC);
+    assert_eq!(Region::from_fps(50.0), Region::PAL);
+    assert_eq!(Region::from_fps(49.701), Region::PAL);
+    assert_eq!(Region::from_fps(55.0), Region::NTSC);
+  }
+
+  #[test]
+  fn test_message_for_seconds() {
+    let msg = CStr::from_bytes_with_nul(b"Saved\0").unwrap();
+    let message = Message::for_seconds(msg, 2.0, 60.0);
+    assert_eq!((message.msg(), message.frames()), (msg, 120));
+    assert_eq!(Message::for_seconds(msg, 1.5, 59.94).frames(), 90);
+    assert_eq!(Message::for_seconds(msg, 0.0, 60.0).frames(), 1);
+    assert_eq!(Message::for_seconds(msg, -3.0, 50.0).frames(), 1);
+    assert_eq!(Message::for_seconds(msg, f32::NAN, 50.0).frames(), 1);
+    assert_eq!(
+      Message::for_seconds(msg, f32::MAX, 60.0).frames(),
+      c_uint::MAX
+    );
+  }
+
+  static SHOWN_MESSAGES: std::sync::Mutex<Vec<(String, c_uint)>> =
+    std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn message_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_MESSAGE {
+      return false;
+    }
+    let message = &*(data as *const retro_message);
+    let msg = CStr::from_ptr(message.msg).to_str().unwrap().to_owned();
+    SHOWN_MESSAGES.lock().unwrap().push((msg, message.frames));
+    true
+  }
+
+  #[test]
+  fn test_message_queue_cycles() {
+    let mut env: non_null_retro_environment_t = message_environment;
+    let mut queue = MessageQueue::new();
+    let saved = CStr::from_bytes_with_nul(b"Saved\0").unwrap();
+    let loaded = CStr::from_bytes_with_nul(b"Loaded\0").unwrap();
+    queue.push(&Message::new(saved, 3));
+    queue.push(&Message::new(loaded, 1));
+    let mut shown_per_frame = vec![];
+    for _ in 0..6 {
+      queue.flush(&mut env).unwrap();
+      shown_per_frame.push(SHOWN_MESSAGES.lock().unwrap().len());
+    }
+    assert_eq!(shown_per_frame, [1, 1, 1, 2, 2, 2]);
+    assert_eq!(
+      *SHOWN_MESSAGES.lock().unwrap(),
+      [("Saved".to_owned(), 3), ("Loaded".to_owned(), 1)]
+    );
+    assert!(queue.is_empty());
+  }
+
+  static REJECTION_MESSAGES: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn rejecting_message_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_MESSAGE {
+      return false;
+    }
+    let message = &*(data as *const retro_message);
+    let msg = CStr::from_ptr(message.msg).to_str().unwrap().to_owned();
+    let accepted = msg != "Rejected";
+    REJECTION_MESSAGES.lock().unwrap().push(msg);
+    accepted
+  }
+
+  #[test]
+  fn test_message_queue_drops_rejected_messages() {
+    let mut env: non_null_retro_environment_t = rejecting_message_environment;
+    let mut queue = MessageQueue::new();
+    let rejected = CStr::from_bytes_with_nul(b"Rejected\0").unwrap();
+    let saved = CStr::from_bytes_with_nul(b"Saved\0").unwrap();
+    queue.push(&Message::new(rejected, 100));
+    queue.push(&Message::new(saved, 1));
+    assert!(queue.flush(&mut env).is_err());
+    queue.flush(&mut env).unwrap();
+    assert_eq!(*REJECTION_MESSAGES.lock().unwrap(), ["Rejected", "Saved"]);
+  }
+
+  static EXT_MESSAGES: std::sync::Mutex<Vec<(String, c_uint, c_uint)>> =
+    std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn message_ext_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION => {
+        *(data as *mut c_uint) = 1;
+        true
+      }
+      RETRO_ENVIRONMENT_SET_MESSAGE_EXT => {
+        let message = &*(data as *const retro_message_ext);
+        let msg = CStr::from_ptr(message.msg).to_str().unwrap().to_owned();
+        let entry = (msg, message.duration, message.priority);
+        EXT_MESSAGES.lock().unwrap().push(entry);
+        true
+      }
+      _ => false,
+    }
+  }
+
+  #[test]
+  fn test_message_queue_uses_message_ext() {
+    let mut env: non_null_retro_environment_t = message_ext_environment;
+    let mut queue = MessageQueue::new().with_fps(50.0);
+    let saved = CStr::from_bytes_with_nul(b"Saved\0").unwrap();
+    let loaded = CStr::from_bytes_with_nul(b"Loaded\0").unwrap();
+    queue.push(&Message::new(saved, 100));
+    queue.push(&Message::new(loaded, 25));
+    queue.flush(&mut env).unwrap();
+    assert!(queue.is_empty());
+    assert_eq!(
+      *EXT_MESSAGES.lock().unwrap(),
+      [("Saved".to_owned(), 2000, 2), ("Loaded".to_owned(), 500, 1)]
+    );
+    queue.flush(&mut env).unwrap();
+    assert_eq!(EXT_MESSAGES.lock().unwrap().len(), 2);
+  }
+
+  #[test]
+  fn test_pixel_format_sizes() {
+    assert_eq!(PixelFormat::RGB1555.bytes_per_pixel(), 2);
+    assert_eq!(PixelFormat::RGB565.bytes_per_pixel(), 2);
+    assert_eq!(PixelFormat::XRGB8888.bytes_per_pixel(), 4);
+    assert_eq!(PixelFormat::RGB565.pitch(320), 640);
+    assert_eq!(PixelFormat::XRGB8888.pitch(320), 1280);
+  }
+
+  #[test]
+  fn test_pixel_conversions() {
+    // Black, white, pure red, pure green, pure blue and a mid gray.
+    let rgb565 = [0x0000, 0xFFFF, 0xF800, 0x07E0, 0x001F, 0x8410];
+    let mut xrgb8888 = [0; 6];
+    pixel::convert_rgb565_to_xrgb8888(&rgb565, &mut xrgb8888);
+    assert_eq!(
+      xrgb8888,
+      [0x000000, 0xFFFFFF, 0xFF0000, 0x00FF00, 0x0000FF, 0x848284]
+    );
+
+    let rgb1555 = [0x0000, 0x7FFF, 0x7C00, 0x03E0, 0x001F, 0x4210];
+    pixel::convert_0rgb1555_to_xrgb8888(&rgb1555, &mut xrgb8888);
+    assert_eq!(
+      xrgb8888,
+      [0x000000, 0xFFFFFF, 0xFF0000, 0x00FF00, 0x0000FF, 0x848484]
+    );
+
+    let mut converted = [0; 6];
+    pixel::convert_0rgb1555_to_rgb565(&rgb1555, &mut converted);
+    assert_eq!(converted, [0x0000, 0xFFFF, 0xF800, 0x07E0, 0x001F, 0x8430]);
+  }
+
+  #[test]
+  fn test_from_raw_slice() {
+    let raw = [0xF800u16, 0x07E0, 0x001F, 0xFFFF];
+    let pixels = pixel::RGB565::from_raw_slice(&raw);
+    assert_eq!(pixels.len(), 4);
+    assert_eq!(pixels[0].r().value(), 0x1F);
+    assert_eq!(pixels[1].g().value(), 0x3F);
+    assert_eq!(pixels[2].b().value(), 0x1F);
+    let frame = Frame::from_packed(pixels, 2, 2);
+    assert_eq!(frame.pitch() as usize * size_of::<pixel::RGB565>(), 4);
+
+    let raw = [0x7C00u16, 0x03E0];
+    let pixels = pixel::ORGB1555::from_raw_slice(&raw);
+    assert_eq!((pixels[0].r().value(), pixels[1].g().value()), (0x1F, 0x1F));
+
+    let raw = [0x00123456u32, 0x00ABCDEF];
+    let pixels = XRGB8888::from_raw_slice(&raw);
+    assert_eq!(
+      (pixels[0].r(), pixels[0].g(), pixels[0].b()),
+      (0x12, 0x34, 0x56)
+    );
+    assert_eq!(pixels[1].raw_value(), 0x00ABCDEF);
+    let frame = Frame::from_packed(pixels, 2, 1);
+    assert_eq!(frame.pitch() as usize * size_of::<XRGB8888>(), 8);
+  }
+
+  unsafe extern "C" fn gl_clear() {}
+
+  unsafe extern "C" fn gl_get_proc_address(sym: *const c_char) -> retro_proc_address_t {
+    match CStr::from_ptr(sym).to_bytes() {
+      b"glClear" => Some(gl_clear),
+      _ => None,
+    }
+  }
+
+  unsafe extern "C" fn gl_get_current_framebuffer() -> usize {
+    0
+  }
+
+  #[test]
+  fn test_gl_loader() {
+    let callbacks = GLContextCallbacks {
+      get_proc_address_cb: gl_get_proc_address,
+      get_current_framebuffer_cb: gl_get_current_framebuffer,
+    };
+    let gl_clear = gl_clear as unsafe extern "C" fn() as *const c_void;
+    let name = CStr::from_bytes_with_nul(b"glClear\0").unwrap();
+    assert_eq!(callbacks.get_proc_address(name), Some(gl_clear));
+    let name = CStr::from_bytes_with_nul(b"glFoo\0").unwrap();
+    assert_eq!(callbacks.get_proc_address(name), None);
+
+    let loader = callbacks.loader();
+    assert_eq!(loader("glClear"), gl_clear);
+    assert!(loader("glFoo").is_null());
+    assert!(loader("gl\0Clear").is_null());
+  }
+
+  #[test]
+  fn test_frame_bounds() {
+    let data = [0u16; 40];
+    let frame = Frame::from_rows(&data[2..], 4, 4, 10);
+    assert_eq!((frame.width(), frame.height(), frame.pitch()), (4, 4, 10));
+    let frame = Frame::from_packed(&data, 8, 5);
+    assert_eq!(frame.pitch(), 8);
+    assert!(std::panic::catch_unwind(|| Frame::from_rows(&data, 4, 5, 10)).is_err());
+    assert!(std::panic::catch_unwind(|| Frame::from_rows(&data, 8, 2, 4)).is_err());
+    assert!(std::panic::catch_unwind(|| Frame::new(&data, 8, 5).with_pitch(10)).is_err());
+    assert_eq!(Frame::from_packed(&data[..0], 0, 0).height(), 0);
+  }
+
+  #[test]
+  fn test_framebuffer_alignment() {
+    for alignment in [1, 16, 64, 4096] {
+      let mut framebuffer = Framebuffer::<XRGB8888>::new(7, 3, alignment);
+      assert_eq!(framebuffer.data().as_ptr() as usize % alignment, 0);
+      assert_eq!(framebuffer.data().len(), 21);
+      framebuffer.data_mut()[20] = XRGB8888::new_with_raw_value(0xFF);
+      assert_eq!(framebuffer.frame().data()[20].raw_value(), 0xFF);
+    }
+    let empty = Framebuffer::<XRGB8888>::new(0, 0, 32);
+    assert_eq!(empty.data().as_ptr() as usize % 32, 0);
+    assert!(empty.data().is_empty());
+  }
 }
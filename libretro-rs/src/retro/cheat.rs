@@ -0,0 +1,174 @@
+//! Parsing of common cheat code formats.
+//!
+//! [`crate::retro::CheatsCore::cheat_set`] receives cheat codes exactly as the player entered
+//! them. Cores that patch memory can use [`CheatCode::parse`] to turn them into
+//! [`CheatEntry`]s instead of handling each format themselves.
+
+use core::fmt::{Display, Formatter};
+use std::error::Error;
+
+/// A single memory patch described by a cheat code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CheatEntry {
+  pub address: u32,
+  pub value: u32,
+  /// If set, the patch only applies while the original value at `address` equals this value.
+  pub compare: Option<u32>,
+}
+
+/// A cheat code, which may hold several codes separated by `+` or line breaks.
+///
+/// The following formats are recognized:
+/// - Raw codes, as hexadecimal `address:value` pairs, e.g. `7E0DBF:63`.
+/// - NES Game Genie codes, with 6 or 8 letters, e.g. `SXIOPO`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CheatCode<'a>(&'a str);
+
+impl<'a> CheatCode<'a> {
+  pub fn new(code: &'a str) -> Self {
+    Self(code)
+  }
+
+  /// Parses every code in `code`. Shorthand for `CheatCode::new(code).entries()`.
+  pub fn parse(code: &'a str) -> Result<Vec<CheatEntry>, CheatParseError> {
+    Self::new(code).entries()
+  }
+
+  /// The individual codes, with surrounding whitespace removed.
+  pub fn parts(&self) -> impl Iterator<Item = &'a str> {
+    self
+      .0
+      .split(['+', '\n', '\r'])
+      .map(str::trim)
+      .filter(|part| !part.is_empty())
+  }
+
+  /// Parses every code, failing if any of them isn't in a recognized format.
+  pub fn entries(&self) -> Result<Vec<CheatEntry>, CheatParseError> {
+    self
+      .parts()
+      .enumerate()
+      .map(|(index, part)| parse_part(part).ok_or(CheatParseError { index }))
+      .collect()
+  }
+}
+
+fn parse_part(part: &str) -> Option<CheatEntry> {
+  match part.split_once(':') {
+    Some((address, value)) => parse_raw(address, value),
+    None => parse_game_genie(part),
+  }
+}
+
+fn parse_raw(address: &str, value: &str) -> Option<CheatEntry> {
+  let hex = |s: &str| match s.len() {
+    1..=8 => u32::from_str_radix(s, 16).ok(),
+    _ => None,
+  };
+  Some(CheatEntry {
+    address: hex(address)?,
+    value: hex(value)?,
+    compare: None,
+  })
+}
+
+/// Decodes an NES Game Genie code. See <https://www.nesdev.org/wiki/Game_Genie>.
+fn parse_game_genie(code: &str) -> Option<CheatEntry> {
+  const LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+  if code.len() != 6 && code.len() != 8 {
+    return None;
+  }
+  let mut n = [0u32; 8];
+  for (n, c) in n.iter_mut().zip(code.bytes()) {
+    *n = LETTERS.iter().position(|&l| l == c.to_ascii_uppercase())? as u32;
+  }
+  let address = 0x8000
+    | (n[3] & 7) << 12
+    | (n[4] & 8) << 8
+    | (n[5] & 7) << 8
+    | (n[1] & 8) << 4
+    | (n[2] & 7) << 4
+    | (n[3] & 8)
+    | (n[4] & 7);
+  // The 8-letter form moves the last value bit to the last letter to make room for the compare
+  // value.
+  let value_bit_3 = if code.len() == 6 { n[5] & 8 } else { n[7] & 8 };
+  let value = (n[0] & 8) << 4 | (n[1] & 7) << 4 | value_bit_3 | (n[0] & 7);
+  let compare =
+    (code.len() == 8).then(|| (n[6] & 8) << 4 | (n[7] & 7) << 4 | (n[5] & 8) | (n[6] & 7));
+  Some(CheatEntry {
+    address,
+    value,
+    compare,
+  })
+}
+
+/// The error returned when a cheat code isn't in a recognized format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CheatParseError {
+  /// The index of the first unrecognized code among [`CheatCode::parts`].
+  pub index: usize,
+}
+
+impl Display for CheatParseError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    write!(f, "cheat code {} is not in a recognized format", self.index)
+  }
+}
+
+impl Error for CheatParseError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_raw_codes() {
+    let entries = CheatCode::parse("7E0DBF:63+7e0dc0:0a\n 00FF:1234 ").unwrap();
+    let entry = |address, value| CheatEntry {
+      address,
+      value,
+      compare: None,
+    };
+    assert_eq!(
+      entries,
+      [
+        entry(0x7E0DBF, 0x63),
+        entry(0x7E0DC0, 0x0A),
+        entry(0x00FF, 0x1234)
+      ]
+    );
+    assert_eq!(
+      CheatCode::parse("7E0DBF:63+7E0DBF:"),
+      Err(CheatParseError { index: 1 })
+    );
+    assert_eq!(
+      CheatCode::parse("123456789:00"),
+      Err(CheatParseError { index: 0 })
+    );
+  }
+
+  #[test]
+  fn test_parse_game_genie_codes() {
+    let entries = CheatCode::parse("SXIOPO+sxiopo\nYEUZUGAA").unwrap();
+    let sxiopo = CheatEntry {
+      address: 0x91D9,
+      value: 0xAD,
+      compare: None,
+    };
+    assert_eq!(entries[0], sxiopo);
+    assert_eq!(entries[1], sxiopo);
+    assert_eq!(
+      entries[2],
+      CheatEntry {
+        address: 0xACB3,
+        value: 0x07,
+        compare: Some(0x00),
+      }
+    );
+    assert_eq!(
+      CheatCode::parse("SXIOPB"),
+      Err(CheatParseError { index: 0 })
+    );
+  }
+}
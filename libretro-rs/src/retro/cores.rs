@@ -16,22 +16,39 @@ use crate::ffi::*;
 use crate::retro::env::Environment;
 use crate::retro::pixel::{Format, ORGB1555};
 use crate::retro::*;
+use core::cell::RefCell;
 use core::ffi::*;
-use core::mem::MaybeUninit;
+use core::mem::{self, MaybeUninit};
 use core::ops::*;
 use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::ffi::CString;
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 /// A basic libretro core.
 #[allow(unused_variables)]
 pub trait Core<'a>: Sized {
   type Init: Sized + 'a;
 
+  /// If true, the frontend is told during `retro_set_environment` that the core can run without
+  /// content, in which case [`Core::load_without_content`] is called instead of
+  /// [`Core::load_game`]. Cores that decide this at runtime can call
+  /// [`env::SetEnvironment::set_support_no_game`] from [`Core::set_environment`] instead.
+  const SUPPORT_NO_GAME: bool = false;
+
+  /// The alignment, in bytes, of the frame buffers passed to [`Callbacks::upload_video_frame`].
+  /// Some frontends' upload paths need aligned video memory; [`Framebuffer::new`] can allocate
+  /// it. Must be a power of two. Debug builds assert that uploaded frames meet this alignment.
+  const VIDEO_ALIGNMENT: usize = 1;
+
   /// Called to get information about the core. This information can then be displayed in a frontend, or used to
   /// construct core-specific paths.
   fn get_system_info() -> SystemInfo;
 
-  /// Called during `retro_set_environment`.
+  /// Called during `retro_set_environment`, after [`Core::SUPPORT_NO_GAME`] has been sent to the
+  /// frontend. This is where cores set per-extension content flags with
+  /// [`env::SetEnvironment::set_content_info_override`].
   fn set_environment(env: &mut impl env::SetEnvironment) {}
 
   /// Called during `retro_init`.
@@ -51,14 +68,46 @@ pub trait Core<'a>: Sized {
     Err(CoreError::new())
   }
 
+  /// Called during `retro_get_system_av_info`.
+  ///
+  /// The geometry may depend on core options, which can be read with
+  /// [`env::Environment::get_variable`]. If an option changes the geometry or timing later on,
+  /// the core must report it with [`env::Run::set_system_av_info`] (or
+  /// [`env::Run::set_geometry`] if only the geometry changed).
   fn get_system_av_info(&self, env: &mut impl env::GetAvInfo) -> SystemAVInfo;
 
+  /// Called during `retro_get_system_av_info` when the frontend queries the AV info again after
+  /// the first time since the game was loaded, right before [`Core::get_system_av_info`].
+  ///
+  /// Some frontends re-query the AV info after the player toggles a setting, so cores that
+  /// derive their geometry or rotation from core options can re-read them here. This is
+  /// best-effort: frontends aren't required to re-query at all, so cores must still report
+  /// changes from [`Core::run`] with [`env::Run::set_geometry`] or
+  /// [`env::Run::set_system_av_info`].
+  fn on_geometry_query(&mut self, env: &mut impl env::GetAvInfo) {}
+
   /// Called continuously once the core is initialized and a game is loaded.
   ///
   /// The core is expected to advance emulation by a single frame before returning.
   /// The core must call [`Callbacks::poll_inputs`] at least once.
   fn run(&mut self, env: &mut impl env::Run, callbacks: &mut impl Callbacks) -> InputsPolled;
 
+  /// Called right after [`Core::run`] when the core requested a shutdown with
+  /// [`env::Run::shutdown`] during it, and the frontend accepted.
+  ///
+  /// The frontend unloads the core at its own pace, and may run more frames first. Cores that
+  /// keep data in memory, e.g. save files written with [`crate::retro::StateFile`], should write
+  /// it out here.
+  fn on_shutdown_requested(&mut self, env: &mut impl env::Run) {}
+
+  /// Called right before [`Core::run`] when the frontend called [`CheatsCore::cheat_set`] or
+  /// [`CheatsCore::cheat_reset`] since the previous frame.
+  ///
+  /// Frontends apply cheats between frames, as a `cheat_reset` followed by a `cheat_set` for each
+  /// enabled cheat, so cores can rebuild their cheat list here once per batch instead of after
+  /// every entry. Nothing is called if the game is unloaded before the next frame.
+  fn cheat_apply(&mut self, env: &mut impl env::CheatSet) {}
+
   /// Called when a player resets their game.
   fn reset(&mut self, env: &mut impl env::Reset);
 
@@ -71,6 +120,15 @@ pub trait Core<'a>: Sized {
   /// Called during `retro_deinit`
   #[allow(unused_variables)]
   fn deinit(env: &mut impl env::Deinit, init_state: Self::Init) {}
+
+  /// Returns the emulated system's RAM, which achievements are evaluated against.
+  ///
+  /// When this returns [`Some`], it's exposed to the frontend as
+  /// [`StandardMemoryType::SystemRam`], taking precedence over
+  /// [`GetMemoryRegionCore::get_memory_data`]. See [`env::LoadGame::set_support_achievements`].
+  fn achievement_ram(&mut self) -> Option<&mut [u8]> {
+    None
+  }
 }
 
 #[non_exhaustive]
@@ -84,8 +142,12 @@ pub struct LoadGameExtraArgs<'init, 'function, Env, Init> {
 
 /// Save state functions.
 pub trait SaveStateCore<'a>: Core<'a> {
-  /// Called to determine the size of the save state buffer. This is only ever called once per run, and the core must
-  /// not exceed the size returned here for subsequent saves.
+  /// Called to determine the size of the save state buffer. This is only ever called once per loaded game, and the core
+  /// must not exceed the size returned here for subsequent saves, unless the core declared
+  /// [`env::SerializationQuirks::CORE_VARIABLE_SIZE`]. In that case, it's called again before every save, and the
+  /// buffer passed to `serialize` has exactly the size returned by the last call.
+  ///
+  /// Debug builds call it again before every save to check that the size didn't grow.
   fn serialize_size(&self, env: &mut impl env::SerializeSize) -> core::num::NonZeroUsize;
 
   /// Allows a core to save its internal state into the specified buffer. The buffer is guaranteed to be at least `size`
@@ -115,6 +177,8 @@ pub trait DeviceTypeAwareCore<'a>: Core<'a> {
 pub trait CheatsCore<'a>: Core<'a> {
   /// Called when a user attempts to apply or remove a cheat code.
   ///
+  /// Cheats are set one at a time; [`Core::cheat_apply`] is called before the next frame.
+  ///
   /// This function returns [`Result`] to make error handling easier.
   /// The libretro function `retro_cheat_set` does not return a result to the frontend.
   fn cheat_set(
@@ -138,6 +202,8 @@ pub trait GetMemoryRegionCore<'a>: Core<'a> {
 
 /// Implementation of `retro_load_game_special`. Should be avoided if possible.
 pub trait SpecialGameCore<'a>: Core<'a> {
+  /// `games` holds one entry per content of the subsystem `game_type`, in the order they're
+  /// declared in the subsystem. Optional content the player didn't provide is empty.
   fn load_game<'b>(
     env: &mut impl env::LoadGameSpecial,
     init_state: &'a mut Self::Init,
@@ -149,22 +215,95 @@ pub trait SpecialGameCore<'a>: Core<'a> {
 /// Implementation of `retro_get_region`.
 ///
 /// This is vestigial functionality; RetroArch no longer calls this function.
-/// If a core does not implement this trait, the [`libretro_core`] macro will
-/// return [`RETRO_REGION_NTSC`], which is the de facto default value.
+/// If a core does not implement this trait, the [`libretro_core`] macro derives the region from
+/// the frame rate last returned by [`Core::get_system_av_info`] with [`Region::from_fps`], or
+/// returns [`RETRO_REGION_NTSC`], the de facto default value, if no game is loaded.
+///
+/// [`RegionAwareCore::get_region`] is called on every `retro_get_region` call and its result is
+/// never cached, so a core that switches between PAL and NTSC mid-session can keep the region in
+/// its state and return it from there. Frontends usually read the region only once after loading
+/// the game though, so also announce the new frame rate with [`env::Run::set_system_av_info`].
 pub trait RegionAwareCore<'a>: Core<'a> {
   fn get_region(&self, env: &mut impl env::GetRegion) -> Region;
 }
 
 /// OpenGL context management functions.
+///
+/// After a successful call to [`env::LoadGame::set_hw_render_gl`], the frontend calls
+/// [`OpenGLCore::context_reset`] before the next call to [`Core::run`]. Cores must not call
+/// [`Callbacks::use_hardware_frame_buffer`] until the context has been reset. Frames submitted
+/// before that point (or after [`OpenGLCore::context_destroy`]) are still passed to the frontend,
+/// which usually shows a black screen, and the first one is logged as an error.
 pub unsafe trait OpenGLCore<'a>: Core<'a> {
   fn context_reset(&mut self, env: &mut impl Environment, callbacks: GLContextCallbacks);
 
   fn context_destroy(&mut self, env: &mut impl Environment);
 }
 
+/// Functions for netplay-aware cores that exchange their own network packets.
+///
+/// These are only called after the core calls
+/// [`env::LoadGame::set_netpacket_interface`], and only while content is loaded.
+#[allow(unused_variables)]
+pub trait NetPacketCore<'a>: Core<'a> {
+  /// Called when a multiplayer session starts. If `client_id` is 0, the local
+  /// player is hosting the session, and no other player has connected yet.
+  /// Otherwise, the local player is already connected to the host.
+  ///
+  /// `sender` must be stored by the core to send packets until [`NetPacketCore::stop`] is called.
+  fn start(&mut self, env: &mut impl Environment, client_id: u16, sender: NetPacketSender);
+
+  /// Called when a packet sent by the player identified by `client_id` arrives.
+  fn receive(&mut self, env: &mut impl Environment, buf: &[u8], client_id: u16);
+
+  /// Called when the multiplayer session ends.
+  fn stop(&mut self, env: &mut impl Environment) {}
+
+  /// Called every frame, between calls to [`Core::run`], while the session is updated.
+  /// This is a good place to send packets from.
+  fn poll(&mut self, env: &mut impl Environment) {}
+
+  /// Called on the host when a new player connects.
+  /// Returning `false` drops the player, e.g. to limit the number of players.
+  fn connected(&mut self, env: &mut impl Environment, client_id: u16) -> bool {
+    true
+  }
+
+  /// Called on the host when a player leaves or disconnects.
+  fn disconnected(&mut self, env: &mut impl Environment, client_id: u16) {}
+}
+
+/// Functions for cores that adapt to the occupancy of the frontend's audio buffer, e.g. by
+/// skipping frames to avoid buffer underruns.
+///
+/// These are only called after the core calls
+/// [`env::LoadGame::set_audio_buffer_status_callback`], and only while content is loaded.
+pub trait AudioBufferStatusCore<'a>: Core<'a> {
+  /// Called right before every call to [`Core::run`].
+  ///
+  /// `active` tells whether the frontend's audio buffer is currently in use, `occupancy` is
+  /// its fill level as a percentage between 0 and 100, and `underrun_likely` is set when the
+  /// frontend expects an underrun during the next frame.
+  fn audio_buffer_status(
+    &mut self,
+    env: &mut impl Environment,
+    active: bool,
+    occupancy: u32,
+    underrun_likely: bool,
+  );
+}
+
 /// Rust interface for [`retro_system_info`].
+///
+/// With the `serde` feature, this can be serialized. Deserializing leaks the strings, since
+/// [`SystemInfo`] only refers to `'static` strings.
 #[repr(transparent)]
 #[derive(Clone, Debug)]
+#[cfg_attr(
+  feature = "serde",
+  derive(serde::Serialize, serde::Deserialize),
+  serde(try_from = "SystemInfoRepr", into = "SystemInfoRepr")
+)]
 pub struct SystemInfo(retro_system_info);
 
 impl SystemInfo {
@@ -235,10 +374,177 @@ impl From<SystemInfo> for retro_system_info {
   }
 }
 
+/// Creates a [`SystemInfo`] whose version is the calling crate's version, so it never falls out of
+/// sync with `Cargo.toml`. The name defaults to the crate's name.
+///
+/// # Examples
+/// ```
+/// use libretro_rs::c_utf8::c_utf8;
+/// use libretro_rs::retro::*;
+/// use libretro_rs::{ext, system_info};
+/// let info = system_info!(ext!["rom"]);
+/// assert_eq!(info.library_name().to_str(), Ok(env!("CARGO_PKG_NAME")));
+/// assert_eq!(info.library_version().to_str(), Ok(env!("CARGO_PKG_VERSION")));
+///
+/// let info = system_info!("chip8.rs", ext!["ch8"]);
+/// assert_eq!(info.library_name().to_str(), Ok("chip8.rs"));
+/// ```
+#[macro_export]
+macro_rules! system_info {
+  ( $extensions:expr ) => {
+    $crate::system_info!(::core::env!("CARGO_PKG_NAME"), $extensions)
+  };
+  ( $name:expr , $extensions:expr ) => {
+    $crate::retro::SystemInfo::new(
+      $crate::c_utf8::c_utf8!($name),
+      $crate::c_utf8::c_utf8!(::core::env!("CARGO_PKG_VERSION")),
+      $extensions,
+    )
+  };
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SystemInfoRepr {
+  library_name: String,
+  library_version: String,
+  valid_extensions: String,
+  need_fullpath: bool,
+  block_extract: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SystemInfoRepr> for SystemInfo {
+  type Error = std::ffi::NulError;
+
+  fn try_from(repr: SystemInfoRepr) -> Result<Self, Self::Error> {
+    let leak = |s: String| -> Result<&'static CStr, Self::Error> {
+      Ok(Box::leak(std::ffi::CString::new(s)?.into_boxed_c_str()))
+    };
+    let extensions = leak(repr.valid_extensions)?;
+    let mut info = Self::new(
+      leak(repr.library_name)?,
+      leak(repr.library_version)?,
+      Extensions::new(extensions),
+    );
+    info.0.need_fullpath = repr.need_fullpath;
+    info.0.block_extract = repr.block_extract;
+    Ok(info)
+  }
+}
+
+#[cfg(feature = "serde")]
+impl From<SystemInfo> for SystemInfoRepr {
+  fn from(info: SystemInfo) -> Self {
+    Self {
+      library_name: info.library_name().to_string_lossy().into_owned(),
+      library_version: info.library_version().to_string_lossy().into_owned(),
+      valid_extensions: info
+        .valid_extensions()
+        .as_c_str()
+        .to_string_lossy()
+        .into_owned(),
+      need_fullpath: info.need_fullpath(),
+      block_extract: info.block_extract(),
+    }
+  }
+}
+
+/// Frontend capabilities and state, cached by the [`Instance`] so [`Callbacks::capabilities`]
+/// doesn't call the environment again.
+///
+/// [`RunCapabilities::bitmasks`] and [`RunCapabilities::can_dupe`] are fixed for a session: they
+/// are queried on the first [`Core::run`] after a game is loaded, and kept until it's unloaded.
+/// The other fields can change from one frame to the next, and are refreshed at the start of
+/// every [`Core::run`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RunCapabilities {
+  /// See [`env::Environment::get_input_bitmasks`].
+  pub bitmasks: bool,
+  /// See [`env::Environment::get_can_dupe`].
+  pub can_dupe: bool,
+  /// See [`AudioVideoEnable::video`].
+  pub video_enabled: bool,
+  /// See [`AudioVideoEnable::audio`].
+  pub audio_enabled: bool,
+  /// See [`env::Environment::get_fastforwarding`].
+  pub fast_forwarding: bool,
+  /// See [`env::Environment::get_input_max_users`]. Defaults to a single player.
+  pub max_users: u32,
+}
+
+impl Default for RunCapabilities {
+  fn default() -> Self {
+    Self {
+      bitmasks: false,
+      can_dupe: false,
+      video_enabled: true,
+      audio_enabled: true,
+      fast_forwarding: false,
+      max_users: 1,
+    }
+  }
+}
+
+impl RunCapabilities {
+  pub fn query(env: &impl Environment) -> Self {
+    Self {
+      bitmasks: env.get_input_bitmasks(),
+      can_dupe: env.get_can_dupe().unwrap_or(false),
+      ..Self::query_per_frame(env)
+    }
+  }
+
+  /// Queries the capabilities that can change between frames again, keeping the ones that are
+  /// fixed for the session.
+  pub fn refresh(&mut self, env: &impl Environment) {
+    *self = Self {
+      bitmasks: self.bitmasks,
+      can_dupe: self.can_dupe,
+      ..Self::query_per_frame(env)
+    };
+  }
+
+  fn query_per_frame(env: &impl Environment) -> Self {
+    let av_enable = env.get_audio_video_enable();
+    Self {
+      video_enabled: av_enable.video(),
+      audio_enabled: av_enable.audio(),
+      fast_forwarding: env.get_fastforwarding(),
+      max_users: env.get_input_max_users().unwrap_or(1),
+      ..Self::default()
+    }
+  }
+}
+
 pub trait Callbacks {
-  /// Sends audio data to the `libretro` frontend.
+  /// Sends audio data to the `libretro` frontend, and returns the number of stereo frames it
+  /// consumed.
+  ///
+  /// `frame` holds interleaved stereo samples, left then right, so its length must be even; debug
+  /// builds assert it, and a trailing odd sample is never consumed. Use
+  /// [`Callbacks::upload_audio_frame_mono`] for mono audio.
   fn upload_audio_frame(&mut self, frame: &[i16]) -> usize;
 
+  /// Sends mono audio data to the `libretro` frontend, by playing each sample on both channels.
+  /// Returns the number of samples the frontend consumed.
+  fn upload_audio_frame_mono(&mut self, samples: &[i16]) -> usize {
+    const CHUNK: usize = 512;
+    let mut stereo = [0; 2 * CHUNK];
+    let mut consumed = 0;
+    for chunk in samples.chunks(CHUNK) {
+      for (frame, &sample) in stereo.chunks_exact_mut(2).zip(chunk) {
+        frame.fill(sample);
+      }
+      let frames = self.upload_audio_frame(&stereo[..2 * chunk.len()]);
+      consumed += frames;
+      if frames < chunk.len() {
+        break;
+      }
+    }
+    consumed
+  }
+
   /// Sends audio data to the `libretro` frontend.
   fn upload_audio_sample(&mut self, left: i16, right: i16);
 
@@ -252,12 +558,32 @@ pub trait Callbacks {
     framebuffer: &Frame<'_, P>,
   );
 
+  /// Sends a frame rendered into a [`SoftwareFramebuffer`] to the `libretro` frontend.
+  fn upload_software_framebuffer(
+    &mut self,
+    enabled: &SoftwareRenderEnabled,
+    framebuffer: SoftwareFramebuffer<'_>,
+  );
+
   /// Explicitly informs the `libretro` frontend to repeat the previous video frame.
   /// Must only be called if [`Environment::get_can_dupe`] returns `true`.
   fn repeat_video_frame(&mut self);
 
+  /// Informs the `libretro` frontend to repeat the previous video frame, which has the given size.
+  ///
+  /// Like [`Callbacks::repeat_video_frame`], this passes a null frame to the frontend, so it
+  /// avoids copying the frame buffer when nothing was drawn. Cores must check that
+  /// [`Environment::get_can_dupe`] returns `true` before calling it.
+  fn dup_video_frame(&mut self, width: c_uint, height: c_uint);
+
   /// When using hardware rendering, informs the `libretro` frontend that core
   /// has finished rendering to the frame buffer.
+  ///
+  /// This is the hardware rendering counterpart to [`Callbacks::upload_video_frame`];
+  /// instead of a data buffer, the frontend receives [`RETRO_HW_FRAME_BUFFER_VALID`].
+  ///
+  /// Must not be called before the frontend has reset the hardware context;
+  /// see [`OpenGLCore`].
   fn use_hardware_frame_buffer(
     &mut self,
     enabled: &impl HWRenderEnabled,
@@ -271,6 +597,55 @@ pub trait Callbacks {
 
   /// Returns true if the specified button is pressed, false otherwise.
   fn is_joypad_button_pressed(&self, port: DevicePort, btn: JoypadButton) -> bool;
+
+  /// Returns the state of every button of the joypad in `port`.
+  ///
+  /// This takes a single query when the frontend supports input bitmasks (see
+  /// [`env::Environment::get_input_bitmasks`]), and falls back to querying each button otherwise.
+  /// With input bitmasks, the result is reused until the next [`Callbacks::poll_inputs`] or
+  /// [`Core::run`], or until the frontend plugs a different device into `port`.
+  /// [`Callbacks::is_joypad_button_pressed`] reads from the same cache.
+  fn joypad_bitmask(&self, port: DevicePort) -> JoypadState;
+
+  /// Reads the device the frontend plugged into `port`, as reported through
+  /// [`Core::set_controller_port_device`]. Ports default to a joypad until the frontend assigns
+  /// a device, and subclassed devices are read as their standard type.
+  fn read_device(&self, port: DevicePort) -> DeviceInput;
+
+  /// Queries the frontend's `retro_input_state_t` callback directly, for any `RETRO_DEVICE_*`
+  /// `device`, `index` and `id`.
+  ///
+  /// This is the escape hatch for devices and inputs the crate doesn't model yet, such as custom
+  /// subclasses; prefer the typed helpers above when they fit. Subclassed devices must be passed
+  /// as their base type, see [`DeviceTypeId::base`].
+  ///
+  /// The default implementation reports every input as released, for implementations that only
+  /// provide the typed helpers.
+  #[allow(unused_variables)]
+  fn input_state(&self, port: DevicePort, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+    0
+  }
+
+  /// The frontend's capabilities for the current frame.
+  fn capabilities(&self) -> RunCapabilities;
+
+  /// Whether [`Callbacks::dup_video_frame`] can be used. Cached for the session, see
+  /// [`RunCapabilities`].
+  fn can_dupe(&self) -> bool {
+    self.capabilities().can_dupe
+  }
+
+  /// Whether the frontend supports input bitmasks. Cached for the session, see
+  /// [`RunCapabilities`].
+  fn has_input_bitmasks(&self) -> bool {
+    self.capabilities().bitmasks
+  }
+
+  /// The ports the frontend feeds input for, up to [`RunCapabilities::max_users`]. Multiplayer
+  /// cores can use this to skip polling unused ports.
+  fn active_ports(&self) -> impl Iterator<Item = DevicePort> {
+    DevicePort::iter_up_to(self.capabilities().max_users)
+  }
 }
 
 impl Callbacks for InstanceCallbacks {
@@ -291,10 +666,22 @@ impl Callbacks for InstanceCallbacks {
     unsafe { self.upload_video_frame(enabled, pixel_format, framebuffer) }
   }
 
+  fn upload_software_framebuffer(
+    &mut self,
+    enabled: &SoftwareRenderEnabled,
+    framebuffer: SoftwareFramebuffer<'_>,
+  ) {
+    unsafe { self.upload_software_framebuffer(enabled, framebuffer) }
+  }
+
   fn repeat_video_frame(&mut self) {
     unsafe { self.repeat_video_frame() }
   }
 
+  fn dup_video_frame(&mut self, width: c_uint, height: c_uint) {
+    unsafe { self.dup_video_frame(width, height) }
+  }
+
   fn use_hardware_frame_buffer(
     &mut self,
     hw_render_enabled: &impl HWRenderEnabled,
@@ -312,6 +699,146 @@ impl Callbacks for InstanceCallbacks {
   fn is_joypad_button_pressed(&self, port: DevicePort, btn: JoypadButton) -> bool {
     unsafe { self.is_joypad_button_pressed(port, btn) }
   }
+
+  fn joypad_bitmask(&self, port: DevicePort) -> JoypadState {
+    unsafe { self.joypad_bitmask(port) }
+  }
+
+  fn read_device(&self, port: DevicePort) -> DeviceInput {
+    unsafe { self.read_device(port) }
+  }
+
+  fn input_state(&self, port: DevicePort, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+    unsafe { self.input_state(port, device, index, id) }
+  }
+
+  fn capabilities(&self) -> RunCapabilities {
+    self.caps
+  }
+}
+
+/// The IO of a single call to [`Core::run`]: it polls the inputs when created, collects the
+/// video frame and audio samples, and sends both to the frontend when dropped. Nothing is sent if
+/// the frame is dropped while the core panics.
+///
+/// ```no_run
+/// use libretro_rs::retro::pixel::{Format, XRGB8888};
+/// use libretro_rs::retro::*;
+///
+/// struct Emulator {
+///   rendering_mode: SoftwareRenderEnabled,
+///   pixel_format: Format<XRGB8888>,
+///   framebuffer: Framebuffer<XRGB8888>,
+///   audio: Vec<i16>,
+/// }
+///
+/// fn emulate(buttons: JoypadState, pixels: &mut [XRGB8888], audio: &mut Vec<i16>) {
+///   # let _ = (buttons, pixels, audio);
+///   // ...
+/// }
+///
+/// impl Emulator {
+///   fn run(&mut self, callbacks: &mut impl Callbacks) -> InputsPolled {
+///     let mut frame = RunFrame::new(
+///       callbacks,
+///       &self.rendering_mode,
+///       &self.pixel_format,
+///       &mut self.framebuffer,
+///     );
+///     let buttons = frame.joypad(DevicePort::new(0));
+///     emulate(buttons, frame.framebuffer(), &mut self.audio);
+///     frame.push_audio(&self.audio);
+///     frame.finish()
+///   }
+/// }
+/// ```
+pub struct RunFrame<'a, C: Callbacks, P> {
+  callbacks: &'a mut C,
+  enabled: &'a SoftwareRenderEnabled,
+  pixel_format: &'a Format<P>,
+  framebuffer: &'a mut Framebuffer<P>,
+  audio: Vec<i16>,
+}
+
+impl<'a, C: Callbacks, P> RunFrame<'a, C, P> {
+  /// Polls the inputs, and starts a frame that will be rendered into `framebuffer`.
+  pub fn new(
+    callbacks: &'a mut C,
+    enabled: &'a SoftwareRenderEnabled,
+    pixel_format: &'a Format<P>,
+    framebuffer: &'a mut Framebuffer<P>,
+  ) -> Self {
+    callbacks.poll_inputs();
+    Self {
+      callbacks,
+      enabled,
+      pixel_format,
+      framebuffer,
+      audio: Vec::new(),
+    }
+  }
+
+  /// The state of the joypad in `port`. See [`Callbacks::joypad_bitmask`].
+  pub fn joypad(&self, port: DevicePort) -> JoypadState {
+    self.callbacks.joypad_bitmask(port)
+  }
+
+  /// The input of the device in `port`. See [`Callbacks::read_device`].
+  pub fn device(&self, port: DevicePort) -> DeviceInput {
+    self.callbacks.read_device(port)
+  }
+
+  /// The frontend's capabilities for this frame. See [`Callbacks::capabilities`].
+  pub fn capabilities(&self) -> RunCapabilities {
+    self.callbacks.capabilities()
+  }
+
+  /// The pixels of the frame, which are sent to the frontend when the frame ends.
+  pub fn framebuffer(&mut self) -> &mut [P] {
+    self.framebuffer.data_mut()
+  }
+
+  /// Queues interleaved stereo samples, which are sent to the frontend when the frame ends.
+  // `is_multiple_of` needs Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
+  pub fn push_audio(&mut self, samples: &[i16]) {
+    debug_assert!(
+      samples.len() % 2 == 0,
+      "audio frames should be interleaved stereo samples"
+    );
+    self.audio.extend_from_slice(samples);
+  }
+
+  /// Queues a single stereo sample.
+  pub fn push_sample(&mut self, left: i16, right: i16) {
+    self.audio.extend_from_slice(&[left, right]);
+  }
+
+  /// Ends the frame, sending the video frame and the queued audio to the frontend.
+  pub fn finish(self) -> InputsPolled {
+    InputsPolled(())
+  }
+}
+
+impl<C: Callbacks, P> Drop for RunFrame<'_, C, P> {
+  fn drop(&mut self) {
+    // A half-emulated frame is worse than a dropped one, and the frontend may be in no state to
+    // take it.
+    if std::thread::panicking() {
+      return;
+    }
+    let frame = self.framebuffer.frame();
+    self
+      .callbacks
+      .upload_video_frame(self.enabled, self.pixel_format, &frame);
+    let mut audio = &self.audio[..];
+    while !audio.is_empty() {
+      match self.callbacks.upload_audio_frame(audio) {
+        0 => break,
+        frames => audio = &audio[(frames * 2).min(audio.len())..],
+      }
+    }
+  }
 }
 
 pub struct InputsPolled(pub(crate) ());
@@ -342,24 +869,71 @@ pub struct Instance<I, C> {
   cb: InstanceCallbacks,
   init: MaybeUninit<I>,
   core: MaybeUninit<C>,
+  /// Whether the AV info was queried since the game was loaded. See [`Core::on_geometry_query`].
+  av_info_queried: bool,
+  /// The save state size, once queried for the loaded game. See [`SaveStateCore::serialize_size`].
+  serialize_size: Option<usize>,
+  /// The frame rate last reported by [`Core::get_system_av_info`] for the loaded game, which
+  /// tells the region of cores that don't implement [`RegionAwareCore`].
+  fps: Option<f64>,
+  /// Whether `retro_init` was called, and `retro_deinit` wasn't called since.
+  initialized: bool,
+  /// Whether a game is loaded. See [`InstanceError::NoSystemLoaded`].
+  game_loaded: bool,
+  /// Whether cheats were set or reset since the last frame. See [`Core::cheat_apply`].
+  cheats_changed: bool,
+  /// Whether the capabilities fixed for the session were queried since the game was loaded. See
+  /// [`RunCapabilities`].
+  session_caps_queried: bool,
+  /// Cleared when the multiplayer session stops, to invalidate the [`NetPacketSender`].
+  netpacket_session: Option<Arc<AtomicBool>>,
+  /// The netpacket callbacks the frontend made during the last [`Core::run`]. See
+  /// [`NetPacketSender::poll_receive`].
+  netpacket_deferred: Vec<DeferredNetPacket>,
 }
 
 impl<I, C> Instance<I, C> {
+  /// Creates an instance with no frontend callbacks set, for the `static` generated by
+  /// [`libretro_core`]. The fields stay private: the frontend sets the callbacks through the
+  /// `on_set_*` methods, and every other API call goes through the matching `on_*` method.
   pub const fn new(
     context_reset: non_null_retro_hw_context_reset_t,
     context_destroy: non_null_retro_hw_context_reset_t,
   ) -> Self {
     Self {
-      env: InstanceEnvironment {
-        cb: None,
-        gl: InstanceGLState::new(context_reset, context_destroy),
-      },
+      env: InstanceEnvironment::new(None, InstanceGLState::new(context_reset, context_destroy)),
       cb: InstanceCallbacks::new(),
       init: MaybeUninit::uninit(),
       core: MaybeUninit::uninit(),
+      av_info_queried: false,
+      serialize_size: None,
+      fps: None,
+      initialized: false,
+      game_loaded: false,
+      cheats_changed: false,
+      session_caps_queried: false,
+      netpacket_session: None,
+      netpacket_deferred: Vec::new(),
     }
   }
 
+  /// Sets the callbacks registered by [`env::LoadGame::set_netpacket_interface`]. Until this is
+  /// called, registering them fails.
+  pub const fn with_netpacket(mut self, netpacket: InstanceNetPacketState) -> Self {
+    self.env.netpacket = Some(netpacket);
+    self
+  }
+
+  /// Sets the callback registered by [`env::LoadGame::set_audio_buffer_status_callback`].
+  /// Until this is called, registering it fails.
+  pub const fn with_audio_buffer_status(
+    mut self,
+    audio_buffer_status: non_null_retro_audio_buffer_status_callback_t,
+  ) -> Self {
+    self.env.audio_buffer_status = Some(audio_buffer_status);
+    self
+  }
+
   pub fn on_set_audio_sample(&mut self, cb: non_null_retro_audio_sample_t) {
     self.cb.audio_sample = Some(cb);
   }
@@ -379,6 +953,184 @@ impl<I, C> Instance<I, C> {
   pub fn on_set_video_refresh(&mut self, cb: non_null_retro_video_refresh_t) {
     self.cb.video_refresh = Some(cb);
   }
+
+  /// Returns the device most recently assigned to `port` by the frontend. Until the frontend
+  /// assigns one, every port is assumed to have a [`DeviceType::Joypad`].
+  pub fn device_for_port(&self, port: DevicePort) -> DeviceTypeId {
+    self.cb.device_for_port(port)
+  }
+
+  /// Remembers the device for `port`, logging unusual arguments. They're still passed through,
+  /// as frontends may support more ports or device types than this crate knows about.
+  fn set_device_for_port(&mut self, port: DevicePort, device: DeviceTypeId) {
+    if let Some(warning) = port_device_warning(port, device) {
+      crate::retro_warn!(
+        self.env.get_log_interface().ok(),
+        "{} in retro_set_controller_port_device({:?}, {:?})",
+        warning,
+        port,
+        device
+      );
+    }
+    self.cb.set_device_for_port(port, device)
+  }
+
+  /// Logs an error reported by one of the `try_*` methods, through the frontend's logger if
+  /// available.
+  pub fn log_error(&self, error: InstanceError) {
+    crate::retro_error!(self.env.get_log_interface().ok(), "{}", error);
+  }
+
+  /// Logs why a game failed to load and shows it to the player, if the core gave a
+  /// [`LoadError`].
+  fn report_load_error(&mut self, error: CoreError) {
+    let Some(reason) = error.load_error() else {
+      return;
+    };
+    crate::retro_error!(
+      self.env.get_log_interface().ok(),
+      "couldn't load the game: {}",
+      reason
+    );
+    if let Ok(text) = std::ffi::CString::new(format!("Couldn't load the game: {reason}")) {
+      let _ = self.env.set_message(&Message::new(text.as_c_str(), 180));
+    }
+  }
+
+  /// Runs the handler of the `entry_point` API call, catching any panic so that it doesn't
+  /// unwind into the frontend, which is undefined behavior. On panic, the panic is logged and
+  /// `fallback` is returned.
+  ///
+  /// The core may be left in an inconsistent state by the panic, but the frontend can still
+  /// unload the game or save its data.
+  ///
+  /// Cores built with `panic = "abort"` can enable the `panic-abort` feature to skip this.
+  #[cfg(not(feature = "panic-abort"))]
+  pub fn catch_panic<R>(
+    &mut self,
+    entry_point: &'static str,
+    fallback: R,
+    f: impl FnOnce(&mut Self) -> R,
+  ) -> R {
+    let outer = self.env.entry_point;
+    let result =
+      std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.enter(entry_point, f)));
+    match result {
+      Ok(result) => result,
+      Err(payload) => {
+        let message = payload
+          .downcast_ref::<&str>()
+          .copied()
+          .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+          .unwrap_or("unknown panic");
+        self.env.entry_point = outer;
+        crate::retro_error!(
+          self.env.get_log_interface().ok(),
+          "{} panicked: {}",
+          entry_point,
+          message
+        );
+        fallback
+      }
+    }
+  }
+  /// Runs the handler of the `entry_point` API call. With the `panic-abort` feature, panics
+  /// abort the process before they can unwind into the frontend.
+  #[cfg(feature = "panic-abort")]
+  pub fn catch_panic<R>(
+    &mut self,
+    entry_point: &'static str,
+    _fallback: R,
+    f: impl FnOnce(&mut Self) -> R,
+  ) -> R {
+    self.enter(entry_point, f)
+  }
+
+  /// Runs `f` as the handler of the `entry_point` API call or callback, so that environment
+  /// commands restricted to some API calls can check where they're issued from. See
+  /// [`env::Run::set_minimum_audio_latency`].
+  ///
+  /// Callbacks registered by the core may be invoked from inside `retro_run`, but API
+  /// calls aren't reentrant, so in debug builds, an API call made from inside another one is
+  /// logged.
+  fn enter<R>(&mut self, entry_point: &'static str, f: impl FnOnce(&mut Self) -> R) -> R {
+    let outer = self.env.entry_point.replace(entry_point);
+    if let (true, Some(outer)) = (cfg!(debug_assertions), outer) {
+      if entry_point.starts_with("retro_") {
+        self.env.nested_api_calls += 1;
+        crate::retro_error!(
+          self.env.get_log_interface().ok(),
+          "the frontend called {} from inside {}",
+          entry_point,
+          outer
+        );
+      }
+    }
+    let result = f(self);
+    self.env.entry_point = outer;
+    result
+  }
+
+  /// Logs the first hardware frame uploaded before `context_reset`, through the frontend's logger.
+  fn report_early_hw_frame(&mut self) {
+    if mem::take(&mut self.cb.early_hw_frame)
+      && !mem::replace(&mut self.cb.early_hw_frame_logged, true)
+    {
+      crate::retro_error!(
+        self.env.get_log_interface().ok(),
+        "hardware frame uploaded before context_reset; the frontend may show a black screen"
+      );
+    }
+  }
+
+  /// Logs the first audio uploaded with a trailing odd sample, which the frontend never played,
+  /// through the frontend's logger.
+  fn report_odd_audio_frame(&mut self) {
+    if mem::take(&mut self.cb.odd_audio_frame)
+      && !mem::replace(&mut self.cb.odd_audio_frame_logged, true)
+    {
+      crate::retro_error!(
+        self.env.get_log_interface().ok(),
+        "audio with an odd number of samples uploaded; the last sample was dropped"
+      );
+    }
+  }
+
+  fn check_environment(&self) -> Result<(), InstanceError> {
+    match self.env.cb {
+      Some(_) => Ok(()),
+      None => Err(InstanceError::MissingEnvironment),
+    }
+  }
+
+  fn check_game_loaded(&self) -> Result<(), InstanceError> {
+    self.check_environment()?;
+    if self.game_loaded {
+      Ok(())
+    } else {
+      Err(InstanceError::NoSystemLoaded)
+    }
+  }
+
+  fn check_can_load_game(&self) -> Result<(), InstanceError> {
+    self.check_environment()?;
+    if !self.initialized || self.game_loaded {
+      return Err(InstanceError::CalledOutOfOrder);
+    }
+    Ok(())
+  }
+
+  /// Like [`Instance::check_game_loaded`], but logs the error, for the entry points that can't
+  /// return it to the frontend.
+  fn guard_game_loaded(&self) -> bool {
+    match self.check_game_loaded() {
+      Ok(()) => true,
+      Err(error) => {
+        self.log_error(error);
+        false
+      }
+    }
+  }
 }
 
 // The following code exploits the fact that inherent impls can shadow trait
@@ -394,14 +1146,67 @@ impl<'a, C: Core<'a>> Instance<C::Init, C> {
 
   pub fn on_set_environment(&mut self, env: non_null_retro_environment_t) {
     self.env.cb = Some(env);
+    self.env.core_options_version = env.get_core_options_version();
+    if C::SUPPORT_NO_GAME {
+      let _ = env::SetEnvironment::set_support_no_game(&mut self.env, true);
+    }
     C::set_environment(&mut self.env);
   }
 
   pub unsafe fn on_init(&mut self) {
+    self.cb.video_alignment = C::VIDEO_ALIGNMENT;
     self.init.write(C::init(&mut self.env));
+    self.initialized = true;
+  }
+
+  /// Like [`Instance::on_load_game`], but checks that the frontend set the environment and
+  /// called `retro_init` first, and that no game is loaded yet.
+  ///
+  /// # Safety
+  /// `game` must be null or point to a valid [`retro_game_info`], whose data stays valid for as
+  /// long as the frontend's `retro_load_game` contract requires.
+  pub unsafe fn try_load_game(
+    &mut self,
+    game: *const retro_game_info,
+  ) -> Result<bool, InstanceError> {
+    self.check_can_load_game()?;
+    Ok(self.on_load_game(game))
+  }
+
+  /// Like [`Instance::on_run`], but checks that a game is loaded and that the frontend set every
+  /// callback first.
+  ///
+  /// # Safety
+  /// The frontend's callbacks must still be valid, as for `retro_run`.
+  pub unsafe fn try_run(&mut self) -> Result<(), InstanceError> {
+    self.check_game_loaded()?;
+    self.cb.check_callbacks()?;
+    self.on_run();
+    Ok(())
+  }
+
+  /// Like [`Instance::on_reset`], but checks that a game is loaded first.
+  ///
+  /// # Safety
+  /// Must only be called from the frontend's `retro_reset` call.
+  pub unsafe fn try_reset(&mut self) -> Result<(), InstanceError> {
+    self.check_game_loaded()?;
+    self.on_reset();
+    Ok(())
+  }
+
+  /// Like [`Instance::on_unload_game`], but checks that a game is loaded first.
+  ///
+  /// # Safety
+  /// Must only be called from the frontend's `retro_unload_game` call.
+  pub unsafe fn try_unload_game(&mut self) -> Result<(), InstanceError> {
+    self.check_game_loaded()?;
+    self.on_unload_game();
+    Ok(())
   }
 
   pub unsafe fn on_load_game(&mut self, game: *const retro_game_info) -> bool {
+    self.env.game_count = 1;
     let Instance {
       env, init, core, ..
     } = self;
@@ -424,19 +1229,55 @@ impl<'a, C: Core<'a>> Instance<C::Init, C> {
     match result {
       Ok(system) => {
         core.write(system);
+        self.game_loaded = true;
         true
       }
-      Err(_) => false,
+      Err(error) => {
+        self.report_load_error(error);
+        false
+      }
     }
   }
 
   pub unsafe fn on_get_system_av_info(&mut self, info: &mut retro_system_av_info) {
-    let Instance { env, core, .. } = self;
-    *info = core.assume_init_mut().get_system_av_info(env).into();
+    if !self.guard_game_loaded() {
+      return;
+    }
+    let Instance {
+      env,
+      core,
+      av_info_queried,
+      ..
+    } = self;
+    let core = core.assume_init_mut();
+    if mem::replace(av_info_queried, true) {
+      core.on_geometry_query(env);
+    }
+    let av_info = core.get_system_av_info(env);
+    self.fps = Some(av_info.timing().fps());
+    *info = av_info.into();
   }
 
   pub unsafe fn on_run(&mut self) {
-    self.core.assume_init_mut().run(&mut self.env, &mut self.cb);
+    if mem::replace(&mut self.session_caps_queried, true) {
+      self.cb.caps.refresh(&self.env);
+    } else {
+      self.cb.caps = RunCapabilities::query(&self.env);
+    }
+    self.cb.joypad_cache.get_mut().clear();
+    let core = self.core.assume_init_mut();
+    if mem::take(&mut self.cheats_changed) {
+      core.cheat_apply(&mut self.env);
+    }
+    let (env, cb) = (&mut self.env, &mut self.cb);
+    self.netpacket_deferred = netpacket::deferring_callbacks(|| {
+      core.run(env, cb);
+    });
+    if mem::take(&mut self.env.shutdown_requested) {
+      core.on_shutdown_requested(&mut self.env);
+    }
+    self.report_early_hw_frame();
+    self.report_odd_audio_frame();
   }
 
   pub unsafe fn on_reset(&mut self) {
@@ -444,28 +1285,85 @@ impl<'a, C: Core<'a>> Instance<C::Init, C> {
   }
 
   pub unsafe fn on_unload_game(&mut self) {
+    self.av_info_queried = false;
+    self.serialize_size = None;
+    self.fps = None;
+    self.game_loaded = false;
+    self.cheats_changed = false;
+    self.session_caps_queried = false;
     self.core.assume_init_read().unload_game(&mut self.env);
+    // The core is gone, so the frontend must stop reporting the buffer occupancy to it.
+    if mem::take(&mut self.env.audio_buffer_status_enabled) {
+      let _ = env::LoadGame::disable_audio_buffer_status_callback(&mut self.env);
+    }
   }
 
   pub unsafe fn on_deinit(&mut self) {
     C::deinit(&mut self.env, self.init.assume_init_read());
+    self.initialized = false;
   }
 }
 
 impl<'a, C: SaveStateCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_serialize_size` API call.
   pub unsafe fn on_serialize_size(&mut self) -> usize {
-    self
-      .core
-      .assume_init_mut()
-      .serialize_size(&mut self.env)
-      .get()
+    if !self.guard_game_loaded() {
+      return 0;
+    }
+    match self.serialize_size {
+      Some(size) if !self.has_variable_serialize_size() => size,
+      _ => {
+        let size = self
+          .core
+          .assume_init_ref()
+          .serialize_size(&mut self.env)
+          .get();
+        self.serialize_size = Some(size);
+        size
+      }
+    }
   }
 
   /// Invoked by a `libretro` frontend, with the `retro_serialize` API call.
   pub unsafe fn on_serialize(&mut self, data: *mut (), size: usize) -> bool {
+    if !self.guard_game_loaded() {
+      return false;
+    }
     let lifetime = ();
-    let data = slice_with_lifetime_mut(data as *mut u8, size, &lifetime);
+    let mut data = slice_with_lifetime_mut(data as *mut u8, size, &lifetime);
+    if self.has_variable_serialize_size() {
+      let current = self
+        .core
+        .assume_init_ref()
+        .serialize_size(&mut self.env)
+        .get();
+      if current > size {
+        self.log_error(InstanceError::BufferTooSmall {
+          size,
+          expected: current,
+        });
+        return false;
+      }
+      data = &mut data[..current];
+    } else if let Some(cached) = self.serialize_size {
+      debug_assert!(
+        self
+          .core
+          .assume_init_ref()
+          .serialize_size(&mut self.env)
+          .get()
+          <= cached,
+        "serialize_size grew past the cached {cached} bytes without CORE_VARIABLE_SIZE"
+      );
+      if size < cached {
+        self.log_error(InstanceError::BufferTooSmall {
+          size,
+          expected: cached,
+        });
+        return false;
+      }
+      data = &mut data[..cached];
+    }
     self
       .core
       .assume_init_mut()
@@ -473,8 +1371,18 @@ impl<'a, C: SaveStateCore<'a>> Instance<C::Init, C> {
       .is_ok()
   }
 
+  fn has_variable_serialize_size(&self) -> bool {
+    self
+      .env
+      .serialization_quirks
+      .contains(env::SerializationQuirks::CORE_VARIABLE_SIZE)
+  }
+
   /// Invoked by a `libretro` frontend, with the `retro_unserialize` API call.
   pub unsafe fn on_unserialize(&mut self, data: *const (), size: usize) -> bool {
+    if !self.guard_game_loaded() {
+      return false;
+    }
     let lifetime = ();
     let data = slice_with_lifetime(data as *const u8, size, &lifetime);
     self
@@ -504,6 +1412,12 @@ impl<I, C> SaveStateCoreFallbacks for Instance<I, C> {}
 impl<'a, C: DeviceTypeAwareCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_set_controller_port_device` API call.
   pub unsafe fn on_set_controller_port_device(&mut self, port: DevicePort, device: DeviceTypeId) {
+    self.set_device_for_port(port, device);
+    // Frontends may plug devices in before loading a game; the core can read them from the
+    // callbacks once it exists, so this isn't an error.
+    if self.check_game_loaded().is_err() {
+      return;
+    }
     let system = self.core.assume_init_mut();
     let env = &mut self.env;
     let _ = system.set_controller_port_device(env, port, device);
@@ -512,9 +1426,14 @@ impl<'a, C: DeviceTypeAwareCore<'a>> Instance<C::Init, C> {
 
 #[doc(hidden)]
 pub trait DeviceTypeAwareCoreFallbacks {
-  unsafe fn on_set_controller_port_device(&mut self, _port: DevicePort, _device: DeviceTypeId) {}
+  unsafe fn on_set_controller_port_device(&mut self, port: DevicePort, device: DeviceTypeId);
+}
+
+impl<I, C> DeviceTypeAwareCoreFallbacks for Instance<I, C> {
+  unsafe fn on_set_controller_port_device(&mut self, port: DevicePort, device: DeviceTypeId) {
+    self.set_device_for_port(port, device);
+  }
 }
-impl<I, C> DeviceTypeAwareCoreFallbacks for Instance<I, C> {}
 
 impl<'a, C: CheatsCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_cheat_set` API call.
@@ -522,7 +1441,11 @@ impl<'a, C: CheatsCore<'a>> Instance<C::Init, C> {
   /// # Safety
   /// `code` must be a valid argument to [`CStr::from_ptr`].
   pub unsafe fn on_cheat_set(&mut self, index: c_uint, enabled: bool, code: *const c_char) {
+    if !self.guard_game_loaded() {
+      return;
+    }
     let code = CStr::from_ptr(code);
+    self.cheats_changed = true;
     let env = &mut self.env;
     let _ = self
       .core
@@ -532,6 +1455,10 @@ impl<'a, C: CheatsCore<'a>> Instance<C::Init, C> {
 
   /// Invoked by a `libretro` frontend, with the `retro_cheat_reset` API call.
   pub unsafe fn on_cheat_reset(&mut self) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    self.cheats_changed = true;
     self.core.assume_init_mut().cheat_reset(&mut self.env)
   }
 }
@@ -544,9 +1471,28 @@ pub trait CheatsCoreFallbacks {
 }
 impl<I, C> CheatsCoreFallbacks for Instance<I, C> {}
 
+impl<'a, C: Core<'a>> Instance<C::Init, C> {
+  /// Returns [`None`] when no game is loaded, as the core doesn't exist then.
+  unsafe fn get_achievement_ram(&mut self, id: MemoryType) -> Option<&mut [u8]> {
+    if !self.game_loaded {
+      None
+    } else if id == StandardMemoryType::SystemRam.into() {
+      self.core.assume_init_mut().achievement_ram()
+    } else {
+      None
+    }
+  }
+}
+
 impl<'a, C: GetMemoryRegionCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_get_memory_data` API call.
   pub unsafe fn on_get_memory_data(&mut self, id: MemoryType) -> *mut () {
+    if !self.guard_game_loaded() {
+      return std::ptr::null_mut();
+    }
+    if let Some(ram) = self.get_achievement_ram(id) {
+      return ram.as_mut_ptr() as *mut ();
+    }
     self
       .core
       .assume_init_mut()
@@ -556,6 +1502,12 @@ impl<'a, C: GetMemoryRegionCore<'a>> Instance<C::Init, C> {
 
   /// Invoked by a `libretro` frontend, with the `retro_get_memory_size` API call.
   pub unsafe fn on_get_memory_size(&mut self, id: MemoryType) -> usize {
+    if !self.guard_game_loaded() {
+      return 0;
+    }
+    if let Some(ram) = self.get_achievement_ram(id) {
+      return ram.len();
+    }
     self
       .core
       .assume_init_mut()
@@ -565,15 +1517,22 @@ impl<'a, C: GetMemoryRegionCore<'a>> Instance<C::Init, C> {
 
 #[doc(hidden)]
 pub trait GetMemoryRegionCoreFallbacks {
-  unsafe fn on_get_memory_data(&mut self, _id: MemoryType) -> *mut () {
-    core::ptr::null_mut()
+  unsafe fn on_get_memory_data(&mut self, id: MemoryType) -> *mut ();
+
+  unsafe fn on_get_memory_size(&mut self, id: MemoryType) -> usize;
+}
+
+impl<'a, C: Core<'a>> GetMemoryRegionCoreFallbacks for Instance<C::Init, C> {
+  unsafe fn on_get_memory_data(&mut self, id: MemoryType) -> *mut () {
+    self
+      .get_achievement_ram(id)
+      .map_or_else(std::ptr::null_mut, |ram| ram.as_mut_ptr() as *mut ())
   }
 
-  unsafe fn on_get_memory_size(&mut self, _id: MemoryType) -> usize {
-    0
+  unsafe fn on_get_memory_size(&mut self, id: MemoryType) -> usize {
+    self.get_achievement_ram(id).map_or(0, |ram| ram.len())
   }
 }
-impl<I, C> GetMemoryRegionCoreFallbacks for Instance<I, C> {}
 
 impl<'a, C: SpecialGameCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_load_game_special` API call.
@@ -583,6 +1542,11 @@ impl<'a, C: SpecialGameCore<'a>> Instance<C::Init, C> {
     info: *const retro_game_info,
     num_info: usize,
   ) -> bool {
+    if let Err(error) = self.check_can_load_game() {
+      self.log_error(error);
+      return false;
+    }
+    self.env.game_count = num_info;
     let Instance {
       env, init, core, ..
     } = self;
@@ -591,11 +1555,16 @@ impl<'a, C: SpecialGameCore<'a>> Instance<C::Init, C> {
     // init data will outlive the core.
     let init: &mut C::Init = &mut *(init.assume_init_mut() as *mut C::Init);
     let lifetime = ();
-    let games = slice_with_lifetime(info.cast(), num_info, &lifetime);
+    let games = if info.is_null() {
+      &[]
+    } else {
+      slice_with_lifetime(info.cast(), num_info, &lifetime)
+    };
     let result = <C as SpecialGameCore>::load_game(env, init, game_type, games);
     match result {
       Ok(system) => {
         core.write(system);
+        self.game_loaded = true;
         true
       }
       Err(_) => false,
@@ -619,6 +1588,9 @@ impl<I, C> SpecialGameCoreFallbacks for Instance<I, C> {}
 impl<'a, C: RegionAwareCore<'a>> Instance<C::Init, C> {
   /// Invoked by a `libretro` frontend, with the `retro_get_region` API call.
   pub unsafe fn on_get_region(&mut self) -> c_uint {
+    if !self.guard_game_loaded() {
+      return RETRO_REGION_NTSC;
+    }
     let env = &mut self.env;
     self.core.assume_init_mut().get_region(env).into()
   }
@@ -626,15 +1598,31 @@ impl<'a, C: RegionAwareCore<'a>> Instance<C::Init, C> {
 
 #[doc(hidden)]
 pub trait RegionAwareCoreFallbacks {
+  unsafe fn on_get_region(&mut self) -> c_uint;
+}
+
+impl<'a, C: Core<'a>> RegionAwareCoreFallbacks for Instance<C::Init, C> {
   unsafe fn on_get_region(&mut self) -> c_uint {
-    RETRO_REGION_NTSC.into()
+    if !self.game_loaded {
+      return RETRO_REGION_NTSC;
+    }
+    let fps = match self.fps {
+      Some(fps) => fps,
+      None => {
+        let core = self.core.assume_init_ref();
+        *self
+          .fps
+          .insert(core.get_system_av_info(&mut self.env).timing().fps())
+      }
+    };
+    Region::from_fps(fps).into()
   }
 }
-impl<I, C> RegionAwareCoreFallbacks for Instance<I, C> {}
 
 impl<'a, C: OpenGLCore<'a>> Instance<C::Init, C> {
   pub unsafe fn on_context_reset(&mut self) {
     let callbacks = self.env.gl.core_callbacks.unwrap_unchecked();
+    self.cb.hw_context_ready = true;
     self
       .core
       .assume_init_mut()
@@ -643,37 +1631,302 @@ impl<'a, C: OpenGLCore<'a>> Instance<C::Init, C> {
 
   pub unsafe fn on_context_destroy(&mut self) {
     self.core.assume_init_mut().context_destroy(&mut self.env);
+    self.cb.hw_context_ready = false;
   }
 }
 
 #[doc(hidden)]
 pub trait OpenGLCoreFallbacks {
-  unsafe fn on_context_reset(&mut self) {}
+  unsafe fn on_context_reset(&mut self);
+
+  unsafe fn on_context_destroy(&mut self);
+}
+
+impl<I, C> OpenGLCoreFallbacks for Instance<I, C> {
+  unsafe fn on_context_reset(&mut self) {
+    self.cb.hw_context_ready = true;
+  }
+
+  unsafe fn on_context_destroy(&mut self) {
+    self.cb.hw_context_ready = false;
+  }
+}
+
+// The netpacket callbacks share the same safety contract: they must only be called by the
+// frontend, through the callbacks registered by `env::LoadGame::set_netpacket_interface`.
+impl<'a, C: NetPacketCore<'a>> Instance<C::Init, C> {
+  /// # Safety
+  /// `send_fn` and `poll_receive_fn` must be the frontend's functions for the session that
+  /// starts, valid until [`Instance::on_netpacket_stop`].
+  pub unsafe fn on_netpacket_start(
+    &mut self,
+    client_id: u16,
+    send_fn: retro_netpacket_send_t,
+    poll_receive_fn: retro_netpacket_poll_receive_t,
+  ) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    if let Some(send_fn) = send_fn {
+      let sender = NetPacketSender::new(send_fn, poll_receive_fn);
+      if let Some(previous) = self.netpacket_session.replace(sender.session()) {
+        previous.store(false, Ordering::Release);
+      }
+      let env = &mut self.env;
+      self.core.assume_init_mut().start(env, client_id, sender);
+    }
+  }
+
+  /// # Safety
+  /// `buf` must be null or point to `len` readable bytes that aren't modified during this call.
+  pub unsafe fn on_netpacket_receive(&mut self, buf: *const c_void, len: usize, client_id: u16) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    let lifetime = ();
+    let buf = if buf.is_null() {
+      &[]
+    } else {
+      slice_with_lifetime(buf as *const u8, len, &lifetime)
+    };
+    let env = &mut self.env;
+    self.core.assume_init_mut().receive(env, buf, client_id);
+  }
+
+  /// # Safety
+  /// Must only be called by the frontend, see above.
+  pub unsafe fn on_netpacket_stop(&mut self) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    if let Some(session) = self.netpacket_session.take() {
+      session.store(false, Ordering::Release);
+    }
+    NetPacketCore::stop(self.core.assume_init_mut(), &mut self.env);
+  }
+
+  /// # Safety
+  /// Must only be called by the frontend, see above.
+  pub unsafe fn on_netpacket_poll(&mut self) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    NetPacketCore::poll(self.core.assume_init_mut(), &mut self.env);
+  }
+
+  /// # Safety
+  /// Must only be called by the frontend, see above.
+  pub unsafe fn on_netpacket_connected(&mut self, client_id: u16) -> bool {
+    if !self.guard_game_loaded() {
+      return false;
+    }
+    let env = &mut self.env;
+    self.core.assume_init_mut().connected(env, client_id)
+  }
+
+  /// # Safety
+  /// Must only be called by the frontend, see above.
+  pub unsafe fn on_netpacket_disconnected(&mut self, client_id: u16) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    let env = &mut self.env;
+    self.core.assume_init_mut().disconnected(env, client_id);
+  }
+
+  /// Replays the receive and stop callbacks the frontend made during [`Instance::on_run`], when
+  /// the core couldn't take them. See [`NetPacketSender::poll_receive`].
+  ///
+  /// # Safety
+  /// Must only be called from `retro_run`, after [`Instance::on_run`].
+  pub unsafe fn on_netpacket_deferred(&mut self) {
+    for callback in mem::take(&mut self.netpacket_deferred) {
+      match callback {
+        DeferredNetPacket::Receive(buf, client_id) => {
+          self.on_netpacket_receive(buf.as_ptr().cast(), buf.len(), client_id)
+        }
+        DeferredNetPacket::Stop => self.on_netpacket_stop(),
+      }
+    }
+  }
+}
+
+#[doc(hidden)]
+pub trait NetPacketCoreFallbacks {
+  unsafe fn on_netpacket_start(
+    &mut self,
+    _client_id: u16,
+    _send_fn: retro_netpacket_send_t,
+    _poll_receive_fn: retro_netpacket_poll_receive_t,
+  ) {
+  }
+
+  unsafe fn on_netpacket_receive(&mut self, _buf: *const c_void, _len: usize, _client_id: u16) {}
+
+  unsafe fn on_netpacket_stop(&mut self) {}
+
+  unsafe fn on_netpacket_poll(&mut self) {}
+
+  unsafe fn on_netpacket_connected(&mut self, _client_id: u16) -> bool {
+    true
+  }
+
+  unsafe fn on_netpacket_disconnected(&mut self, _client_id: u16) {}
+
+  unsafe fn on_netpacket_deferred(&mut self) {}
+}
+impl<I, C> NetPacketCoreFallbacks for Instance<I, C> {}
+
+impl<'a, C: AudioBufferStatusCore<'a>> Instance<C::Init, C> {
+  /// # Safety
+  /// Must only be called by the frontend, through the callback registered by
+  /// [`env::LoadGame::set_audio_buffer_status_callback`], while a game is loaded.
+  pub unsafe fn on_audio_buffer_status(
+    &mut self,
+    active: bool,
+    occupancy: c_uint,
+    underrun_likely: bool,
+  ) {
+    if !self.guard_game_loaded() {
+      return;
+    }
+    let env = &mut self.env;
+    self
+      .core
+      .assume_init_mut()
+      .audio_buffer_status(env, active, occupancy, underrun_likely);
+  }
+}
+
+#[doc(hidden)]
+pub trait AudioBufferStatusCoreFallbacks {
+  unsafe fn on_audio_buffer_status(
+    &mut self,
+    _active: bool,
+    _occupancy: c_uint,
+    _underrun_likely: bool,
+  ) {
+  }
+}
+impl<I, C> AudioBufferStatusCoreFallbacks for Instance<I, C> {}
 
-  unsafe fn on_context_destroy(&mut self) {}
+/// Stands in for the environment callback of frontends that call into the core before
+/// `retro_set_environment`.
+unsafe extern "C" fn missing_environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+  false
 }
-impl<I, C> OpenGLCoreFallbacks for Instance<I, C> {}
 
 #[doc(hidden)]
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug)]
 pub struct InstanceEnvironment {
   cb: retro_environment_t,
   gl: InstanceGLState,
+  netpacket: Option<InstanceNetPacketState>,
+  audio_buffer_status: retro_audio_buffer_status_callback_t,
+  /// Whether the frontend accepted [`InstanceEnvironment::audio_buffer_status`], since the game
+  /// was loaded.
+  audio_buffer_status_enabled: bool,
+  core_options_version: c_uint,
+  /// The API call or callback in progress, when called through [`Instance::catch_panic`].
+  entry_point: Option<&'static str>,
+  /// The number of API calls made from inside another one. See [`Instance::catch_panic`].
+  nested_api_calls: c_uint,
+  run_only_violations: c_uint,
+  /// The core options the core wrote and their new values, until the next variable update
+  /// query.
+  written_variables: Vec<(CString, CString)>,
+  serialization_quirks: env::SerializationQuirks,
+  /// The number of games passed to the load call in progress. See [`env::LoadGame::game_count`].
+  game_count: usize,
+  /// Set when the frontend accepts a shutdown request, until [`Core::on_shutdown_requested`] is
+  /// called.
+  shutdown_requested: bool,
 }
 
 impl InstanceEnvironment {
   pub const fn new(cb: retro_environment_t, gl: InstanceGLState) -> Self {
-    Self { cb, gl }
+    Self {
+      cb,
+      gl,
+      netpacket: None,
+      audio_buffer_status: None,
+      audio_buffer_status_enabled: false,
+      core_options_version: 0,
+      entry_point: None,
+      nested_api_calls: 0,
+      run_only_violations: 0,
+      written_variables: Vec::new(),
+      serialization_quirks: env::SerializationQuirks::new(0),
+      game_count: 1,
+      shutdown_requested: false,
+    }
   }
 }
 
 impl Environment for InstanceEnvironment {
+  /// Until the frontend calls `retro_set_environment`, every command fails as if the frontend
+  /// didn't support it.
   fn get_ptr(&self) -> non_null_retro_environment_t {
-    unsafe { self.cb.unwrap_unchecked() }
+    self.cb.unwrap_or(missing_environment)
+  }
+
+  /// Returns the version queried in `retro_set_environment`, as the frontend can't change it
+  /// afterwards.
+  fn get_core_options_version(&self) -> c_uint {
+    self.core_options_version
+  }
+
+  /// In debug builds, logs commands that are issued outside of `retro_run`, e.g. from
+  /// [`AudioBufferStatusCore::audio_buffer_status`] or a callback invoked during `retro_run`, as
+  /// some frontends crash on them.
+  fn check_run_only(&mut self, command: &str) {
+    if cfg!(debug_assertions) && self.entry_point != Some("retro_run") {
+      self.run_only_violations += 1;
+      crate::retro_error!(
+        self.get_log_interface().ok(),
+        "{} must only be issued from retro_run, not {}",
+        command,
+        self.entry_point.unwrap_or("outside of any API call")
+      );
+    }
+  }
+
+  fn shutdown_accepted(&mut self) {
+    self.shutdown_requested = true;
+  }
+
+  fn variable_written(&mut self, key: &CStr, value: &CStr) {
+    self
+      .written_variables
+      .retain(|(written, _)| written.as_c_str() != key);
+    self
+      .written_variables
+      .push((key.to_owned(), value.to_owned()));
+  }
+
+  fn serialization_quirks_set(&mut self, quirks: env::SerializationQuirks) {
+    self.serialization_quirks = quirks;
+  }
+
+  /// Hides the variable update caused by the core's own [`Environment::set_variable`], unless
+  /// one of the options it wrote has changed since. The writes are forgotten after one query, in
+  /// case the frontend doesn't report them at all.
+  fn filter_variable_update(&mut self, updated: bool) -> bool {
+    let written = mem::take(&mut self.written_variables);
+    if !updated || written.is_empty() {
+      return updated;
+    }
+    !written.iter().all(|(key, value)| {
+      matches!(self.get_variable(key), Ok(Some(current)) if current == value.as_c_str())
+    })
   }
 }
 
 impl env::LoadGame for InstanceEnvironment {
+  fn game_count(&self) -> usize {
+    self.game_count
+  }
+
   fn set_hw_render_none(&mut self) -> env::Result<()> {
     let data = retro_hw_render_callback::default();
     unsafe { self.cmd(RETRO_ENVIRONMENT_SET_HW_RENDER, data) }.map(|_: retro_hw_render_callback| ())
@@ -692,16 +1945,54 @@ impl env::LoadGame for InstanceEnvironment {
     }
     Ok(GLRenderEnabled(()))
   }
+
+  fn set_netpacket_interface(
+    &mut self,
+    protocol_version: Option<&'static CStr>,
+  ) -> env::Result<()> {
+    let Some(netpacket) = self.netpacket else {
+      return Err(CommandError::new());
+    };
+    let data = netpacket.callbacks(protocol_version);
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_NETPACKET_INTERFACE, &data) }
+  }
+
+  fn set_audio_buffer_status_callback(&mut self) -> env::Result<()> {
+    if self.audio_buffer_status.is_none() {
+      return Err(CommandError::new());
+    }
+    let data = retro_audio_buffer_status_callback {
+      callback: self.audio_buffer_status,
+    };
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_AUDIO_BUFFER_STATUS_CALLBACK, &data)? };
+    self.audio_buffer_status_enabled = true;
+    Ok(())
+  }
 }
 
 #[doc(hidden)]
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InstanceCallbacks {
   audio_sample: retro_audio_sample_t,
   audio_sample_batch: retro_audio_sample_batch_t,
   input_poll: retro_input_poll_t,
   input_state: retro_input_state_t,
   video_refresh: retro_video_refresh_t,
+  hw_context_ready: bool,
+  /// Whether a hardware frame was uploaded before `context_reset` during the current frame.
+  early_hw_frame: bool,
+  /// Whether an early hardware frame was already logged, so it's only logged once.
+  early_hw_frame_logged: bool,
+  /// Whether audio with a trailing odd sample was uploaded during the current frame.
+  odd_audio_frame: bool,
+  /// Whether odd audio was already logged, so it's only logged once.
+  odd_audio_frame_logged: bool,
+  caps: RunCapabilities,
+  video_alignment: usize,
+  /// Joypad bitmasks queried since the last poll, per port. Cleared when inputs are polled, and
+  /// for a single port when the device in that port changes.
+  joypad_cache: RefCell<Vec<(DevicePort, JoypadState)>>,
+  port_devices: Vec<(DevicePort, DeviceTypeId)>,
 }
 
 impl InstanceCallbacks {
@@ -712,10 +2003,73 @@ impl InstanceCallbacks {
       input_poll: None,
       input_state: None,
       video_refresh: None,
+      hw_context_ready: false,
+      early_hw_frame: false,
+      early_hw_frame_logged: false,
+      odd_audio_frame: false,
+      odd_audio_frame_logged: false,
+      caps: RunCapabilities {
+        bitmasks: false,
+        can_dupe: false,
+        video_enabled: true,
+        audio_enabled: true,
+        fast_forwarding: false,
+        max_users: 1,
+      },
+      video_alignment: 1,
+      joypad_cache: RefCell::new(Vec::new()),
+      port_devices: Vec::new(),
+    }
+  }
+
+  fn device_for_port(&self, port: DevicePort) -> DeviceTypeId {
+    self
+      .port_devices
+      .iter()
+      .find(|(p, _)| *p == port)
+      .map_or(DeviceTypeId::new(RETRO_DEVICE_JOYPAD), |(_, device)| {
+        *device
+      })
+  }
+
+  fn set_device_for_port(&mut self, port: DevicePort, device: DeviceTypeId) {
+    self.joypad_cache.get_mut().retain(|(p, _)| *p != port);
+    match self.port_devices.iter_mut().find(|(p, _)| *p == port) {
+      Some((_, d)) => *d = device,
+      None => self.port_devices.push((port, device)),
+    }
+  }
+
+  /// Checks that the frontend set every callback needed to run the core.
+  fn check_callbacks(&self) -> Result<(), InstanceError> {
+    let callbacks = [
+      (self.video_refresh.is_some(), CallbackKind::VideoRefresh),
+      (self.audio_sample.is_some(), CallbackKind::AudioSample),
+      (
+        self.audio_sample_batch.is_some(),
+        CallbackKind::AudioSampleBatch,
+      ),
+      (self.input_poll.is_some(), CallbackKind::InputPoll),
+      (self.input_state.is_some(), CallbackKind::InputState),
+    ];
+    match callbacks.into_iter().find(|(set, _)| !set) {
+      Some((_, kind)) => Err(InstanceError::MissingCallback(kind)),
+      None => Ok(()),
     }
   }
 
+  unsafe fn input_state(&self, port: DevicePort, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+    self.input_state.unwrap_unchecked()(port.into_inner(), device, index, id)
+  }
+
+  // `is_multiple_of` needs Rust 1.87.
+  #[allow(clippy::manual_is_multiple_of)]
   unsafe fn upload_audio_frame(&mut self, frame: &[i16]) -> usize {
+    debug_assert!(
+      frame.len() % 2 == 0,
+      "audio frames should be interleaved stereo samples"
+    );
+    self.odd_audio_frame |= frame.len() % 2 != 0;
     self.audio_sample_batch.unwrap_unchecked()(frame.as_ptr(), frame.len() / 2)
   }
 
@@ -729,6 +2083,10 @@ impl InstanceCallbacks {
     _pixel_format: &Format<P>,
     framebuffer: &Frame<'_, P>,
   ) {
+    debug_assert!(
+      framebuffer.data().as_ptr() as usize & (self.video_alignment - 1) == 0,
+      "frame buffer should be aligned to Core::VIDEO_ALIGNMENT"
+    );
     self.video_refresh.unwrap_unchecked()(
       framebuffer.data().as_ptr() as *const c_void,
       framebuffer.width(),
@@ -737,32 +2095,140 @@ impl InstanceCallbacks {
     )
   }
 
+  unsafe fn upload_software_framebuffer(
+    &mut self,
+    _enabled: &SoftwareRenderEnabled,
+    framebuffer: SoftwareFramebuffer<'_>,
+  ) {
+    self.video_refresh.unwrap_unchecked()(
+      framebuffer.data().as_ptr() as *const c_void,
+      framebuffer.width(),
+      framebuffer.height(),
+      framebuffer.pitch(),
+    )
+  }
+
   unsafe fn repeat_video_frame(&mut self) {
     self.video_refresh.unwrap_unchecked()(core::ptr::null(), 0, 0, 0)
   }
 
+  unsafe fn dup_video_frame(&mut self, width: c_uint, height: c_uint) {
+    self.video_refresh.unwrap_unchecked()(core::ptr::null(), width, height, 0)
+  }
+
   unsafe fn use_hardware_frame_buffer(
     &mut self,
     _hw_render_enabled: &impl HWRenderEnabled,
     width: c_uint,
     height: c_uint,
   ) {
+    self.early_hw_frame |= !self.hw_context_ready;
     self.video_refresh.unwrap_unchecked()(RETRO_HW_FRAME_BUFFER_VALID, width, height, 0)
   }
 
   unsafe fn poll_inputs(&mut self) -> InputsPolled {
+    self.joypad_cache.get_mut().clear();
     self.input_poll.unwrap_unchecked()();
     InputsPolled(())
   }
 
   /// Returns true if the specified button is pressed, false otherwise.
+  ///
+  /// With input bitmasks, this reads the cached state of the whole joypad, so querying every button
+  /// of a port takes a single call to the frontend.
   unsafe fn is_joypad_button_pressed(&self, port: DevicePort, btn: JoypadButton) -> bool {
+    if self.caps.bitmasks {
+      self.joypad_bitmask(port).contains(btn)
+    } else {
+      self.query_joypad_button(port, btn)
+    }
+  }
+
+  unsafe fn query_joypad_button(&self, port: DevicePort, btn: JoypadButton) -> bool {
     let port = c_uint::from(port.into_inner());
     let device = RETRO_DEVICE_JOYPAD;
     let index = 0;
     let id = btn.into();
     self.input_state.unwrap_unchecked()(port, device, index, id) != 0
   }
+
+  unsafe fn joypad_bitmask(&self, port: DevicePort) -> JoypadState {
+    if self.caps.bitmasks {
+      let mut cache = self.joypad_cache.borrow_mut();
+      if let Some((_, state)) = cache.iter().find(|(p, _)| *p == port) {
+        return *state;
+      }
+      let id = RETRO_DEVICE_ID_JOYPAD_MASK;
+      let bits = self.input_state.unwrap_unchecked()(port.into_inner(), RETRO_DEVICE_JOYPAD, 0, id);
+      let state = JoypadState::from_bits(bits as u16);
+      cache.push((port, state));
+      state
+    } else {
+      let mut state = JoypadState::default();
+      for btn in JoypadButton::ALL {
+        state.set(btn, self.query_joypad_button(port, btn));
+      }
+      state
+    }
+  }
+
+  unsafe fn read_device(&self, port: DevicePort) -> DeviceInput {
+    let device = self.device_for_port(port);
+    let Ok(device_type) = DeviceType::try_from(device) else {
+      return DeviceInput::Unsupported(device);
+    };
+    match device_type {
+      DeviceType::None => DeviceInput::None,
+      DeviceType::Joypad => DeviceInput::Joypad(self.joypad_bitmask(port)),
+      DeviceType::Analog => {
+        let stick = |index, id| self.input_state(port, RETRO_DEVICE_ANALOG, index, id);
+        DeviceInput::Analog(AnalogState {
+          left_x: stick(RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_X),
+          left_y: stick(RETRO_DEVICE_INDEX_ANALOG_LEFT, RETRO_DEVICE_ID_ANALOG_Y),
+          right_x: stick(RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_X),
+          right_y: stick(RETRO_DEVICE_INDEX_ANALOG_RIGHT, RETRO_DEVICE_ID_ANALOG_Y),
+          buttons: self.joypad_bitmask(port),
+        })
+      }
+      DeviceType::Mouse => {
+        let mouse = |id| self.input_state(port, RETRO_DEVICE_MOUSE, 0, id);
+        DeviceInput::Mouse(MouseState {
+          x: mouse(RETRO_DEVICE_ID_MOUSE_X),
+          y: mouse(RETRO_DEVICE_ID_MOUSE_Y),
+          left: mouse(RETRO_DEVICE_ID_MOUSE_LEFT) != 0,
+          right: mouse(RETRO_DEVICE_ID_MOUSE_RIGHT) != 0,
+          middle: mouse(RETRO_DEVICE_ID_MOUSE_MIDDLE) != 0,
+          wheel_up: mouse(RETRO_DEVICE_ID_MOUSE_WHEELUP) != 0,
+          wheel_down: mouse(RETRO_DEVICE_ID_MOUSE_WHEELDOWN) != 0,
+        })
+      }
+      DeviceType::Pointer => {
+        let pointer = |id| self.input_state(port, RETRO_DEVICE_POINTER, 0, id);
+        DeviceInput::Pointer(PointerState {
+          x: pointer(RETRO_DEVICE_ID_POINTER_X),
+          y: pointer(RETRO_DEVICE_ID_POINTER_Y),
+          pressed: pointer(RETRO_DEVICE_ID_POINTER_PRESSED) != 0,
+        })
+      }
+      DeviceType::LightGun => {
+        let gun = |id| self.input_state(port, RETRO_DEVICE_LIGHTGUN, 0, id);
+        let pressed = |id| gun(id) != 0;
+        DeviceInput::LightGun(LightGunState {
+          x: gun(RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X),
+          y: gun(RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y),
+          offscreen: pressed(RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN),
+          trigger: pressed(RETRO_DEVICE_ID_LIGHTGUN_TRIGGER),
+          reload: pressed(RETRO_DEVICE_ID_LIGHTGUN_RELOAD),
+          aux_a: pressed(RETRO_DEVICE_ID_LIGHTGUN_AUX_A),
+          aux_b: pressed(RETRO_DEVICE_ID_LIGHTGUN_AUX_B),
+          aux_c: pressed(RETRO_DEVICE_ID_LIGHTGUN_AUX_C),
+          start: pressed(RETRO_DEVICE_ID_LIGHTGUN_START),
+          select: pressed(RETRO_DEVICE_ID_LIGHTGUN_SELECT),
+        })
+      }
+      _ => DeviceInput::Unsupported(device),
+    }
+  }
 }
 
 #[doc(hidden)]
@@ -786,6 +2252,61 @@ impl InstanceGLState {
   }
 }
 
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceNetPacketState {
+  start: non_null_retro_netpacket_start_t,
+  receive: non_null_retro_netpacket_receive_t,
+  stop: non_null_retro_netpacket_stop_t,
+  poll: non_null_retro_netpacket_poll_t,
+  connected: non_null_retro_netpacket_connected_t,
+  disconnected: non_null_retro_netpacket_disconnected_t,
+}
+
+impl InstanceNetPacketState {
+  pub const fn new(
+    start: non_null_retro_netpacket_start_t,
+    receive: non_null_retro_netpacket_receive_t,
+    stop: non_null_retro_netpacket_stop_t,
+    poll: non_null_retro_netpacket_poll_t,
+    connected: non_null_retro_netpacket_connected_t,
+    disconnected: non_null_retro_netpacket_disconnected_t,
+  ) -> Self {
+    Self {
+      start,
+      receive,
+      stop,
+      poll,
+      connected,
+      disconnected,
+    }
+  }
+
+  fn callbacks(&self, protocol_version: Option<&'static CStr>) -> retro_netpacket_callback {
+    retro_netpacket_callback {
+      start: Some(self.start),
+      receive: Some(self.receive),
+      stop: Some(self.stop),
+      poll: Some(self.poll),
+      connected: Some(self.connected),
+      disconnected: Some(self.disconnected),
+      protocol_version: protocol_version.map_or_else(core::ptr::null, CStr::as_ptr),
+    }
+  }
+}
+
+/// Describes what's unusual about the arguments of `retro_set_controller_port_device`, if
+/// anything. Subclassed devices are fine as long as their base type is.
+fn port_device_warning(port: DevicePort, device: DeviceTypeId) -> Option<&'static str> {
+  if u8::try_from(port.into_inner()).is_err() {
+    Some("out of range port")
+  } else if DeviceType::try_from(device).is_err() {
+    Some("unknown device type")
+  } else {
+    None
+  }
+}
+
 unsafe fn as_ref_with_lifetime<T>(ptr: *const T, _lifetime: &()) -> Option<&T> {
   ptr.as_ref()
 }
@@ -798,6 +2319,67 @@ unsafe fn slice_with_lifetime_mut<T>(ptr: *mut T, len: usize, _lifetime: &()) ->
   slice::from_raw_parts_mut(ptr, len)
 }
 
+// The glue is written against version 1 of `libretro.h`; a different version in regenerated
+// bindings means the API changed in ways the glue doesn't handle.
+const _: () = assert!(RETRO_API_VERSION == 1, "unsupported libretro API version");
+
+/// The `libretro` API version implemented by this crate, which the [`libretro_core`] macro returns
+/// from `retro_api_version`. Frontends refuse to load cores that report another version.
+pub const fn libretro_api_version() -> c_uint {
+  RETRO_API_VERSION
+}
+
+/// Runs `f`, the handler of the `entry_point` API call made by the frontend.
+///
+/// With the `thread-safe` feature, the calls are serialized by a global lock, so frontends calling
+/// e.g. `retro_run` and `retro_serialize` from different threads don't race on the core. This
+/// costs a lock per call, and a call from another thread waits until the running one returns,
+/// which is a whole frame for `retro_run`. The lock doesn't make the core reentrant though: a call
+/// made on the same thread while another one is running, e.g. from a frontend function the core
+/// called, would alias the running call's core, so it's logged to stderr and rejected with `None`.
+///
+/// Without the feature, `f` is called directly and the frontend must call the core from a single
+/// thread at a time, and never from inside another call, as the `libretro` API requires.
+#[doc(hidden)]
+#[cfg(feature = "thread-safe")]
+pub fn serialize_api_call<R>(entry_point: &'static str, f: impl FnOnce() -> R) -> Option<R> {
+  use std::cell::Cell;
+  use std::sync::{Mutex, PoisonError};
+
+  static LOCK: Mutex<()> = Mutex::new(());
+  thread_local! {
+    static HOLDS_LOCK: Cell<bool> = const { Cell::new(false) };
+  }
+
+  struct Release;
+
+  impl Drop for Release {
+    fn drop(&mut self) {
+      HOLDS_LOCK.set(false);
+    }
+  }
+
+  if HOLDS_LOCK.get() {
+    crate::retro_error!(
+      StderrLogger,
+      "the frontend called {} from inside another libretro call",
+      entry_point
+    );
+    return None;
+  }
+  let _guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+  HOLDS_LOCK.set(true);
+  let _release = Release;
+  Some(f())
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "thread-safe"))]
+#[inline(always)]
+pub fn serialize_api_call<R>(_entry_point: &'static str, f: impl FnOnce() -> R) -> Option<R> {
+  Some(f())
+}
+
 #[macro_export]
 macro_rules! libretro_core {
   ($core:ty) => {
@@ -810,61 +2392,109 @@ macro_rules! libretro_core {
       use libretro_rs::retro::*;
 
       static mut RETRO_INSTANCE: Instance<<$core as Core>::Init, $core> =
-        Instance::new(on_context_reset, on_context_destroy);
+        Instance::new(on_context_reset, on_context_destroy)
+          .with_netpacket(InstanceNetPacketState::new(
+            on_netpacket_start,
+            on_netpacket_receive,
+            on_netpacket_stop,
+            on_netpacket_poll,
+            on_netpacket_connected,
+            on_netpacket_disconnected,
+          ))
+          .with_audio_buffer_status(on_audio_buffer_status);
+
+      /// Runs `f` on the instance, unless the call is rejected. See [`serialize_api_call`].
+      unsafe fn with_instance<R>(
+        entry_point: &'static str,
+        f: impl FnOnce(&mut Instance<<$core as Core<'static>>::Init, $core>) -> R,
+      ) -> Option<R> {
+        serialize_api_call(entry_point, || {
+          f(&mut *core::ptr::addr_of_mut!(RETRO_INSTANCE))
+        })
+      }
+
+      /// Runs `f` on the instance with [`Instance::catch_panic`], or returns `fallback` if the
+      /// call is rejected. See [`serialize_api_call`].
+      unsafe fn catch_panic<R: Copy>(
+        entry_point: &'static str,
+        fallback: R,
+        f: impl FnOnce(&mut Instance<<$core as Core<'static>>::Init, $core>) -> R,
+      ) -> R {
+        with_instance(entry_point, |instance| {
+          instance.catch_panic(entry_point, fallback, f)
+        })
+        .unwrap_or(fallback)
+      }
 
       #[no_mangle]
       extern "C" fn retro_api_version() -> c_uint {
-        RETRO_API_VERSION
+        libretro_api_version()
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_get_system_info(info: &mut retro_system_info) {
-        RETRO_INSTANCE.on_get_system_info(info)
+        catch_panic("retro_get_system_info", (), |instance| {
+          instance.on_get_system_info(info)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_get_system_av_info(info: &mut retro_system_av_info) {
-        RETRO_INSTANCE.on_get_system_av_info(info)
+        catch_panic("retro_get_system_av_info", (), |instance| {
+          instance.on_get_system_av_info(info)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_init() {
-        RETRO_INSTANCE.on_init()
+        catch_panic("retro_init", (), |instance| instance.on_init())
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_deinit() {
-        RETRO_INSTANCE.on_deinit()
+        catch_panic("retro_deinit", (), |instance| instance.on_deinit())
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_environment(cb: non_null_retro_environment_t) {
-        RETRO_INSTANCE.on_set_environment(cb)
+        catch_panic("retro_set_environment", (), |instance| {
+          instance.on_set_environment(cb)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_audio_sample(cb: non_null_retro_audio_sample_t) {
-        RETRO_INSTANCE.on_set_audio_sample(cb)
+        with_instance("retro_set_audio_sample", |instance| {
+          instance.on_set_audio_sample(cb)
+        });
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_audio_sample_batch(cb: non_null_retro_audio_sample_batch_t) {
-        RETRO_INSTANCE.on_set_audio_sample_batch(cb)
+        with_instance("retro_set_audio_sample_batch", |instance| {
+          instance.on_set_audio_sample_batch(cb)
+        });
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_input_poll(cb: non_null_retro_input_poll_t) {
-        RETRO_INSTANCE.on_set_input_poll(cb)
+        with_instance("retro_set_input_poll", |instance| {
+          instance.on_set_input_poll(cb)
+        });
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_input_state(cb: non_null_retro_input_state_t) {
-        RETRO_INSTANCE.on_set_input_state(cb)
+        with_instance("retro_set_input_state", |instance| {
+          instance.on_set_input_state(cb)
+        });
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_set_video_refresh(cb: non_null_retro_video_refresh_t) {
-        RETRO_INSTANCE.on_set_video_refresh(cb)
+        with_instance("retro_set_video_refresh", |instance| {
+          instance.on_set_video_refresh(cb)
+        });
       }
 
       #[no_mangle]
@@ -872,86 +2502,1679 @@ macro_rules! libretro_core {
         port: DevicePort,
         device: DeviceTypeId,
       ) {
-        RETRO_INSTANCE.on_set_controller_port_device(port, device)
+        catch_panic("retro_set_controller_port_device", (), |instance| {
+          instance.on_set_controller_port_device(port, device)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_reset() {
-        RETRO_INSTANCE.on_reset()
+        catch_panic("retro_reset", (), |instance| {
+          if let Err(err) = instance.try_reset() {
+            instance.log_error(err);
+          }
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_run() {
-        RETRO_INSTANCE.on_run()
+        catch_panic("retro_run", (), |instance| {
+          if let Err(err) = instance.try_run() {
+            instance.log_error(err);
+          }
+          instance.on_netpacket_deferred();
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_serialize_size() -> usize {
-        RETRO_INSTANCE.on_serialize_size()
+        catch_panic("retro_serialize_size", 0, |instance| {
+          instance.on_serialize_size()
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_serialize(data: *mut (), size: usize) -> bool {
-        RETRO_INSTANCE.on_serialize(data, size)
+        catch_panic("retro_serialize", false, |instance| {
+          instance.on_serialize(data, size)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_unserialize(data: *const (), size: usize) -> bool {
-        RETRO_INSTANCE.on_unserialize(data, size)
+        catch_panic("retro_unserialize", false, |instance| {
+          instance.on_unserialize(data, size)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_cheat_reset() {
-        RETRO_INSTANCE.on_cheat_reset()
+        catch_panic("retro_cheat_reset", (), |instance| {
+          instance.on_cheat_reset()
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
-        RETRO_INSTANCE.on_cheat_set(index, enabled, code)
+        catch_panic("retro_cheat_set", (), |instance| {
+          instance.on_cheat_set(index, enabled, code)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_load_game(game: *const retro_game_info) -> bool {
-        RETRO_INSTANCE.on_load_game(game)
+        catch_panic("retro_load_game", false, |instance| {
+          match instance.try_load_game(game) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+              instance.log_error(err);
+              false
+            }
+          }
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_load_game_special(
         game_type: GameType,
-        info: &retro_game_info,
+        info: *const retro_game_info,
         num_info: usize,
       ) -> bool {
-        RETRO_INSTANCE.on_load_game_special(game_type, info, num_info)
+        catch_panic("retro_load_game_special", false, |instance| {
+          instance.on_load_game_special(game_type, info, num_info)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_unload_game() {
-        RETRO_INSTANCE.on_unload_game()
+        catch_panic("retro_unload_game", (), |instance| {
+          if let Err(err) = instance.try_unload_game() {
+            instance.log_error(err);
+          }
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_get_region() -> c_uint {
-        RETRO_INSTANCE.on_get_region()
+        catch_panic("retro_get_region", 0, |instance| instance.on_get_region())
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_get_memory_data(id: MemoryType) -> *mut () {
-        RETRO_INSTANCE.on_get_memory_data(id)
+        catch_panic("retro_get_memory_data", core::ptr::null_mut(), |instance| {
+          instance.on_get_memory_data(id)
+        })
       }
 
       #[no_mangle]
       unsafe extern "C" fn retro_get_memory_size(id: MemoryType) -> usize {
-        RETRO_INSTANCE.on_get_memory_size(id)
+        catch_panic("retro_get_memory_size", 0, |instance| {
+          instance.on_get_memory_size(id)
+        })
       }
 
       // These don't need no_mangle; they're only used through pointers
       unsafe extern "C" fn on_context_reset() {
-        RETRO_INSTANCE.on_context_reset()
+        catch_panic("on_context_reset", (), |instance| {
+          instance.on_context_reset()
+        })
       }
 
       unsafe extern "C" fn on_context_destroy() {
-        RETRO_INSTANCE.on_context_destroy()
+        catch_panic("on_context_destroy", (), |instance| {
+          instance.on_context_destroy()
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_start(
+        client_id: u16,
+        send_fn: retro_netpacket_send_t,
+        poll_receive_fn: retro_netpacket_poll_receive_t,
+      ) {
+        catch_panic("on_netpacket_start", (), |instance| {
+          instance.on_netpacket_start(client_id, send_fn, poll_receive_fn)
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_receive(buf: *const c_void, len: usize, client_id: u16) {
+        // The frontend calls these from `NetPacketSender::poll_receive` while `retro_run` holds
+        // the instance, so they're queued instead.
+        if defer_netpacket_receive(buf, len, client_id) {
+          return;
+        }
+        catch_panic("on_netpacket_receive", (), |instance| {
+          instance.on_netpacket_receive(buf, len, client_id)
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_stop() {
+        if defer_netpacket_stop() {
+          return;
+        }
+        catch_panic("on_netpacket_stop", (), |instance| {
+          instance.on_netpacket_stop()
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_poll() {
+        catch_panic("on_netpacket_poll", (), |instance| {
+          instance.on_netpacket_poll()
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_connected(client_id: u16) -> bool {
+        catch_panic("on_netpacket_connected", false, |instance| {
+          instance.on_netpacket_connected(client_id)
+        })
+      }
+
+      unsafe extern "C" fn on_netpacket_disconnected(client_id: u16) {
+        catch_panic("on_netpacket_disconnected", (), |instance| {
+          instance.on_netpacket_disconnected(client_id)
+        })
+      }
+
+      unsafe extern "C" fn on_audio_buffer_status(
+        active: bool,
+        occupancy: c_uint,
+        underrun_likely: bool,
+      ) {
+        catch_panic("on_audio_buffer_status", (), |instance| {
+          instance.on_audio_buffer_status(active, occupancy, underrun_likely)
+        })
       }
     }
   };
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::retro::pixel::XRGB8888;
+  use crate::retro::testing::MockEnvironment;
+  use c_utf8::c_utf8;
+  use core::cell::Cell;
+  use core::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+  use std::sync::Mutex;
+
+  static HW_FRAMES: AtomicUsize = AtomicUsize::new(0);
+  static LAST_FRAME: Mutex<Option<(usize, c_uint, c_uint, usize)>> = Mutex::new(None);
+  static DUPED_FRAME: Mutex<Option<(usize, c_uint, c_uint, usize)>> = Mutex::new(None);
+
+  unsafe extern "C" fn video_refresh(data: *const c_void, _: c_uint, _: c_uint, _: usize) {
+    if data == RETRO_HW_FRAME_BUFFER_VALID {
+      HW_FRAMES.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  static LOG_QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn log_counting_environment(cmd: c_uint, _data: *mut c_void) -> bool {
+    if cmd == RETRO_ENVIRONMENT_GET_LOG_INTERFACE {
+      LOG_QUERIES.fetch_add(1, Ordering::SeqCst);
+    }
+    false
+  }
+
+  #[test]
+  fn test_hw_frame_before_context_reset() {
+    let mut instance = new_instance::<(), ()>();
+    instance.env.cb = Some(log_counting_environment);
+    instance.cb.video_refresh = Some(video_refresh);
+    let enabled = GLRenderEnabled(());
+    unsafe {
+      for _ in 0..2 {
+        instance.cb.use_hardware_frame_buffer(&enabled, 320, 240);
+        instance.report_early_hw_frame();
+      }
+      assert_eq!(HW_FRAMES.load(Ordering::SeqCst), 2, "frames are still sent");
+      assert_eq!(LOG_QUERIES.load(Ordering::SeqCst), 1, "logged only once");
+
+      instance.cb.hw_context_ready = true;
+      instance.cb.use_hardware_frame_buffer(&enabled, 320, 240);
+      assert!(!instance.cb.early_hw_frame);
+      assert_eq!(HW_FRAMES.load(Ordering::SeqCst), 3);
+    }
+  }
+
+  unsafe extern "C" fn record_frame(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+  ) {
+    *LAST_FRAME.lock().unwrap() = Some((data as usize, width, height, pitch));
+  }
+
+  unsafe extern "C" fn input_state(
+    _port: c_uint,
+    _device: c_uint,
+    _index: c_uint,
+    id: c_uint,
+  ) -> i16 {
+    const PRESSED: u16 = (1 << JoypadButton::A as u16) | (1 << JoypadButton::Start as u16);
+    match id {
+      RETRO_DEVICE_ID_JOYPAD_MASK => PRESSED as i16,
+      id if id < 16 => (PRESSED >> id & 1) as i16,
+      _ => 0,
+    }
+  }
+
+  #[test]
+  fn test_joypad_bitmask() {
+    let mut cb = InstanceCallbacks::new();
+    cb.input_state = Some(input_state);
+    for input_bitmasks in [true, false] {
+      cb.caps.bitmasks = input_bitmasks;
+      let state = unsafe { cb.joypad_bitmask(DevicePort::new(0)) };
+      assert!(state.contains(JoypadButton::A));
+      assert!(state.contains(JoypadButton::Start));
+      assert!(!state.contains(JoypadButton::B));
+      assert_eq!(state.bits().count_ones(), 2);
+    }
+  }
+
+  unsafe extern "C" fn echo_input_state(
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+  ) -> i16 {
+    (port * 1000 + device * 100 + index * 10 + id) as i16
+  }
+
+  #[test]
+  fn test_raw_input_state() {
+    let mut cb = InstanceCallbacks::new();
+    cb.input_state = Some(echo_input_state);
+    let port = DevicePort::new(2);
+    assert_eq!(
+      Callbacks::input_state(&cb, port, RETRO_DEVICE_KEYBOARD, 0, 7),
+      2307
+    );
+    assert_eq!(
+      Callbacks::input_state(&cb, port, RETRO_DEVICE_ANALOG, 1, 1),
+      2511
+    );
+  }
+
+  static INPUT_QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn counting_input_state(
+    port: c_uint,
+    device: c_uint,
+    index: c_uint,
+    id: c_uint,
+  ) -> i16 {
+    INPUT_QUERIES.fetch_add(1, Ordering::SeqCst);
+    input_state(port, device, index, id)
+  }
+
+  /// What [`TestCore`] does in [`Core::run`], besides polling inputs.
+  #[derive(Default)]
+  enum OnRun {
+    #[default]
+    Poll,
+    /// Asserts that exactly two joypad buttons are pressed on port 0.
+    CountButtons,
+    /// Issues a command that may only be issued from `retro_run`.
+    SetLatency,
+    /// Checks the capabilities reported by [`caps_environment`].
+    CheckCaps,
+    /// Asks the frontend to shut down.
+    Shutdown,
+    /// Draws a frame and sends audio with [`RunFrame`].
+    DrawFrame(Framebuffer<XRGB8888>),
+    /// Polls for packets with the [`NetPacketSender`] given to [`NetPacketCore::start`].
+    PollPackets,
+  }
+
+  /// The core behind the [`Instance`] tests. Each test configures the behavior it checks, and
+  /// inspects what the instance passed to the core. `NO_GAME` sets [`Core::SUPPORT_NO_GAME`].
+  ///
+  /// The init state is the error `load_without_content` fails with, if any.
+  #[derive(Default)]
+  struct TestCore<const NO_GAME: bool = false> {
+    on_run: OnRun,
+    /// Panics in [`Core::run`] and [`SaveStateCore::serialize_size`].
+    panics: bool,
+    /// Exposed with [`Core::achievement_ram`].
+    ram: [u8; 4],
+    region: Region,
+    /// Reported by [`SaveStateCore::serialize_size`].
+    state_size: usize,
+    /// The length of the buffer passed to the last [`SaveStateCore::serialize`].
+    serialized_len: Cell<usize>,
+    /// The occupancy passed to the last [`AudioBufferStatusCore::audio_buffer_status`].
+    occupancy: u32,
+    /// The number of calls to [`Core::on_geometry_query`].
+    geometry_queries: usize,
+    /// Set by [`Core::on_shutdown_requested`].
+    flushed: bool,
+    /// The enabled cheat codes.
+    codes: Vec<String>,
+    /// The codes enabled on every call to [`Core::cheat_apply`].
+    applied: Vec<Vec<String>>,
+    /// The size of every game passed to [`SpecialGameCore::load_game`].
+    game_sizes: Vec<usize>,
+    /// The sender passed to [`NetPacketCore::start`].
+    sender: Option<NetPacketSender>,
+    /// The packets passed to [`NetPacketCore::receive`], and the client they came from.
+    packets: Vec<(Vec<u8>, u16)>,
+    /// Set by [`NetPacketCore::stop`].
+    stopped: bool,
+  }
+
+  impl<'a, const NO_GAME: bool> Core<'a> for TestCore<NO_GAME> {
+    type Init = Option<LoadError>;
+    const SUPPORT_NO_GAME: bool = NO_GAME;
+
+    fn get_system_info() -> SystemInfo {
+      unimplemented!()
+    }
+
+    fn set_environment(env: &mut impl env::SetEnvironment) {
+      if NO_GAME {
+        let _ = env.set_support_no_game(false);
+      }
+    }
+
+    fn init(_env: &mut impl env::Init) -> Self::Init {
+      None
+    }
+
+    fn load_without_content<E: env::LoadGame>(
+      args: LoadGameExtraArgs<'a, '_, E, Self::Init>,
+    ) -> Result<Self, CoreError> {
+      if let Some(error) = *args.init_state {
+        return Err(error.into());
+      }
+      args.env.set_pixel_format_xrgb8888(args.pixel_format)?;
+      Ok(Self::default())
+    }
+
+    fn get_system_av_info(&self, env: &mut impl env::GetAvInfo) -> SystemAVInfo {
+      let key = CStr::from_bytes_with_nul(b"internal_resolution\0").unwrap();
+      let scale = match env.get_variable(&key) {
+        Ok(Some(value)) if value.to_bytes() == b"2x" => 2,
+        _ => 1,
+      };
+      SystemAVInfo::default_timings(GameGeometry::fixed(64 * scale, 32 * scale))
+    }
+
+    fn on_geometry_query(&mut self, _env: &mut impl env::GetAvInfo) {
+      self.geometry_queries += 1;
+    }
+
+    fn run(&mut self, env: &mut impl env::Run, callbacks: &mut impl Callbacks) -> InputsPolled {
+      assert!(!self.panics, "emulation error");
+      match &mut self.on_run {
+        OnRun::Poll => {}
+        OnRun::CountButtons => {
+          let port = DevicePort::new(0);
+          let pressed = JoypadButton::ALL
+            .into_iter()
+            .filter(|&btn| callbacks.is_joypad_button_pressed(port, btn))
+            .count();
+          assert_eq!(pressed, 2);
+        }
+        OnRun::SetLatency => {
+          let _ = env.set_minimum_audio_latency(64);
+        }
+        OnRun::CheckCaps => {
+          for _ in 0..3 {
+            let caps = callbacks.capabilities();
+            assert!(caps.bitmasks && caps.fast_forwarding && !caps.audio_enabled);
+          }
+          let ports: Vec<_> = callbacks.active_ports().map(c_uint::from).collect();
+          assert_eq!(ports, [0, 1, 2]);
+        }
+        OnRun::Shutdown => {
+          let _ = env.shutdown();
+        }
+        OnRun::DrawFrame(framebuffer) => {
+          let mut frame = RunFrame::new(
+            callbacks,
+            &SoftwareRenderEnabled(()),
+            &Format::<XRGB8888>(PhantomData),
+            framebuffer,
+          );
+          let buttons = frame.joypad(DevicePort::new(0));
+          let color = if buttons.contains(JoypadButton::A) { 0xFF0000 } else { 0x0000FF };
+          frame
+            .framebuffer()
+            .fill(XRGB8888::new_with_raw_value(color));
+          for i in 0..3 {
+            frame.push_sample(i, -i);
+          }
+          frame.push_audio(&[7, -7]);
+          return frame.finish();
+        }
+        OnRun::PollPackets => {
+          let sender = self.sender.as_ref().unwrap();
+          sender.poll_receive();
+          assert!(self.packets.is_empty() && !self.stopped);
+          assert!(!sender.is_active());
+        }
+      }
+      callbacks.poll_inputs()
+    }
+
+    fn on_shutdown_requested(&mut self, _env: &mut impl env::Run) {
+      self.flushed = true;
+    }
+
+    fn cheat_apply(&mut self, _env: &mut impl env::CheatSet) {
+      self.applied.push(self.codes.clone());
+    }
+
+    fn reset(&mut self, _env: &mut impl env::Reset) {}
+
+    fn unload_game(self, _env: &mut impl env::UnloadGame) -> Self::Init {
+      None
+    }
+
+    fn achievement_ram(&mut self) -> Option<&mut [u8]> {
+      Some(&mut self.ram)
+    }
+  }
+
+  impl<'a> SaveStateCore<'a> for TestCore {
+    fn serialize_size(&self, _env: &mut impl env::SerializeSize) -> core::num::NonZeroUsize {
+      assert!(!self.panics, "no state");
+      core::num::NonZeroUsize::new(self.state_size).unwrap()
+    }
+
+    fn serialize(&self, _env: &mut impl env::Serialize, data: &mut [u8]) -> Result<(), CoreError> {
+      self.serialized_len.set(data.len());
+      Ok(())
+    }
+
+    fn unserialize(
+      &mut self,
+      _env: &mut impl env::Unserialize,
+      _data: &[u8],
+    ) -> Result<(), CoreError> {
+      Ok(())
+    }
+  }
+
+  impl<'a> AudioBufferStatusCore<'a> for TestCore {
+    fn audio_buffer_status(
+      &mut self,
+      env: &mut impl Environment,
+      _: bool,
+      occupancy: u32,
+      _: bool,
+    ) {
+      self.occupancy = occupancy;
+      let _ = env::Run::set_minimum_audio_latency(env, 64);
+    }
+  }
+
+  impl<'a> NetPacketCore<'a> for TestCore {
+    fn start(&mut self, _env: &mut impl Environment, _client_id: u16, sender: NetPacketSender) {
+      self.sender = Some(sender);
+    }
+
+    fn receive(&mut self, _env: &mut impl Environment, buf: &[u8], client_id: u16) {
+      self.packets.push((buf.to_vec(), client_id));
+    }
+
+    fn stop(&mut self, _env: &mut impl Environment) {
+      self.stopped = true;
+    }
+  }
+
+  impl<'a> RegionAwareCore<'a> for TestCore {
+    fn get_region(&self, _env: &mut impl env::GetRegion) -> Region {
+      self.region
+    }
+  }
+
+  impl<'a> CheatsCore<'a> for TestCore {
+    fn cheat_set(
+      &mut self,
+      _env: &mut impl env::CheatSet,
+      _index: c_uint,
+      _enabled: bool,
+      code: &CStr,
+    ) -> Result<(), CoreError> {
+      self.codes.push(code.to_string_lossy().into_owned());
+      Ok(())
+    }
+
+    fn cheat_reset(&mut self, _env: &mut impl env::CheatReset) {
+      self.codes.clear();
+    }
+  }
+
+  impl<'a> SpecialGameCore<'a> for TestCore {
+    fn load_game<'b>(
+      _env: &mut impl env::LoadGameSpecial,
+      _init_state: &'a mut Self::Init,
+      _game_type: GameType,
+      games: &[SpecialGameInfo],
+    ) -> Result<Self, LoadGameError<Self::Init>> {
+      let sizes = games
+        .iter()
+        .map(|game| game.as_data().map_or(0, |data| data.data().len()));
+      Ok(TestCore {
+        game_sizes: sizes.collect(),
+        ..TestCore::default()
+      })
+    }
+  }
+
+  #[test]
+  fn test_button_reads_use_cached_bitmask() {
+    let mock = MockEnvironment::new();
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::CountButtons,
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(mock.get_ptr());
+    instance.cb.input_poll = Some(noop_input_poll);
+    instance.cb.input_state = Some(counting_input_state);
+    unsafe {
+      instance.on_run();
+      assert_eq!(INPUT_QUERIES.load(Ordering::SeqCst), 1);
+      instance.on_run();
+      assert_eq!(INPUT_QUERIES.load(Ordering::SeqCst), 2);
+    }
+  }
+
+  unsafe extern "C" fn noop() {}
+  unsafe extern "C" fn noop_start(
+    _: u16,
+    _: retro_netpacket_send_t,
+    _: retro_netpacket_poll_receive_t,
+  ) {
+  }
+  unsafe extern "C" fn noop_receive(_: *const c_void, _: usize, _: u16) {}
+  unsafe extern "C" fn noop_connected(_: u16) -> bool {
+    true
+  }
+  unsafe extern "C" fn noop_disconnected(_: u16) {}
+  unsafe extern "C" fn noop_audio_buffer_status(_: bool, _: c_uint, _: bool) {}
+
+  unsafe extern "C" fn noop_send(_: c_int, _: *const c_void, _: usize, _: u16) {}
+
+  /// Delivers a packet and stops the session, the way the `libretro_core` wrappers forward them.
+  unsafe extern "C" fn reentrant_poll_receive() {
+    assert!(defer_netpacket_receive(b"hi".as_ptr().cast(), 2, 3));
+    assert!(defer_netpacket_stop());
+  }
+
+  fn new_instance<I, C>() -> Instance<I, C> {
+    Instance::new(noop, noop)
+      .with_netpacket(InstanceNetPacketState::new(
+        noop_start,
+        noop_receive,
+        noop,
+        noop,
+        noop_connected,
+        noop_disconnected,
+      ))
+      .with_audio_buffer_status(noop_audio_buffer_status)
+  }
+
+  type TestInstance<const NO_GAME: bool = false> = Instance<Option<LoadError>, TestCore<NO_GAME>>;
+
+  /// Creates an instance that loaded `core`, without going through `retro_load_game`.
+  fn loaded_instance(core: TestCore) -> TestInstance {
+    let mut instance = new_instance();
+    instance.env.cb = Some(reject_environment);
+    instance.init.write(None);
+    instance.initialized = true;
+    instance.core.write(core);
+    instance.game_loaded = true;
+    instance
+  }
+
+  #[test]
+  fn test_netpacket_polled_during_run_is_deferred() {
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::PollPackets,
+      ..TestCore::default()
+    });
+    instance.cb.input_poll = Some(noop_input_poll);
+    unsafe {
+      instance.on_netpacket_start(1, Some(noop_send), Some(reentrant_poll_receive));
+      instance.on_run();
+      instance.on_netpacket_deferred();
+      let core = instance.core.assume_init_ref();
+      assert_eq!(core.packets, [(b"hi".to_vec(), 3)]);
+      assert!(core.stopped);
+      assert!(!defer_netpacket_stop());
+    }
+  }
+
+  #[test]
+  fn test_netpacket_requires_loaded_game() {
+    let mut instance = loaded_instance(TestCore::default());
+    instance.game_loaded = false;
+    unsafe {
+      instance.on_netpacket_receive(b"hi".as_ptr().cast(), 2, 3);
+      assert!(!instance.on_netpacket_connected(3));
+      assert!(instance.core.assume_init_ref().packets.is_empty());
+    }
+  }
+
+  #[test]
+  fn test_achievement_ram_is_system_ram() {
+    let mut instance = loaded_instance(TestCore {
+      ram: [1, 2, 3, 4],
+      ..TestCore::default()
+    });
+    let system_ram = StandardMemoryType::SystemRam.into();
+    unsafe {
+      let data = instance.on_get_memory_data(system_ram) as *const u8;
+      assert_eq!(data, instance.core.assume_init_ref().ram.as_ptr());
+      assert_eq!(instance.on_get_memory_size(system_ram), 4);
+      let save_ram = StandardMemoryType::SaveRam.into();
+      assert!(instance.on_get_memory_data(save_ram).is_null());
+      assert_eq!(instance.on_get_memory_size(save_ram), 0);
+    }
+  }
+
+  #[test]
+  fn test_achievement_ram_requires_loaded_game() {
+    let mock = MockEnvironment::new();
+    let mut instance: TestInstance = new_instance();
+    instance.on_set_environment(mock.get_ptr());
+    let system_ram = StandardMemoryType::SystemRam.into();
+    unsafe {
+      instance.on_init();
+      assert!(instance.on_get_memory_data(system_ram).is_null());
+      assert_eq!(instance.on_get_memory_size(system_ram), 0);
+      assert!(instance.on_load_game(core::ptr::null()));
+      assert_eq!(instance.on_get_memory_size(system_ram), 4);
+      instance.on_unload_game();
+      assert!(instance.on_get_memory_data(system_ram).is_null());
+      assert_eq!(instance.on_get_memory_size(system_ram), 0);
+    }
+  }
+
+  #[test]
+  fn test_entry_points_require_loaded_game() {
+    let mock = MockEnvironment::new();
+    let mut instance: TestInstance = new_instance();
+    instance.on_set_environment(mock.get_ptr());
+    let mut av_info = retro_system_av_info::default();
+    unsafe {
+      assert!(!instance.on_load_game_special(GameType::new(1), core::ptr::null(), 0));
+      instance.on_init();
+      instance.on_get_system_av_info(&mut av_info);
+      assert_eq!(av_info.timing.fps, 0.0);
+      assert_eq!(instance.on_serialize_size(), 0);
+      let mut state = [0u8; 4];
+      assert!(!instance.on_serialize(state.as_mut_ptr().cast(), state.len()));
+      assert!(!instance.on_unserialize(state.as_ptr().cast(), state.len()));
+      instance.on_cheat_reset();
+      assert!(!instance.cheats_changed);
+      assert_eq!(instance.on_get_region(), RETRO_REGION_NTSC);
+      let port = DevicePort::new(0);
+      let mouse = DeviceTypeId::new(RETRO_DEVICE_MOUSE);
+      instance.on_set_controller_port_device(port, mouse);
+      assert_eq!(instance.device_for_port(port), mouse);
+      assert!(instance.on_load_game(core::ptr::null()));
+      instance.on_cheat_reset();
+      assert!(instance.cheats_changed);
+    }
+  }
+
+  static REGISTERED_STATUS_CALLBACK: Mutex<retro_audio_buffer_status_callback_t> = Mutex::new(None);
+
+  unsafe extern "C" fn status_callback_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd == RETRO_ENVIRONMENT_SET_AUDIO_BUFFER_STATUS_CALLBACK {
+      let data = (data as *const retro_audio_buffer_status_callback).as_ref();
+      *REGISTERED_STATUS_CALLBACK.lock().unwrap() = data.and_then(|data| data.callback);
+    }
+    true
+  }
+
+  #[test]
+  fn test_audio_buffer_status_callback() {
+    let mut instance = TestInstance::new(noop, noop);
+    instance.env.cb = Some(status_callback_environment);
+    assert!(env::LoadGame::set_audio_buffer_status_callback(&mut instance.env).is_err());
+    assert!(REGISTERED_STATUS_CALLBACK.lock().unwrap().is_none());
+
+    let mut instance = instance.with_audio_buffer_status(noop_audio_buffer_status);
+    assert!(env::LoadGame::set_audio_buffer_status_callback(&mut instance.env).is_ok());
+    let registered = REGISTERED_STATUS_CALLBACK.lock().unwrap().unwrap();
+    assert_eq!(registered as usize, noop_audio_buffer_status as usize);
+
+    instance.init.write(None);
+    instance.initialized = true;
+    instance.core.write(TestCore::default());
+    unsafe { instance.on_audio_buffer_status(true, 75, false) };
+    assert_eq!(unsafe { instance.core.assume_init_ref() }.occupancy, 0);
+
+    instance.game_loaded = true;
+    unsafe { instance.on_audio_buffer_status(true, 75, false) };
+    assert_eq!(unsafe { instance.core.assume_init_ref() }.occupancy, 75);
+
+    unsafe { instance.on_unload_game() };
+    assert!(REGISTERED_STATUS_CALLBACK.lock().unwrap().is_none());
+  }
+
+  unsafe extern "C" fn accept_environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+    true
+  }
+
+  unsafe extern "C" fn noop_input_poll() {}
+
+  #[test]
+  fn test_run_only_command_outside_run() {
+    let mock = MockEnvironment::new();
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::SetLatency,
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(mock.get_ptr());
+    instance.cb.input_poll = Some(noop_input_poll);
+    let run = |instance: &mut TestInstance| {
+      instance.catch_panic("retro_run", (), |instance| unsafe { instance.on_run() })
+    };
+    let audio_buffer_status = |instance: &mut TestInstance| {
+      instance.catch_panic("on_audio_buffer_status", (), |instance| unsafe {
+        instance.on_audio_buffer_status(true, 50, false)
+      })
+    };
+    run(&mut instance);
+    assert_eq!(instance.env.run_only_violations, 0);
+    audio_buffer_status(&mut instance);
+    let debug = cfg!(debug_assertions) as c_uint;
+    assert_eq!(instance.env.run_only_violations, debug);
+
+    // A callback from inside retro_run isn't retro_run itself.
+    instance.catch_panic("retro_run", (), |instance| audio_buffer_status(instance));
+    assert_eq!(instance.env.run_only_violations, 2 * debug);
+    assert_eq!(instance.env.nested_api_calls, 0);
+
+    instance.catch_panic("retro_serialize", (), |instance| run(instance));
+    assert_eq!(instance.env.nested_api_calls, debug);
+    assert_eq!(instance.env.entry_point, None);
+  }
+
+  #[test]
+  fn test_av_info_reads_options() {
+    let mut mock = MockEnvironment::new().with_variable("internal_resolution", "2x");
+    let mut instance = loaded_instance(TestCore::default());
+    let mut query = |mock: &MockEnvironment| {
+      instance.env.cb = Some(mock.get_ptr());
+      let mut info = retro_system_av_info::default();
+      unsafe { instance.on_get_system_av_info(&mut info) };
+      let geometry = (info.geometry.base_width, info.geometry.base_height);
+      let queries = unsafe { instance.core.assume_init_ref() }.geometry_queries;
+      (geometry, queries)
+    };
+    assert_eq!(
+      query(&mock),
+      ((128, 64), 0),
+      "the first query comes right after loading"
+    );
+    assert_eq!(query(&mock), ((128, 64), 1));
+    mock.update_variable("internal_resolution", "1x");
+    assert_eq!(query(&mock), ((64, 32), 2));
+  }
+
+  unsafe extern "C" fn noop_video_refresh(_: *const c_void, _: c_uint, _: c_uint, _: usize) {}
+  unsafe extern "C" fn noop_audio_sample(_: i16, _: i16) {}
+  unsafe extern "C" fn noop_audio_sample_batch(_: *const i16, frames: usize) -> usize {
+    frames
+  }
+
+  #[test]
+  fn test_instance_errors() {
+    use InstanceError::*;
+    let mock = MockEnvironment::new();
+    let mut instance: TestInstance = new_instance();
+    let no_game = core::ptr::null();
+    unsafe {
+      assert_eq!(instance.try_run(), Err(MissingEnvironment));
+      assert_eq!(instance.try_load_game(no_game), Err(MissingEnvironment));
+      instance.env.cb = Some(mock.get_ptr());
+      assert_eq!(instance.try_load_game(no_game), Err(CalledOutOfOrder));
+      assert_eq!(instance.try_reset(), Err(NoSystemLoaded));
+      instance.on_init();
+      assert_eq!(instance.try_run(), Err(NoSystemLoaded));
+      assert_eq!(instance.try_load_game(no_game), Ok(true));
+      assert_eq!(instance.try_load_game(no_game), Err(CalledOutOfOrder));
+
+      let missing = |kind| Err(MissingCallback(kind));
+      assert_eq!(instance.try_run(), missing(CallbackKind::VideoRefresh));
+      instance.on_set_video_refresh(noop_video_refresh);
+      instance.on_set_audio_sample(noop_audio_sample);
+      instance.on_set_audio_sample_batch(noop_audio_sample_batch);
+      assert_eq!(instance.try_run(), missing(CallbackKind::InputPoll));
+      instance.on_set_input_poll(noop_input_poll);
+      instance.on_set_input_state(input_state);
+      assert_eq!(instance.try_run(), Ok(()));
+      assert_eq!(instance.try_reset(), Ok(()));
+
+      assert_eq!(instance.try_unload_game(), Ok(()));
+      assert_eq!(instance.try_unload_game(), Err(NoSystemLoaded));
+      instance.on_deinit();
+      assert_eq!(instance.try_load_game(no_game), Err(CalledOutOfOrder));
+    }
+  }
+
+  #[test]
+  fn test_commands_fail_without_environment() {
+    let mut instance: TestInstance = new_instance();
+    assert!(instance.env.get_log_interface().is_err());
+    assert_eq!(instance.env.get_input_max_users(), None);
+    instance.log_error(InstanceError::MissingEnvironment);
+    unsafe { instance.on_init() };
+    assert!(instance.initialized);
+  }
+
+  #[cfg(not(feature = "panic-abort"))]
+  #[test]
+  fn test_catch_panic() {
+    let mut instance: TestInstance = new_instance();
+    let loaded = instance.catch_panic("retro_load_game", false, |instance| {
+      assert_eq!(instance.env.entry_point, Some("retro_load_game"));
+      panic!("corrupted rom")
+    });
+    assert!(!loaded);
+    assert_eq!(instance.env.entry_point, None);
+    assert!(instance.catch_panic("retro_load_game", false, |_| true));
+  }
+
+  #[cfg(not(feature = "panic-abort"))]
+  #[test]
+  fn test_panicking_core_does_not_unwind() {
+    let mock = MockEnvironment::new();
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(mock.get_ptr());
+    instance.on_set_video_refresh(noop_video_refresh);
+    instance.on_set_audio_sample(noop_audio_sample);
+    instance.on_set_audio_sample_batch(noop_audio_sample_batch);
+    instance.on_set_input_poll(noop_input_poll);
+    instance.on_set_input_state(input_state);
+    unsafe {
+      instance.on_init();
+      let no_game = core::ptr::null();
+      let loaded = instance.catch_panic("retro_load_game", false, |instance| {
+        instance.try_load_game(no_game).unwrap()
+      });
+      assert!(loaded);
+      instance.core.assume_init_mut().panics = true;
+      instance.catch_panic("retro_run", (), |instance| instance.try_run().unwrap());
+      assert_eq!(instance.env.entry_point, None);
+      let size = instance.catch_panic("retro_serialize_size", 0, |instance| {
+        instance.on_serialize_size()
+      });
+      assert_eq!(size, 0);
+    }
+  }
+
+  static RUN_FRAME_VIDEO: Mutex<Vec<(c_uint, c_uint, u32)>> = Mutex::new(Vec::new());
+  static RUN_FRAME_AUDIO: Mutex<Vec<i16>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn run_frame_video(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    _pitch: usize,
+  ) {
+    let first = *(data as *const u32);
+    RUN_FRAME_VIDEO.lock().unwrap().push((width, height, first));
+  }
+
+  unsafe extern "C" fn run_frame_audio(data: *const i16, frames: usize) -> usize {
+    // Accept at most 2 frames per call, to check that the rest is sent again.
+    let frames = frames.min(2);
+    let samples = slice::from_raw_parts(data, frames * 2);
+    RUN_FRAME_AUDIO.lock().unwrap().extend_from_slice(samples);
+    frames
+  }
+
+  static MONO_AUDIO: Mutex<Vec<i16>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn mono_audio(data: *const i16, frames: usize) -> usize {
+    let samples = slice::from_raw_parts(data, frames * 2);
+    MONO_AUDIO.lock().unwrap().extend_from_slice(samples);
+    frames
+  }
+
+  #[test]
+  fn test_upload_audio_frame_mono() {
+    let mut callbacks = InstanceCallbacks::new();
+    callbacks.audio_sample_batch = Some(mono_audio);
+    assert_eq!(
+      Callbacks::upload_audio_frame_mono(&mut callbacks, &[1, -2, 3]),
+      3
+    );
+    assert_eq!(*MONO_AUDIO.lock().unwrap(), [1, 1, -2, -2, 3, 3]);
+
+    let samples: Vec<i16> = (0..1200).collect();
+    assert_eq!(
+      Callbacks::upload_audio_frame_mono(&mut callbacks, &samples),
+      1200
+    );
+    let audio = MONO_AUDIO.lock().unwrap();
+    let expected = samples.iter().flat_map(|&sample| [sample, sample]);
+    assert!(audio[6..].iter().copied().eq(expected));
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "interleaved stereo")]
+  fn test_upload_audio_frame_odd_length() {
+    let mut callbacks = InstanceCallbacks::new();
+    callbacks.audio_sample_batch = Some(noop_audio_sample_batch);
+    Callbacks::upload_audio_frame(&mut callbacks, &[1, 2, 3]);
+  }
+
+  #[test]
+  fn test_run_frame_flushes_video_and_audio() {
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::DrawFrame(Framebuffer::new(4, 2, 1)),
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(reject_environment);
+    instance.cb.input_poll = Some(noop_input_poll);
+    instance.cb.input_state = Some(input_state);
+    instance.cb.video_refresh = Some(run_frame_video);
+    instance.cb.audio_sample_batch = Some(run_frame_audio);
+    unsafe { instance.on_run() };
+    assert_eq!(*RUN_FRAME_VIDEO.lock().unwrap(), [(4, 2, 0xFF0000)]);
+    assert_eq!(
+      *RUN_FRAME_AUDIO.lock().unwrap(),
+      [0, 0, 1, -1, 2, -2, 7, -7]
+    );
+  }
+
+  #[test]
+  fn test_run_frame_dropped_while_panicking_sends_nothing() {
+    let mut callbacks = testing::MockCallbacks::new();
+    let mut framebuffer = Framebuffer::new(4, 2, 1);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+      let mut frame = RunFrame::new(
+        &mut callbacks,
+        &SoftwareRenderEnabled(()),
+        &Format::<XRGB8888>(PhantomData),
+        &mut framebuffer,
+      );
+      frame.push_sample(1, -1);
+      panic!("emulation failed");
+    }));
+    assert!(result.is_err());
+    assert!(callbacks.frames.is_empty() && callbacks.audio.is_empty());
+  }
+
+  #[test]
+  #[cfg(feature = "thread-safe")]
+  fn test_nested_api_calls_are_rejected() {
+    let value = serialize_api_call("retro_run", || serialize_api_call("retro_reset", || 42));
+    assert_eq!(value, Some(None));
+    assert_eq!(serialize_api_call("retro_reset", || 42), Some(42));
+  }
+
+  #[cfg(feature = "thread-safe")]
+  #[test]
+  fn test_api_calls_are_serialized() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    let threads: Vec<_> = (0..4)
+      .map(|_| {
+        std::thread::spawn(|| {
+          for _ in 0..1000 {
+            serialize_api_call("retro_run", || {
+              // Not an atomic increment, so concurrent calls would lose updates.
+              let calls = CALLS.load(Ordering::Relaxed);
+              std::thread::yield_now();
+              CALLS.store(calls + 1, Ordering::Relaxed);
+            });
+          }
+        })
+      })
+      .collect();
+    for thread in threads {
+      thread.join().unwrap();
+    }
+    assert_eq!(CALLS.load(Ordering::SeqCst), 4000);
+  }
+
+  #[test]
+  fn test_region_follows_reported_fps() {
+    let mut instance: TestInstance<true> = new_instance();
+    instance.env.cb = Some(reject_environment);
+    instance.init.write(None);
+    instance.initialized = true;
+    instance.core.write(TestCore::default());
+    instance.game_loaded = true;
+    let mut info = retro_system_av_info::default();
+    unsafe {
+      instance.on_get_system_av_info(&mut info);
+      assert_eq!(instance.fps, Some(info.timing.fps));
+      assert_eq!(instance.on_get_region(), RETRO_REGION_NTSC);
+      instance.fps = Some(50.0);
+      assert_eq!(instance.on_get_region(), RETRO_REGION_PAL);
+      instance.on_unload_game();
+      assert_eq!(instance.fps, None);
+    }
+  }
+
+  #[test]
+  fn test_region_is_queried_on_every_call() {
+    let mut instance = loaded_instance(TestCore::default());
+    unsafe {
+      assert_eq!(instance.on_get_region(), RETRO_REGION_NTSC);
+      instance.core.assume_init_mut().region = Region::PAL;
+      assert_eq!(instance.on_get_region(), RETRO_REGION_PAL);
+    }
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "serialize_size grew")]
+  fn test_growing_serialize_size_is_caught() {
+    let mut instance = loaded_instance(TestCore {
+      state_size: 8,
+      ..TestCore::default()
+    });
+    let mut buffer = [0u8; 16];
+    let data = buffer.as_mut_ptr() as *mut ();
+    unsafe {
+      assert_eq!(instance.on_serialize_size(), 8);
+      instance.core.assume_init_mut().state_size = 4;
+      assert!(instance.on_serialize(data, 8), "shrinking is allowed");
+      instance.core.assume_init_mut().state_size = 12;
+      instance.on_serialize(data, 8);
+    }
+  }
+
+  #[test]
+  fn test_serialize_buffer_is_clamped_to_size() {
+    let mut instance = loaded_instance(TestCore {
+      state_size: 8,
+      ..TestCore::default()
+    });
+    let mut buffer = [0u8; 16];
+    unsafe {
+      assert_eq!(instance.on_serialize_size(), 8);
+      assert!(instance.on_serialize(buffer.as_mut_ptr() as *mut (), 16));
+      assert_eq!(instance.core.assume_init_ref().serialized_len.get(), 8);
+    }
+  }
+
+  #[test]
+  fn test_undersized_serialize_buffer() {
+    let mut instance = loaded_instance(TestCore {
+      state_size: 8,
+      ..TestCore::default()
+    });
+    let mut buffer = [0u8; 4];
+    unsafe {
+      assert_eq!(instance.on_serialize_size(), 8);
+      assert!(!instance.on_serialize(buffer.as_mut_ptr() as *mut (), 4));
+      assert_eq!(instance.core.assume_init_ref().serialized_len.get(), 0);
+    }
+  }
+
+  #[test]
+  fn test_variable_serialize_size() {
+    let mut instance = loaded_instance(TestCore {
+      state_size: 4,
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(accept_environment);
+    let mut buffer = [0u8; 16];
+    let data = buffer.as_mut_ptr() as *mut ();
+    let set_size = |instance: &mut TestInstance, size| unsafe {
+      instance.core.assume_init_mut().state_size = size;
+    };
+    let serialized_len =
+      |instance: &TestInstance| unsafe { instance.core.assume_init_ref().serialized_len.get() };
+    unsafe {
+      assert_eq!(instance.on_serialize_size(), 4);
+      set_size(&mut instance, 8);
+      assert_eq!(
+        instance.on_serialize_size(),
+        4,
+        "the size is fixed by default"
+      );
+
+      let quirks = env::SerializationQuirks::CORE_VARIABLE_SIZE;
+      assert!(instance.env.set_serialization_quirks(quirks).is_ok());
+      assert_eq!(instance.on_serialize_size(), 8);
+      set_size(&mut instance, 12);
+      assert!(instance.on_serialize(data, 16));
+      assert_eq!(serialized_len(&instance), 12);
+      set_size(&mut instance, 6);
+      assert_eq!(instance.on_serialize_size(), 6);
+      assert!(instance.on_serialize(data, 6));
+      assert_eq!(serialized_len(&instance), 6);
+      set_size(&mut instance, 32);
+      assert!(!instance.on_serialize(data, 16), "the state no longer fits");
+    }
+  }
+
+  #[test]
+  fn test_device_for_port() {
+    let mut instance: TestInstance = new_instance();
+    let (port0, port1) = (DevicePort::new(0), DevicePort::new(1));
+    let mouse = DeviceTypeId::new(RETRO_DEVICE_MOUSE);
+    let lightgun = DeviceTypeId::subclass(DeviceType::LightGun, 1);
+    assert_eq!(
+      instance.device_for_port(port1),
+      DeviceTypeId::new(RETRO_DEVICE_JOYPAD)
+    );
+    unsafe {
+      instance.on_set_controller_port_device(port1, mouse);
+      instance.on_set_controller_port_device(port0, lightgun);
+      instance.on_set_controller_port_device(port1, lightgun);
+      instance.on_set_controller_port_device(DevicePort::new(256), mouse);
+    }
+    assert_eq!(instance.device_for_port(port0), lightgun);
+    assert_eq!(instance.device_for_port(port1), lightgun);
+    assert_eq!(instance.device_for_port(DevicePort::new(256)), mouse);
+    assert_eq!(instance.cb.port_devices.len(), 3);
+  }
+
+  static MASK: AtomicU16 = AtomicU16::new(0);
+
+  unsafe extern "C" fn mask_input_state(_: c_uint, _: c_uint, _: c_uint, id: c_uint) -> i16 {
+    match id {
+      RETRO_DEVICE_ID_JOYPAD_MASK => MASK.load(Ordering::SeqCst) as i16,
+      _ => 0,
+    }
+  }
+
+  #[test]
+  fn test_joypad_cache_invalidated_on_device_change() {
+    let mut instance: TestInstance = new_instance();
+    instance.cb.input_state = Some(mask_input_state);
+    instance.cb.caps.bitmasks = true;
+    let (port0, port1) = (DevicePort::new(0), DevicePort::new(1));
+    MASK.store(1 << JoypadButton::A as u16, Ordering::SeqCst);
+    unsafe {
+      assert!(instance.cb.joypad_bitmask(port0).contains(JoypadButton::A));
+      assert!(instance.cb.joypad_bitmask(port1).contains(JoypadButton::A));
+      MASK.store(1 << JoypadButton::B as u16, Ordering::SeqCst);
+      assert!(instance.cb.joypad_bitmask(port0).contains(JoypadButton::A));
+      instance.on_set_controller_port_device(port0, DeviceType::Mouse.into());
+      assert!(instance.cb.joypad_bitmask(port0).contains(JoypadButton::B));
+      assert!(instance.cb.joypad_bitmask(port1).contains(JoypadButton::A));
+    }
+  }
+
+  #[test]
+  fn test_port_device_validation() {
+    let subclass = DeviceTypeId::subclass(DeviceType::Joypad, DeviceTypeId::MAX_SUBCLASS_INDEX);
+    assert_eq!(port_device_warning(DevicePort::new(255), subclass), None);
+    assert!(port_device_warning(DevicePort::new(256), subclass).is_some());
+    let unknown = DeviceTypeId::new(0x1FF);
+    assert!(port_device_warning(DevicePort::new(0), unknown).is_some());
+
+    let mut instance: TestInstance = new_instance();
+    unsafe { instance.on_set_controller_port_device(DevicePort::new(0), unknown) };
+    assert_eq!(instance.device_for_port(DevicePort::new(0)), unknown);
+  }
+
+  #[test]
+  fn test_hw_frame_uses_sentinel() {
+    let mut cb = InstanceCallbacks::new();
+    cb.video_refresh = Some(record_frame);
+    cb.hw_context_ready = true;
+    unsafe { cb.use_hardware_frame_buffer(&GLRenderEnabled(()), 640, 480) };
+    assert_eq!(
+      *LAST_FRAME.lock().unwrap(),
+      Some((RETRO_HW_FRAME_BUFFER_VALID as usize, 640, 480, 0))
+    );
+  }
+
+  unsafe extern "C" fn record_dupe(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+  ) {
+    *DUPED_FRAME.lock().unwrap() = Some((data as usize, width, height, pitch));
+  }
+
+  #[test]
+  fn test_dup_video_frame_passes_null() {
+    let mut cb = InstanceCallbacks::new();
+    cb.video_refresh = Some(record_dupe);
+    Callbacks::dup_video_frame(&mut cb, 256, 224);
+    assert_eq!(*DUPED_FRAME.lock().unwrap(), Some((0, 256, 224, 0)));
+  }
+
+  unsafe extern "C" fn mixed_input_state(
+    _port: c_uint,
+    device: c_uint,
+    _index: c_uint,
+    id: c_uint,
+  ) -> i16 {
+    match (device, id) {
+      (RETRO_DEVICE_POINTER, RETRO_DEVICE_ID_POINTER_X) => 100,
+      (RETRO_DEVICE_POINTER, RETRO_DEVICE_ID_POINTER_Y) => -50,
+      (RETRO_DEVICE_POINTER, RETRO_DEVICE_ID_POINTER_PRESSED) => 1,
+      (RETRO_DEVICE_MOUSE, RETRO_DEVICE_ID_MOUSE_X) => 3,
+      (RETRO_DEVICE_MOUSE, RETRO_DEVICE_ID_MOUSE_Y) => -4,
+      (RETRO_DEVICE_MOUSE, RETRO_DEVICE_ID_MOUSE_LEFT) => 1,
+      (RETRO_DEVICE_JOYPAD, RETRO_DEVICE_ID_JOYPAD_B) => 1,
+      _ => 0,
+    }
+  }
+
+  #[test]
+  fn test_read_device_mixed_ports() {
+    let mut cb = InstanceCallbacks::new();
+    cb.input_state = Some(mixed_input_state);
+    cb.set_device_for_port(DevicePort::new(0), DeviceType::Pointer.into());
+    cb.set_device_for_port(DevicePort::new(1), DeviceType::Mouse.into());
+    cb.set_device_for_port(DevicePort::new(3), DeviceType::Keyboard.into());
+
+    let pointer = PointerState {
+      x: 100,
+      y: -50,
+      pressed: true,
+    };
+    let mouse = MouseState {
+      x: 3,
+      y: -4,
+      left: true,
+      ..MouseState::default()
+    };
+    let read = |port| Callbacks::read_device(&cb, DevicePort::new(port));
+    let mut joypad = JoypadState::default();
+    joypad.set(JoypadButton::B, true);
+    assert_eq!(read(0), DeviceInput::Pointer(pointer));
+    assert_eq!(read(1), DeviceInput::Mouse(mouse));
+    assert_eq!(read(2), DeviceInput::Joypad(joypad));
+    assert_eq!(
+      read(3),
+      DeviceInput::Unsupported(DeviceType::Keyboard.into())
+    );
+  }
+
+  static FRAMEBUFFER: Mutex<[u8; 64]> = Mutex::new([0; 64]);
+  static UPLOADED_FRAMEBUFFER: Mutex<Option<(usize, c_uint, c_uint, usize)>> = Mutex::new(None);
+
+  unsafe extern "C" fn framebuffer_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_GET_CURRENT_SOFTWARE_FRAMEBUFFER => {
+        let framebuffer = &mut *(data as *mut retro_framebuffer);
+        assert_eq!(framebuffer.access_flags, RETRO_MEMORY_ACCESS_WRITE);
+        framebuffer.data = FRAMEBUFFER.lock().unwrap().as_mut_ptr() as *mut c_void;
+        framebuffer.pitch = 16;
+        framebuffer.format = retro_pixel_format::RETRO_PIXEL_FORMAT_RGB565;
+        framebuffer.memory_flags = RETRO_MEMORY_TYPE_CACHED;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  unsafe extern "C" fn record_framebuffer(
+    data: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+  ) {
+    *UPLOADED_FRAMEBUFFER.lock().unwrap() = Some((data as usize, width, height, pitch));
+  }
+
+  #[test]
+  fn test_software_framebuffer_round_trip() {
+    let mut env: non_null_retro_environment_t = framebuffer_environment;
+    let mut cb = InstanceCallbacks::new();
+    cb.video_refresh = Some(record_framebuffer);
+    let mut framebuffer =
+      env::Run::get_current_software_framebuffer(&mut env, 6, 4, FramebufferAccess::WRITE).unwrap();
+    assert_eq!(framebuffer.data().len(), 64);
+    assert_eq!(framebuffer.format(), PixelFormat::RGB565);
+    assert!(framebuffer.is_cached());
+    framebuffer.data_mut().fill(0xFF);
+    let ptr = framebuffer.data().as_ptr() as usize;
+    Callbacks::upload_software_framebuffer(&mut cb, &SoftwareRenderEnabled(()), framebuffer);
+    assert_eq!(*UPLOADED_FRAMEBUFFER.lock().unwrap(), Some((ptr, 6, 4, 16)));
+    assert!(FRAMEBUFFER.lock().unwrap().iter().all(|&b| b == 0xFF));
+  }
+
+  #[test]
+  #[cfg(feature = "serde")]
+  fn test_serde_json_round_trip() {
+    let av_info = SystemAVInfo::new(
+      GameGeometry::new(64..=128, 32..=64, 2.0),
+      SystemTiming::new(50.0, 48_000.0),
+    );
+    let json = serde_json::to_string(&av_info).unwrap();
+    let decoded: SystemAVInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.geometry().max_width(), 128);
+    assert_eq!(decoded.geometry().base_height(), 32);
+    assert_eq!(decoded.geometry().aspect_ratio(), 2.0);
+    assert_eq!(decoded.timing().fps(), 50.0);
+    assert_eq!(decoded.timing().sample_rate(), 48_000.0);
+    assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+
+    let name = CStr::from_bytes_with_nul(b"chip8\0").unwrap();
+    let version = CStr::from_bytes_with_nul(b"1.0\0").unwrap();
+    let extensions = CStr::from_bytes_with_nul(b"ch8|c8\0").unwrap();
+    let info = SystemInfo::new(name, version, Extensions::new(extensions)).with_need_full_path();
+    let json = serde_json::to_string(&info).unwrap();
+    let decoded: SystemInfo = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.library_name(), name);
+    assert_eq!(decoded.valid_extensions().as_c_str(), extensions);
+    assert!(decoded.need_fullpath());
+    assert!(!decoded.block_extract());
+
+    let format: PixelFormat = serde_json::from_str("\"RGB565\"").unwrap();
+    assert_eq!(format, PixelFormat::RGB565);
+  }
+
+  static CAPS_QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn caps_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    CAPS_QUERIES.fetch_add(1, Ordering::SeqCst);
+    match cmd {
+      RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE => *(data as *mut c_int) = 1,
+      RETRO_ENVIRONMENT_GET_INPUT_BITMASKS | RETRO_ENVIRONMENT_GET_FASTFORWARDING => {
+        *(data as *mut bool) = true
+      }
+      RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS => *(data as *mut c_uint) = 3,
+      _ => return false,
+    }
+    true
+  }
+
+  #[test]
+  fn test_capabilities_queried_once_per_run() {
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::CheckCaps,
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(caps_environment);
+    instance.cb.input_poll = Some(noop_input_poll);
+    unsafe {
+      instance.on_run();
+      assert_eq!(CAPS_QUERIES.load(Ordering::SeqCst), 5);
+      // Input bitmasks and frame duping are only queried on the first frame.
+      instance.on_run();
+      assert_eq!(CAPS_QUERIES.load(Ordering::SeqCst), 8);
+      assert!(instance.cb.has_input_bitmasks() && !instance.cb.can_dupe());
+    }
+  }
+
+  unsafe extern "C" fn reject_environment(_cmd: c_uint, _data: *mut c_void) -> bool {
+    false
+  }
+
+  #[test]
+  fn test_active_ports_default_to_one() {
+    let env: non_null_retro_environment_t = reject_environment;
+    let caps = RunCapabilities::query(&env);
+    assert_eq!(caps.max_users, 1);
+    assert_eq!(RunCapabilities::default().max_users, 1);
+  }
+
+  static SHARED_CONTEXT_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn shared_context_environment(cmd: c_uint, _data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_HW_SHARED_CONTEXT {
+      return false;
+    }
+    SHARED_CONTEXT_REQUESTS.fetch_add(1, Ordering::SeqCst);
+    true
+  }
+
+  #[test]
+  fn test_set_hw_shared_context() {
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(shared_context_environment);
+    assert!(env::LoadGame::set_hw_shared_context(&mut instance.env).is_ok());
+    assert_eq!(SHARED_CONTEXT_REQUESTS.load(Ordering::SeqCst), 1);
+    instance.env.cb = Some(reject_environment);
+    assert!(env::LoadGame::set_hw_shared_context(&mut instance.env).is_err());
+  }
+
+  unsafe extern "C" fn game_info_ext_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_GET_GAME_INFO_EXT {
+      return false;
+    }
+    let cue = retro_game_info_ext {
+      full_path: c_utf8!("/roms/game.cue").as_ptr(),
+      dir: c_utf8!("/roms").as_ptr(),
+      name: c_utf8!("game").as_ptr(),
+      ext: c_utf8!("cue").as_ptr(),
+      ..Default::default()
+    };
+    let data_bytes: &'static [u8] = &[1, 2, 3];
+    let bin = retro_game_info_ext {
+      data: data_bytes.as_ptr().cast(),
+      size: data_bytes.len(),
+      persistent_data: true,
+      ..Default::default()
+    };
+    let info = Box::leak(Box::new([cue, bin]));
+    *(data as *mut *const retro_game_info_ext) = info.as_ptr();
+    true
+  }
+
+  #[test]
+  fn test_get_game_info_ext() {
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(game_info_ext_environment);
+    instance.env.game_count = 2;
+    let info = env::LoadGame::get_game_info_ext(&instance.env).unwrap();
+    assert_eq!(info.len(), 2);
+    assert_eq!(info[0].full_path(), Some(c_utf8!("/roms/game.cue")));
+    assert_eq!(info[0].dir(), Some(c_utf8!("/roms")));
+    assert_eq!(info[0].ext(), Some(c_utf8!("cue")));
+    assert_eq!(info[0].data(), None);
+    assert_eq!(info[1].full_path(), None);
+    assert_eq!(info[1].data(), Some(&[1u8, 2, 3][..]));
+    assert!(info[1].persistent_data());
+
+    instance.env.cb = Some(reject_environment);
+    assert!(env::LoadGame::get_game_info_ext(&instance.env).is_none());
+  }
+
+  #[test]
+  fn test_on_shutdown_requested() {
+    let mut instance = loaded_instance(TestCore {
+      on_run: OnRun::Shutdown,
+      ..TestCore::default()
+    });
+    instance.env.cb = Some(reject_environment);
+    instance.cb.input_poll = Some(noop_input_poll);
+    unsafe {
+      instance.on_run();
+      assert!(!instance.core.assume_init_ref().flushed, "shutdown refused");
+      instance.env.cb = Some(accept_environment);
+      instance.on_run();
+      assert!(instance.core.assume_init_ref().flushed);
+    }
+    assert!(!instance.env.shutdown_requested);
+  }
+
+  #[test]
+  fn test_cheat_apply_once_per_batch() {
+    let mut instance = loaded_instance(TestCore::default());
+    instance.env.cb = Some(reject_environment);
+    instance.cb.input_poll = Some(noop_input_poll);
+    unsafe {
+      instance.on_run();
+      instance.on_cheat_reset();
+      instance.on_cheat_set(0, true, c_utf8!("AAAA-BBBB").as_ptr());
+      instance.on_cheat_set(1, true, c_utf8!("CCCC-DDDD").as_ptr());
+      instance.on_run();
+      instance.on_run();
+      instance.on_cheat_reset();
+      instance.on_run();
+      let applied = &instance.core.assume_init_ref().applied;
+      assert_eq!(applied.len(), 2);
+      assert_eq!(applied[0], ["AAAA-BBBB", "CCCC-DDDD"]);
+      assert!(applied[1].is_empty());
+    }
+  }
+
+  static ACHIEVEMENTS: Mutex<Vec<bool>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn achievements_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS {
+      return false;
+    }
+    ACHIEVEMENTS.lock().unwrap().push(*(data as *const bool));
+    true
+  }
+
+  #[test]
+  fn test_set_support_achievements() {
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(achievements_environment);
+    assert!(env::LoadGame::set_support_achievements(&mut instance.env, true).is_ok());
+    assert_eq!(*ACHIEVEMENTS.lock().unwrap(), [true]);
+    instance.env.cb = Some(reject_environment);
+    assert!(env::LoadGame::set_support_achievements(&mut instance.env, true).is_err());
+  }
+
+  static NO_GAME: Mutex<Vec<bool>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn no_game_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd == RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME {
+      NO_GAME.lock().unwrap().push(*(data as *const bool));
+    }
+    true
+  }
+
+  #[test]
+  fn test_support_no_game_overridden_at_runtime() {
+    new_instance::<_, TestCore>().on_set_environment(no_game_environment);
+    assert!(NO_GAME.lock().unwrap().is_empty());
+    new_instance::<_, TestCore<true>>().on_set_environment(no_game_environment);
+    assert_eq!(*NO_GAME.lock().unwrap(), [true, false]);
+  }
+
+  #[test]
+  fn test_load_game_special_sees_every_game() {
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(reject_environment);
+    instance.init.write(None);
+    instance.initialized = true;
+    let (bios, rom) = ([0u8; 4], [0u8; 16]);
+    let games = [
+      retro_game_info {
+        data: bios.as_ptr() as *const c_void,
+        size: bios.len(),
+        ..Default::default()
+      },
+      retro_game_info {
+        data: rom.as_ptr() as *const c_void,
+        size: rom.len(),
+        ..Default::default()
+      },
+    ];
+    unsafe {
+      assert!(instance.on_load_game_special(GameType::new(1), games.as_ptr(), games.len()));
+      assert_eq!(instance.core.assume_init_ref().game_sizes, [4, 16]);
+      instance.on_unload_game();
+      assert!(instance.on_load_game_special(GameType::new(1), core::ptr::null(), 0));
+      assert!(instance.core.assume_init_ref().game_sizes.is_empty());
+    }
+  }
+
+  static VARIABLE_UPDATED: AtomicBool = AtomicBool::new(false);
+  static VARIABLE_VALUE: Mutex<Option<CString>> = Mutex::new(None);
+
+  unsafe extern "C" fn variable_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_SET_VARIABLE => {
+        let variable = &*(data as *const retro_variable);
+        *VARIABLE_VALUE.lock().unwrap() = Some(CStr::from_ptr(variable.value).to_owned());
+        VARIABLE_UPDATED.store(true, Ordering::SeqCst)
+      }
+      RETRO_ENVIRONMENT_GET_VARIABLE => {
+        let variable = &mut *(data as *mut retro_variable);
+        let value = VARIABLE_VALUE.lock().unwrap();
+        variable.value = value
+          .as_ref()
+          .map_or(core::ptr::null(), |value| value.as_ptr());
+      }
+      RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+        *(data as *mut bool) = VARIABLE_UPDATED.swap(false, Ordering::SeqCst)
+      }
+      _ => return false,
+    }
+    true
+  }
+
+  #[test]
+  fn test_own_variable_write_is_not_an_update() {
+    let mut instance: TestInstance = new_instance();
+    instance.env.cb = Some(variable_environment);
+    let env = &mut instance.env;
+    let key = CStr::from_bytes_with_nul(b"chip8_speed\0").unwrap();
+    let value = CStr::from_bytes_with_nul(b"2x\0").unwrap();
+    assert!(env.set_variable(&key, &value).is_ok());
+    assert!(!env.get_variable_update());
+
+    VARIABLE_UPDATED.store(true, Ordering::SeqCst);
+    assert!(env.get_variable_update());
+    assert!(!env.get_variable_update());
+
+    // The player changes the option again before the core queries the update.
+    assert!(env.set_variable(&key, &value).is_ok());
+    *VARIABLE_VALUE.lock().unwrap() = Some(CString::new("4x").unwrap());
+    assert!(env.get_variable_update());
+    assert!(!env.get_variable_update());
+  }
+
+  #[test]
+  fn test_pixel_format_set_once_during_load() {
+    let mock = testing::MockEnvironment::new();
+    let mut instance: TestInstance = new_instance();
+    instance.on_set_environment(mock.get_ptr());
+    assert_eq!(mock.env_call_count(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT), 0);
+    unsafe {
+      instance.on_init();
+      assert!(instance.on_load_game(core::ptr::null()));
+    }
+    mock.assert_env_called(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT);
+    assert_eq!(mock.env_call_count(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT), 1);
+    assert_eq!(
+      mock.last_payload(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT),
+      Some(retro_pixel_format::RETRO_PIXEL_FORMAT_XRGB8888 as c_uint)
+    );
+    assert_eq!(mock.pixel_format(), Some(PixelFormat::XRGB8888));
+  }
+
+  static LOAD_ERROR_MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn load_error_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_MESSAGE {
+      return false;
+    }
+    let message = &*(data as *const retro_message);
+    let text = CStr::from_ptr(message.msg).to_string_lossy().into_owned();
+    LOAD_ERROR_MESSAGES.lock().unwrap().push(text);
+    true
+  }
+
+  #[test]
+  fn test_load_error_is_shown() {
+    let mut instance: TestInstance = new_instance();
+    instance.on_set_environment(load_error_environment);
+    unsafe {
+      instance.on_init();
+      instance.init.write(Some(LoadError::MissingBios));
+      assert!(!instance.on_load_game(core::ptr::null()));
+    }
+    assert_eq!(
+      *LOAD_ERROR_MESSAGES.lock().unwrap(),
+      ["Couldn't load the game: a required BIOS file is missing"]
+    );
+    assert_eq!(
+      CoreError::from(LoadError::MissingBios).load_error(),
+      Some(LoadError::MissingBios)
+    );
+    assert_eq!(CoreError::new().load_error(), None);
+  }
+}
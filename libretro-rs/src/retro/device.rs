@@ -1,4 +1,7 @@
+use crate::ffi::*;
 use ::core::ffi::*;
+use ::core::ops::{BitOr, BitOrAssign};
+use c_utf8::CUtf8;
 
 #[repr(transparent)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -12,6 +15,32 @@ impl DeviceTypeId {
     Self(id)
   }
 
+  /// The largest `id` accepted by [`DeviceTypeId::subclass`].
+  pub const MAX_SUBCLASS_INDEX: c_uint = (c_uint::MAX >> RETRO_DEVICE_TYPE_SHIFT) - 1;
+
+  /// Creates the ID of a core-specific subclass of a standard device type, like
+  /// `RETRO_DEVICE_SUBCLASS` does.
+  ///
+  /// # Panics
+  /// If `id` is greater than [`DeviceTypeId::MAX_SUBCLASS_INDEX`], as it wouldn't fit.
+  pub const fn subclass(base: DeviceType, id: c_uint) -> Self {
+    assert!(
+      id <= Self::MAX_SUBCLASS_INDEX,
+      "subclass index doesn't fit in a device type ID"
+    );
+    Self(((id + 1) << RETRO_DEVICE_TYPE_SHIFT) | base as c_uint)
+  }
+
+  /// The ID of the standard device type this device is based on, with any subclass masked out.
+  pub fn base(self) -> Self {
+    Self(self.0 & RETRO_DEVICE_MASK)
+  }
+
+  /// The `id` passed to [`DeviceTypeId::subclass`], or [`None`] if this isn't a subclass.
+  pub fn subclass_index(self) -> Option<c_uint> {
+    (self.0 >> RETRO_DEVICE_TYPE_SHIFT).checked_sub(1)
+  }
+
   pub fn into_inner(self) -> c_uint {
     self.0
   }
@@ -42,11 +71,18 @@ pub enum DeviceType {
   Pointer = 6,
 }
 
+impl From<DeviceType> for DeviceTypeId {
+  fn from(device: DeviceType) -> Self {
+    Self::new(device as c_uint)
+  }
+}
+
 impl TryFrom<DeviceTypeId> for DeviceType {
   type Error = ();
 
+  /// Converts the base type of `val`, so subclassed devices convert to their standard type.
   fn try_from(val: DeviceTypeId) -> Result<Self, Self::Error> {
-    match val.into_inner() {
+    match val.base().into_inner() {
       0 => Ok(Self::None),
       1 => Ok(Self::Joypad),
       2 => Ok(Self::Mouse),
@@ -65,6 +101,19 @@ impl TryFrom<DeviceTypeId> for DeviceType {
 pub struct DevicePort(c_uint);
 
 impl DevicePort {
+  /// The number of ports frontends commonly support; RetroArch allows up to 16 users.
+  pub const MAX: c_uint = 16;
+
+  /// The first [`DevicePort::MAX`] ports.
+  pub fn all() -> impl Iterator<Item = DevicePort> {
+    Self::iter_up_to(Self::MAX)
+  }
+
+  /// The first `count` ports, e.g. up to [`crate::retro::RunCapabilities::max_users`].
+  pub fn iter_up_to(count: c_uint) -> impl Iterator<Item = DevicePort> {
+    (0..count).map(Self::new)
+  }
+
   /// Creates a [`DevicePort`].
   pub fn new(port_number: c_uint) -> Self {
     DevicePort(port_number)
@@ -117,3 +166,592 @@ impl From<JoypadButton> for c_uint {
     button as c_uint
   }
 }
+
+impl JoypadButton {
+  /// Every button, in the order of their IDs.
+  pub const ALL: [JoypadButton; 16] = [
+    Self::B,
+    Self::Y,
+    Self::Select,
+    Self::Start,
+    Self::Up,
+    Self::Down,
+    Self::Left,
+    Self::Right,
+    Self::A,
+    Self::X,
+    Self::L1,
+    Self::R1,
+    Self::L2,
+    Self::R2,
+    Self::L3,
+    Self::R3,
+  ];
+}
+
+/// Declares an enum of `RETRO_DEVICE_ID_*` or `RETRO_DEVICE_INDEX_*` values, which converts to
+/// and from [`c_uint`] for [`crate::retro::Callbacks::input_state`].
+macro_rules! input_ids {
+  (
+    $(#[$attr:meta])*
+    pub enum $name:ident {
+      $( $(#[$variant_attr:meta])* $variant:ident = $value:path, )*
+    }
+  ) => {
+    $(#[$attr])*
+    #[non_exhaustive]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    #[repr(u32)]
+    pub enum $name {
+      $( $(#[$variant_attr])* $variant = $value, )*
+    }
+
+    impl $name {
+      /// Every value, in declaration order.
+      pub const ALL: &'static [Self] = &[$(Self::$variant),*];
+    }
+
+    impl From<$name> for c_uint {
+      fn from(id: $name) -> c_uint {
+        id as c_uint
+      }
+    }
+
+    impl TryFrom<c_uint> for $name {
+      type Error = ();
+
+      fn try_from(val: c_uint) -> Result<Self, Self::Error> {
+        Self::ALL.iter().copied().find(|&id| id as c_uint == val).ok_or(())
+      }
+    }
+  };
+}
+
+input_ids! {
+  /// The inputs of a [`DeviceType::Mouse`].
+  pub enum MouseId {
+    /// The horizontal movement since the last poll.
+    X = RETRO_DEVICE_ID_MOUSE_X,
+    /// The vertical movement since the last poll.
+    Y = RETRO_DEVICE_ID_MOUSE_Y,
+    Left = RETRO_DEVICE_ID_MOUSE_LEFT,
+    Right = RETRO_DEVICE_ID_MOUSE_RIGHT,
+    WheelUp = RETRO_DEVICE_ID_MOUSE_WHEELUP,
+    WheelDown = RETRO_DEVICE_ID_MOUSE_WHEELDOWN,
+    Middle = RETRO_DEVICE_ID_MOUSE_MIDDLE,
+    HorizontalWheelUp = RETRO_DEVICE_ID_MOUSE_HORIZ_WHEELUP,
+    HorizontalWheelDown = RETRO_DEVICE_ID_MOUSE_HORIZ_WHEELDOWN,
+    Button4 = RETRO_DEVICE_ID_MOUSE_BUTTON_4,
+    Button5 = RETRO_DEVICE_ID_MOUSE_BUTTON_5,
+  }
+}
+
+input_ids! {
+  /// The inputs of a [`DeviceType::LightGun`]. The deprecated IDs, which overlap with these, are
+  /// left out.
+  pub enum LightGunId {
+    /// The absolute horizontal position on the screen, from -0x7fff to 0x7fff.
+    ScreenX = RETRO_DEVICE_ID_LIGHTGUN_SCREEN_X,
+    /// The absolute vertical position on the screen, from -0x7fff to 0x7fff.
+    ScreenY = RETRO_DEVICE_ID_LIGHTGUN_SCREEN_Y,
+    IsOffscreen = RETRO_DEVICE_ID_LIGHTGUN_IS_OFFSCREEN,
+    Trigger = RETRO_DEVICE_ID_LIGHTGUN_TRIGGER,
+    Reload = RETRO_DEVICE_ID_LIGHTGUN_RELOAD,
+    AuxA = RETRO_DEVICE_ID_LIGHTGUN_AUX_A,
+    AuxB = RETRO_DEVICE_ID_LIGHTGUN_AUX_B,
+    Start = RETRO_DEVICE_ID_LIGHTGUN_START,
+    Select = RETRO_DEVICE_ID_LIGHTGUN_SELECT,
+    AuxC = RETRO_DEVICE_ID_LIGHTGUN_AUX_C,
+    DpadUp = RETRO_DEVICE_ID_LIGHTGUN_DPAD_UP,
+    DpadDown = RETRO_DEVICE_ID_LIGHTGUN_DPAD_DOWN,
+    DpadLeft = RETRO_DEVICE_ID_LIGHTGUN_DPAD_LEFT,
+    DpadRight = RETRO_DEVICE_ID_LIGHTGUN_DPAD_RIGHT,
+  }
+}
+
+input_ids! {
+  /// The inputs of a [`DeviceType::Pointer`]. The `index` of the query selects the touch point.
+  pub enum PointerId {
+    X = RETRO_DEVICE_ID_POINTER_X,
+    Y = RETRO_DEVICE_ID_POINTER_Y,
+    Pressed = RETRO_DEVICE_ID_POINTER_PRESSED,
+    /// The number of touch points currently pressed.
+    Count = RETRO_DEVICE_ID_POINTER_COUNT,
+  }
+}
+
+input_ids! {
+  /// The `index` of a [`DeviceType::Analog`] query: which stick, or the analog buttons.
+  pub enum AnalogIndex {
+    Left = RETRO_DEVICE_INDEX_ANALOG_LEFT,
+    Right = RETRO_DEVICE_INDEX_ANALOG_RIGHT,
+    /// The analog value of a joypad button, whose [`JoypadButton`] is the ID of the query.
+    Button = RETRO_DEVICE_INDEX_ANALOG_BUTTON,
+  }
+}
+
+input_ids! {
+  /// The axis of an [`AnalogIndex::Left`] or [`AnalogIndex::Right`] stick.
+  pub enum AnalogId {
+    X = RETRO_DEVICE_ID_ANALOG_X,
+    Y = RETRO_DEVICE_ID_ANALOG_Y,
+  }
+}
+
+/// The state of every button of a joypad, where each bit is set if the [`JoypadButton`] with
+/// that ID is pressed.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JoypadState(u16);
+
+impl JoypadState {
+  pub fn from_bits(bits: u16) -> Self {
+    Self(bits)
+  }
+
+  pub fn bits(self) -> u16 {
+    self.0
+  }
+
+  /// Returns true if `button` is pressed.
+  pub fn contains(self, button: JoypadButton) -> bool {
+    self.0 & (1 << button as u16) != 0
+  }
+
+  /// Marks `button` as pressed or released.
+  pub fn set(&mut self, button: JoypadButton, pressed: bool) {
+    if pressed {
+      self.0 |= 1 << button as u16;
+    } else {
+      self.0 &= !(1 << button as u16);
+    }
+  }
+}
+
+/// The keyboard modifiers held during a key event, as `RETROKMOD_*` flags.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyModifiers(u16);
+
+impl KeyModifiers {
+  pub const NONE: Self = Self(retro_mod::RETROKMOD_NONE as u16);
+  pub const SHIFT: Self = Self(retro_mod::RETROKMOD_SHIFT as u16);
+  pub const CTRL: Self = Self(retro_mod::RETROKMOD_CTRL as u16);
+  pub const ALT: Self = Self(retro_mod::RETROKMOD_ALT as u16);
+  pub const META: Self = Self(retro_mod::RETROKMOD_META as u16);
+  pub const NUM_LOCK: Self = Self(retro_mod::RETROKMOD_NUMLOCK as u16);
+  pub const CAPS_LOCK: Self = Self(retro_mod::RETROKMOD_CAPSLOCK as u16);
+  pub const SCROLL_LOCK: Self = Self(retro_mod::RETROKMOD_SCROLLOCK as u16);
+
+  pub fn new(flags: u16) -> Self {
+    Self(flags)
+  }
+
+  pub fn into_inner(self) -> u16 {
+    self.0
+  }
+
+  /// Returns true if all the modifiers in `other` are held.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  /// Returns true if no modifier is held.
+  pub fn is_empty(self) -> bool {
+    self.0 == 0
+  }
+}
+
+impl BitOr for KeyModifiers {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for KeyModifiers {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+impl From<u16> for KeyModifiers {
+  fn from(flags: u16) -> Self {
+    Self(flags)
+  }
+}
+
+impl From<KeyModifiers> for u16 {
+  fn from(modifiers: KeyModifiers) -> Self {
+    modifiers.into_inner()
+  }
+}
+
+/// The state of the analog sticks and buttons of a [`DeviceType::Analog`] controller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AnalogState {
+  pub left_x: i16,
+  pub left_y: i16,
+  pub right_x: i16,
+  pub right_y: i16,
+  pub buttons: JoypadState,
+}
+
+/// The state of a [`DeviceType::Mouse`]. The coordinates are relative to the last poll.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MouseState {
+  pub x: i16,
+  pub y: i16,
+  pub left: bool,
+  pub right: bool,
+  pub middle: bool,
+  pub wheel_up: bool,
+  pub wheel_down: bool,
+}
+
+/// The state of a [`DeviceType::Pointer`], like a touch screen. The coordinates are absolute,
+/// from `-0x7FFF` to `0x7FFF` across the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PointerState {
+  pub x: i16,
+  pub y: i16,
+  pub pressed: bool,
+}
+
+/// The state of a [`DeviceType::LightGun`]. The coordinates are absolute, from `-0x7FFF` to
+/// `0x7FFF` across the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct LightGunState {
+  pub x: i16,
+  pub y: i16,
+  /// True if the gun points outside of the screen; `x` and `y` are then meaningless.
+  pub offscreen: bool,
+  pub trigger: bool,
+  pub reload: bool,
+  pub aux_a: bool,
+  pub aux_b: bool,
+  pub aux_c: bool,
+  pub start: bool,
+  pub select: bool,
+}
+
+impl LightGunState {
+  /// Returns true if the player is reloading.
+  ///
+  /// Frontends report a reload in one of two ways. Those that map the gun to a mouse or a
+  /// controller usually have a dedicated reload button, reported in `reload`. Those driven by a
+  /// real light gun follow the convention of most light gun games instead, where pulling the
+  /// trigger while aiming off the screen reloads. This accepts both.
+  pub fn is_reloading(&self) -> bool {
+    self.reload || (self.offscreen && self.trigger)
+  }
+
+  /// Returns true if the player is shooting, i.e. the trigger is pulled and isn't part of a
+  /// reload. See [`LightGunState::is_reloading`].
+  pub fn is_firing(&self) -> bool {
+    self.trigger && !self.is_reloading()
+  }
+}
+
+/// The input read from a port, according to the device the frontend plugged into it.
+/// See [`crate::retro::Callbacks::read_device`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceInput {
+  /// No device is plugged into the port.
+  None,
+  Joypad(JoypadState),
+  Analog(AnalogState),
+  Mouse(MouseState),
+  Pointer(PointerState),
+  LightGun(LightGunState),
+  /// A device that can't be read as a whole, like a keyboard, or a type unknown to this crate.
+  Unsupported(DeviceTypeId),
+}
+
+/// Describes what an input does in the core, so the frontend can present it when the player
+/// remaps their controls. See [`crate::retro::env::Environment::set_input_descriptors`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputDescriptor {
+  pub port: DevicePort,
+  pub device: DeviceTypeId,
+  pub index: c_uint,
+  pub id: c_uint,
+  pub description: &'static CUtf8,
+}
+
+impl InputDescriptor {
+  /// Describes the joypad button `btn` in `port`.
+  pub fn joypad(port: DevicePort, btn: JoypadButton, description: &'static CUtf8) -> Self {
+    Self {
+      port,
+      device: DeviceTypeId::new(DeviceType::Joypad as c_uint),
+      index: 0,
+      id: btn.into(),
+      description,
+    }
+  }
+}
+
+impl From<&InputDescriptor> for retro_input_descriptor {
+  fn from(descriptor: &InputDescriptor) -> Self {
+    Self {
+      port: descriptor.port.into(),
+      device: descriptor.device.into(),
+      index: descriptor.index,
+      id: descriptor.id,
+      description: descriptor.description.as_ptr(),
+    }
+  }
+}
+
+/// Builds the array passed to `RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS`, which is terminated by
+/// a zeroed entry.
+pub(crate) fn input_descriptor_array(
+  descriptors: &[InputDescriptor],
+) -> Vec<retro_input_descriptor> {
+  descriptors
+    .iter()
+    .map(retro_input_descriptor::from)
+    .chain(Some(retro_input_descriptor::default()))
+    .collect()
+}
+
+/// A controller that can be plugged into a port, as shown to the player by the frontend.
+/// Rust interface for [`retro_controller_description`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerDescription(retro_controller_description);
+
+impl ControllerDescription {
+  /// Describes the device `id`, which is usually a subclass created with
+  /// [`DeviceTypeId::subclass`], e.g. a specific light gun.
+  pub fn new(desc: &'static CUtf8, id: DeviceTypeId) -> Self {
+    Self(retro_controller_description {
+      desc: desc.as_ptr(),
+      id: id.into(),
+    })
+  }
+
+  pub fn id(&self) -> DeviceTypeId {
+    DeviceTypeId::new(self.0.id)
+  }
+}
+
+// SAFETY: the description only points to a `&'static CUtf8`, which is never mutated, so
+// descriptions can be kept in a `static` and shared between threads.
+unsafe impl Send for ControllerDescription {}
+unsafe impl Sync for ControllerDescription {}
+
+/// The controllers available for a port. See
+/// [`crate::retro::env::Environment::set_controller_info`].
+///
+/// Frontends may keep pointers to the descriptions instead of copying them, so they must live
+/// for the rest of the program.
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerPortInfo {
+  pub types: &'static [ControllerDescription],
+}
+
+impl ControllerPortInfo {
+  pub fn new(types: &'static [ControllerDescription]) -> Self {
+    Self { types }
+  }
+}
+
+/// Builds the array passed to `RETRO_ENVIRONMENT_SET_CONTROLLER_INFO`, which is terminated by a
+/// zeroed entry.
+pub(crate) fn controller_info_array(ports: &[ControllerPortInfo]) -> Vec<retro_controller_info> {
+  ports
+    .iter()
+    .map(|port| retro_controller_info {
+      types: port.types.as_ptr().cast(),
+      num_types: port.types.len() as c_uint,
+    })
+    .chain(Some(retro_controller_info {
+      types: core::ptr::null(),
+      num_types: 0,
+    }))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::LazyLock;
+
+  #[test]
+  fn test_device_port_iteration() {
+    assert_eq!(DevicePort::all().count(), 16);
+    assert_eq!(DevicePort::all().last(), Some(DevicePort::new(15)));
+    let ports: Vec<_> = DevicePort::iter_up_to(2).collect();
+    assert_eq!(ports, [DevicePort::new(0), DevicePort::new(1)]);
+    assert_eq!(DevicePort::iter_up_to(0).count(), 0);
+  }
+
+  fn assert_round_trips<T>(all: &[T])
+  where
+    T: Copy + PartialEq + core::fmt::Debug + Into<c_uint> + TryFrom<c_uint>,
+  {
+    for &id in all {
+      assert_eq!(T::try_from(id.into()).ok(), Some(id));
+    }
+  }
+
+  #[test]
+  fn test_input_ids_round_trip() {
+    assert_round_trips(MouseId::ALL);
+    assert_round_trips(LightGunId::ALL);
+    assert_round_trips(PointerId::ALL);
+    assert_round_trips(AnalogIndex::ALL);
+    assert_round_trips(AnalogId::ALL);
+
+    assert_eq!(c_uint::from(MouseId::WheelDown), 5);
+    assert_eq!(c_uint::from(LightGunId::Reload), 16);
+    assert_eq!(c_uint::from(PointerId::Count), 3);
+    assert_eq!(c_uint::from(AnalogIndex::Button), 2);
+    assert_eq!(MouseId::ALL.len(), 11);
+    assert_eq!(LightGunId::ALL.len(), 14);
+    assert_eq!(LightGunId::try_from(0), Err(()));
+    assert_eq!(AnalogId::try_from(2), Err(()));
+  }
+
+  #[test]
+  fn test_key_modifiers_decode() {
+    let modifiers = KeyModifiers::from(0b0100_0011);
+    assert!(modifiers.contains(KeyModifiers::SHIFT | KeyModifiers::CTRL));
+    assert!(modifiers.contains(KeyModifiers::SCROLL_LOCK));
+    assert!(!modifiers.contains(KeyModifiers::ALT));
+    assert!(!modifiers.contains(KeyModifiers::SHIFT | KeyModifiers::META));
+    assert!(modifiers.contains(KeyModifiers::NONE));
+    assert!(!modifiers.is_empty());
+    assert!(KeyModifiers::from(0).is_empty());
+
+    let mut locks = KeyModifiers::NUM_LOCK;
+    locks |= KeyModifiers::CAPS_LOCK;
+    assert_eq!(u16::from(locks), 16 | 32);
+    assert_eq!(u16::from(KeyModifiers::ALT | KeyModifiers::META), 4 | 8);
+  }
+
+  #[test]
+  fn test_lightgun_reload_conventions() {
+    let explicit = LightGunState {
+      reload: true,
+      ..LightGunState::default()
+    };
+    assert!(explicit.is_reloading());
+    assert!(!explicit.is_firing());
+
+    let gesture = LightGunState {
+      offscreen: true,
+      trigger: true,
+      ..LightGunState::default()
+    };
+    assert!(gesture.is_reloading());
+    assert!(!gesture.is_firing());
+
+    let shot = LightGunState {
+      trigger: true,
+      ..LightGunState::default()
+    };
+    assert!(!shot.is_reloading());
+    assert!(shot.is_firing());
+    let aiming_away = LightGunState {
+      offscreen: true,
+      ..LightGunState::default()
+    };
+    assert!(!aiming_away.is_reloading());
+  }
+
+  #[test]
+  fn test_subclass_base_type() {
+    let id = DeviceTypeId::subclass(DeviceType::Analog, 0xFF_FFFE);
+    assert_eq!(id.into_inner(), 0xFFFF_FF05);
+    assert_eq!(id.base(), DeviceTypeId::new(5));
+    assert_eq!(DeviceType::try_from(id), Ok(DeviceType::Analog));
+    assert_eq!(DeviceType::try_from(DeviceTypeId::new(0x107)), Err(()));
+  }
+
+  #[test]
+  fn test_subclass_round_trip() {
+    for base in [
+      DeviceType::Joypad,
+      DeviceType::LightGun,
+      DeviceType::Pointer,
+    ] {
+      for index in [0, 1, 7, DeviceTypeId::MAX_SUBCLASS_INDEX] {
+        let id = DeviceTypeId::subclass(base, index);
+        assert_eq!(DeviceType::try_from(id), Ok(base));
+        assert_eq!(id.base(), base.into());
+        assert_eq!(id.subclass_index(), Some(index));
+      }
+      assert_eq!(DeviceTypeId::from(base).subclass_index(), None);
+    }
+  }
+
+  #[test]
+  #[should_panic]
+  fn test_subclass_index_out_of_range() {
+    DeviceTypeId::subclass(DeviceType::Joypad, DeviceTypeId::MAX_SUBCLASS_INDEX + 1);
+  }
+
+  #[test]
+  fn test_controller_info_array() {
+    static PORT0: LazyLock<[ControllerDescription; 2]> = LazyLock::new(|| {
+      [
+        ControllerDescription::new(c_utf8::c_utf8!("Joypad"), DeviceType::Joypad.into()),
+        ControllerDescription::new(
+          c_utf8::c_utf8!("Justifier"),
+          DeviceTypeId::subclass(DeviceType::LightGun, 1),
+        ),
+      ]
+    });
+    static PORT1: LazyLock<[ControllerDescription; 1]> = LazyLock::new(|| {
+      [ControllerDescription::new(
+        c_utf8::c_utf8!("Mouse"),
+        DeviceType::Mouse.into(),
+      )]
+    });
+    let ports = [
+      ControllerPortInfo::new(&*PORT0),
+      ControllerPortInfo::new(&*PORT1),
+    ];
+    let array = controller_info_array(&ports);
+    assert_eq!(array.len(), 3);
+    assert_eq!(array[0].num_types, 2);
+    let justifier = unsafe { &*array[0].types.add(1) };
+    assert_eq!(justifier.id, 0x204);
+    assert_eq!(array[1].num_types, 1);
+    assert!(array[2].types.is_null());
+    assert_eq!(array[2].num_types, 0);
+  }
+
+  #[test]
+  fn test_input_descriptor_array_terminator() {
+    let descriptors = [
+      InputDescriptor::joypad(DevicePort::new(0), JoypadButton::A, c_utf8::c_utf8!("Jump")),
+      InputDescriptor::joypad(
+        DevicePort::new(1),
+        JoypadButton::Start,
+        c_utf8::c_utf8!("Pause"),
+      ),
+    ];
+    let array = input_descriptor_array(&descriptors);
+    assert_eq!(array.len(), 3);
+    assert_eq!(array[1].port, 1);
+    assert_eq!(array[1].id, JoypadButton::Start as c_uint);
+    assert_eq!(array[1].description, descriptors[1].description.as_ptr());
+    let terminator = array[2];
+    assert!(terminator.description.is_null());
+    assert_eq!(
+      (
+        terminator.port,
+        terminator.device,
+        terminator.index,
+        terminator.id
+      ),
+      (0, 0, 0, 0)
+    );
+  }
+}
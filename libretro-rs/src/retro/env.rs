@@ -1,7 +1,9 @@
 pub use crate::convert::*;
 use crate::ffi::*;
-use crate::retro::pixel::{Format, ORGB1555, RGB565, XRGB8888};
+use crate::retro::pixel::{AnyFormat, Format, ORGB1555, RGB565, XRGB8888};
 use crate::retro::*;
+use core::ops::{BitOr, BitOrAssign};
+use core::sync::atomic::{AtomicBool, Ordering};
 use libretro_rs_ffi::retro_pixel_format::{
   RETRO_PIXEL_FORMAT_0RGB1555, RETRO_PIXEL_FORMAT_RGB565, RETRO_PIXEL_FORMAT_XRGB8888,
 };
@@ -9,11 +11,20 @@ use std::marker::PhantomData;
 
 pub type Result<T> = core::result::Result<T, CommandError>;
 
+/// A [`Result`] that describes why an environment command failed. See [`EnvError`].
+pub type EnvResult<T> = core::result::Result<T, EnvError>;
+
 /// Exposes the [`retro_environment_t`] callback in an idiomatic fashion.
 /// Each of the `RETRO_ENVIRONMENT_*` keys will eventually have a corresponding method here.
 ///
 /// Until that is accomplished, the keys are available in [`libretro_rs::ffi`] and can be used
-/// manually with the various `*_raw` methods.
+/// manually with [`Environment::get`], [`Environment::set`] and [`Environment::cmd`].
+///
+/// This trait holds the commands that are valid at any time, such as paths, core options,
+/// messages and logging. Each [`crate::retro::Core`] method receives one of the traits below,
+/// e.g. [`Run`] or [`LoadGame`], which extend it with the commands that are only valid during
+/// that call. Helpers that only need the common commands can take `&mut impl Environment`
+/// and be called from any of them.
 pub trait Environment: Sized {
   fn get_ptr(&self) -> non_null_retro_environment_t;
 
@@ -50,7 +61,7 @@ pub trait Environment: Sized {
   unsafe fn set<C, D>(&mut self, cmd: C, data: &D) -> Result<()>
   where
     C: Into<c_uint>,
-    D: CommandData,
+    D: CommandData + ?Sized,
   {
     with_ref(self.get_ptr(), cmd.into(), data)
   }
@@ -65,16 +76,43 @@ pub trait Environment: Sized {
     with_mut(self.get_ptr(), cmd.into(), &mut data).map(|_| data)
   }
 
-  /// Sets screen rotation of graphics.
+  /// Like [`Environment::get`], but reports a failed command as an [`EnvError`].
+  ///
+  /// # Safety
+  /// See [`Environment::get`].
+  unsafe fn get_checked<C, R>(&self, cmd: C) -> EnvResult<R>
+  where
+    C: Into<c_uint>,
+    R: Default + CommandData,
+  {
+    self.get(cmd).map_err(|_| EnvError::Failed)
+  }
+
+  /// Like [`Environment::set`], but reports a failed command as an [`EnvError`].
+  ///
+  /// # Safety
+  /// See [`Environment::set`].
+  unsafe fn set_checked<C, D>(&mut self, cmd: C, data: &D) -> EnvResult<()>
+  where
+    C: Into<c_uint>,
+    D: CommandData + ?Sized,
+  {
+    self.set(cmd, data).map_err(|_| EnvError::Failed)
+  }
+
+  /// Sets screen rotation of graphics, counter-clockwise.
+  ///
+  /// The frontend rotates the output itself; the core still renders frames in its native
+  /// orientation. Frontends may ignore the request, e.g. when rotation is disabled by the user.
   fn set_rotation(&mut self, rotation: ScreenRotation) -> Result<()> {
-    unsafe { self.set(RETRO_ENVIRONMENT_SET_ROTATION, &(rotation as c_int)) }
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_ROTATION, &c_uint::from(rotation)) }
   }
 
-  #[cfg(deprecated)]
   /// Boolean value whether or not the implementation should use overscan,
-  /// or crop away overscan.
-  fn get_overscan(&self) -> Result<bool> {
-    unsafe { self.get(RETRO_ENVIRONMENT_GET_OVERSCAN) }
+  /// or crop away overscan. Returns `false` if the frontend doesn't support this command.
+  #[deprecated(note = "libretro deprecated this in favor of a core option for overscan")]
+  fn get_overscan(&self) -> bool {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_OVERSCAN) }.unwrap_or(false)
   }
 
   /// Boolean value indicating whether or not frontend supports frame duping.
@@ -90,6 +128,20 @@ pub trait Environment: Sized {
     unsafe { self.set(RETRO_ENVIRONMENT_SET_MESSAGE, message) }
   }
 
+  /// Queries the version of the message interface supported by the frontend: 1 or more if it
+  /// supports [`Environment::set_message_ext`], and 0 if it only supports
+  /// [`Environment::set_message`] or doesn't recognize this command.
+  fn get_message_interface_version(&self) -> c_uint {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_MESSAGE_INTERFACE_VERSION) }.unwrap_or(0)
+  }
+
+  /// Like [`Environment::set_message`], but with a duration in milliseconds and a priority.
+  /// Requires a message interface version of 1 or more, see
+  /// [`Environment::get_message_interface_version`].
+  fn set_message_ext(&mut self, message: &MessageExt) -> Result<()> {
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_MESSAGE_EXT, message) }
+  }
+
   /// Queries the path where the current libretro core resides.
   fn get_libretro_path(&self) -> Result<Option<&CStr>> {
     unsafe { self.get(RETRO_ENVIRONMENT_GET_LIBRETRO_PATH).unsafe_into() }
@@ -137,6 +189,343 @@ pub trait Environment: Sized {
     }
   }
 
+  /// Describes the inputs used by the core, so the frontend can show them when the player remaps
+  /// their controls. It's recommended to call this as early as possible, e.g. in
+  /// [`crate::retro::Core::set_environment`].
+  fn set_input_descriptors(&mut self, descriptors: &[InputDescriptor]) -> Result<()> {
+    let array = input_descriptor_array(descriptors);
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, array.as_slice()) }
+  }
+
+  /// Tells the frontend which controllers can be plugged into each port, starting from port 0.
+  /// The frontend lets the player choose between them, and then calls
+  /// [`crate::retro::DeviceTypeAwareCore::set_controller_port_device`].
+  fn set_controller_info(&mut self, ports: &[ControllerPortInfo]) -> Result<()> {
+    let array = controller_info_array(ports);
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_CONTROLLER_INFO, array.as_slice()) }
+  }
+
+  /// Queries the version of the core options interface supported by the frontend. Frontends that
+  /// don't recognize this command only support `RETRO_ENVIRONMENT_SET_VARIABLES`, which is
+  /// reported as version 0.
+  fn get_core_options_version(&self) -> c_uint {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION) }.unwrap_or(0)
+  }
+
+  /// Queries the version of the disk control interface supported by the frontend: 1 or more if
+  /// it supports `RETRO_ENVIRONMENT_SET_DISK_CONTROL_EXT_INTERFACE`, and 0 if it only supports
+  /// `RETRO_ENVIRONMENT_SET_DISK_CONTROL_INTERFACE` or doesn't recognize this command.
+  fn get_disk_control_interface_version(&self) -> c_uint {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_DISK_CONTROL_INTERFACE_VERSION) }.unwrap_or(0)
+  }
+
+  /// Called before issuing a command that may only be issued from `retro_run`. Implementors may
+  /// use this to detect commands issued from the wrong callback.
+  #[doc(hidden)]
+  fn check_run_only(&mut self, _command: &str) {}
+
+  /// Called after the core changes the core option `key` to `value` with
+  /// [`Environment::set_variable`].
+  #[doc(hidden)]
+  fn variable_written(&mut self, _key: &CStr, _value: &CStr) {}
+
+  /// Called after the frontend accepts a [`Run::shutdown`] request.
+  #[doc(hidden)]
+  fn shutdown_accepted(&mut self) {}
+
+  /// Called after the frontend accepts the quirks set with
+  /// [`Environment::set_serialization_quirks`].
+  #[doc(hidden)]
+  fn serialization_quirks_set(&mut self, _quirks: SerializationQuirks) {}
+
+  /// Given the result of every `RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE` query. Implementors may
+  /// use this to hide the update caused by the core's own write.
+  #[doc(hidden)]
+  fn filter_variable_update(&mut self, updated: bool) -> bool {
+    updated
+  }
+
+  /// Returns true if a core option changed since the last call, so the core should read its
+  /// options again with [`Environment::get_variable`].
+  ///
+  /// Frontends also report the changes made by [`Environment::set_variable`]. The environment
+  /// passed to a [`crate::retro::Core`] hides the first update after such a write if the options
+  /// the core wrote still hold the values it wrote, since the core already knows them. If the
+  /// player only changed other options before that query, those changes are hidden too.
+  fn get_variable_update(&mut self) -> bool {
+    let updated = unsafe { self.get(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE) }.unwrap_or(false);
+    self.filter_variable_update(updated)
+  }
+
+  /// Changes the value of the core option `key`, e.g. when the core changes a setting from its
+  /// own menu. `value` must be one of the values the option was declared with.
+  fn set_variable(&mut self, key: &impl AsRef<CStr>, value: &impl AsRef<CStr>) -> EnvResult<()> {
+    let variable = retro_variable {
+      key: key.as_ref().as_ptr(),
+      value: value.as_ref().as_ptr(),
+    };
+    unsafe { self.set_checked(RETRO_ENVIRONMENT_SET_VARIABLE, &variable) }?;
+    self.variable_written(key.as_ref(), value.as_ref());
+    Ok(())
+  }
+
+  /// Declares the limitations of the core's save states, so the frontend can avoid the features
+  /// that rely on them, like runahead. Should be called from [`crate::retro::Core::init`] or
+  /// [`crate::retro::Core::load_game`].
+  ///
+  /// Returns the quirks acknowledged by the frontend, which adds
+  /// [`SerializationQuirks::FRONT_VARIABLE_SIZE`] if it supports save states whose size changes.
+  /// With [`SerializationQuirks::CORE_VARIABLE_SIZE`], the [`crate::retro::Instance`] queries
+  /// [`crate::retro::SaveStateCore::serialize_size`] before every save instead of only once.
+  fn set_serialization_quirks(
+    &mut self,
+    quirks: SerializationQuirks,
+  ) -> Result<SerializationQuirks> {
+    let quirks: u64 = unsafe {
+      self.cmd(
+        RETRO_ENVIRONMENT_SET_SERIALIZATION_QUIRKS,
+        quirks.into_inner(),
+      )
+    }?;
+    let quirks = SerializationQuirks::new(quirks);
+    self.serialization_quirks_set(quirks);
+    Ok(quirks)
+  }
+
+  /// Declares the core options, in US English. See [`Environment::declare_options_intl`].
+  fn declare_options(&mut self, options: &[CoreOption]) -> EnvResult<()> {
+    self.declare_options_intl(options, None)
+  }
+
+  /// Declares the core options, in US English and optionally in the frontend's language.
+  ///
+  /// This uses `RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL` on core options version 2 and later,
+  /// and `RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL` on version 1. If the frontend rejects either,
+  /// the next older command is tried instead. On version 0, the US English options are declared
+  /// with `RETRO_ENVIRONMENT_SET_VARIABLES`, which can't show labels or sublabels.
+  ///
+  /// The frontend copies the options, so they don't need to outlive this call. Options are
+  /// shown in the frontend's menu in the order they appear in `options`.
+  fn declare_options_intl(
+    &mut self,
+    us: &[CoreOption],
+    local: Option<&[CoreOption]>,
+  ) -> EnvResult<()> {
+    let version = self.get_core_options_version();
+    if version >= 2 {
+      let us = v2_definition_array(us);
+      let local = local.map(v2_definition_array);
+      let us = retro_core_options_v2 {
+        categories: core::ptr::null_mut(),
+        definitions: us.as_ptr() as *mut _,
+      };
+      let local = local.as_ref().map(|local| retro_core_options_v2 {
+        categories: core::ptr::null_mut(),
+        definitions: local.as_ptr() as *mut _,
+      });
+      let data = retro_core_options_v2_intl {
+        us: &us as *const _ as *mut _,
+        local: local
+          .as_ref()
+          .map_or(core::ptr::null_mut(), |local| local as *const _ as *mut _),
+      };
+      if unsafe { self.set(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL, &data) }.is_ok() {
+        return Ok(());
+      }
+    }
+    if version >= 1 {
+      let us = definition_array(us);
+      let local = local.map(definition_array);
+      let data = retro_core_options_intl {
+        us: us.as_ptr() as *mut _,
+        local: local
+          .as_ref()
+          .map_or(core::ptr::null_mut(), |local| local.as_ptr() as *mut _),
+      };
+      if unsafe { self.set(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL, &data) }.is_ok() {
+        return Ok(());
+      }
+    }
+    let values: Vec<_> = us.iter().map(CoreOption::variable_value).collect();
+    let variables: Vec<_> = us
+      .iter()
+      .zip(&values)
+      .map(|(option, value)| Variable::new(option.key.as_c_str(), value))
+      .collect();
+    self.set_variables(&variables)
+  }
+
+  /// Declares core options with `RETRO_ENVIRONMENT_SET_VARIABLES`, which every frontend supports
+  /// but which can't show labels, sublabels or translations. See [`Variable::value`] for the
+  /// format of the values.
+  ///
+  /// [`Environment::declare_options`] already falls back to this on frontends that don't support
+  /// newer interfaces, so this is only needed by cores that build the value strings themselves.
+  fn set_variables(&mut self, variables: &[Variable]) -> EnvResult<()> {
+    let variables = variable_array(variables);
+    unsafe { self.set_checked(RETRO_ENVIRONMENT_SET_VARIABLES, variables.as_slice()) }
+  }
+
+  /// Suggests to the frontend whether the core option `key` should be displayed.
+  ///
+  /// This requires a core options version of at least 1. Older frontends can't hide options, so
+  /// on those this does nothing and logs a warning the first time it's called.
+  fn set_option_visible(&mut self, key: &impl AsRef<CStr>, visible: bool) -> EnvResult<()> {
+    if self.get_core_options_version() == 0 {
+      if !OPTION_DISPLAY_WARNED.swap(true, Ordering::Relaxed) {
+        crate::retro_warn!(
+          self.get_log_interface().ok(),
+          "frontend doesn't support hiding core options; ignoring set_option_visible"
+        );
+      }
+      return Ok(());
+    }
+    let data = retro_core_option_display {
+      key: key.as_ref().as_ptr(),
+      visible,
+    };
+    unsafe { self.set_checked(RETRO_ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY, &data) }
+  }
+
+  /// Gets an interface to set the state of the rumble motors in controllers.
+  fn get_rumble_interface(&self) -> Result<RumbleInterface> {
+    unsafe {
+      self
+        .get(RETRO_ENVIRONMENT_GET_RUMBLE_INTERFACE)
+        .unsafe_into()
+    }
+  }
+
+  /// Gets an interface to the frontend's timers and CPU feature detection. See [`CpuFeatures`].
+  ///
+  /// Returns [`None`] if the frontend doesn't support the command.
+  fn get_perf_interface(&self) -> Option<PerfInterface> {
+    let data = unsafe { self.get(RETRO_ENVIRONMENT_GET_PERF_INTERFACE) }.ok()?;
+    Some(PerfInterface::new(data))
+  }
+
+  /// Gets the API-specific interface of the hardware context, for rendering APIs that need one,
+  /// such as Vulkan. See [`HwRenderInterface`].
+  ///
+  /// Must not be called before the frontend has reset the hardware context, and the interface is
+  /// invalidated when the context is destroyed. Returns [`None`] if the frontend doesn't provide
+  /// an interface for the current context.
+  fn get_hw_render_interface(&self) -> Option<HwRenderInterface> {
+    let iface: Option<&c_void> =
+      unsafe { self.get(RETRO_ENVIRONMENT_GET_HW_RENDER_INTERFACE) }.ok()?;
+    let iface = core::ptr::NonNull::from(iface?).cast();
+    Some(unsafe { HwRenderInterface::new(iface) })
+  }
+
+  /// Gets the hardware rendering API the frontend prefers, so cores that support several can
+  /// pick one before calling [`LoadGame::set_hw_render_gl`] or similar.
+  ///
+  /// Returns [`None`] if the frontend doesn't support the command.
+  fn get_preferred_hw_render(&self) -> Option<HwContextType> {
+    // Read as an integer, since frontends may return types missing from the bindings.
+    let context_type: c_uint =
+      unsafe { self.get(RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER) }.ok()?;
+    Some(context_type.into())
+  }
+
+  /// Gets an interface to open microphones, requesting the given interface `version` (usually
+  /// [`RETRO_MICROPHONE_INTERFACE_VERSION`]).
+  ///
+  /// Returns [`None`] if microphone support is disabled or unavailable, or if the frontend
+  /// doesn't provide the requested version. This command is experimental upstream.
+  fn get_microphone_interface(&self, version: u32) -> Option<MicrophoneInterface> {
+    let data = retro_microphone_interface {
+      interface_version: version,
+      ..Default::default()
+    };
+    unsafe { self.get_with(RETRO_ENVIRONMENT_GET_MICROPHONE_INTERFACE, data) }
+      .ok()
+      .filter(|interface: &retro_microphone_interface| interface.interface_version == version)
+      .and_then(MicrophoneInterface::new)
+  }
+
+  /// Returns true if the frontend can return the state of every joypad button with a single
+  /// input query. See [`crate::retro::Callbacks::joypad_bitmask`].
+  fn get_input_bitmasks(&self) -> bool {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_INPUT_BITMASKS) }.unwrap_or(false)
+  }
+
+  /// Returns how many players the frontend feeds input for, or [`None`] if it doesn't say.
+  /// See [`crate::retro::Callbacks::active_ports`].
+  fn get_input_max_users(&self) -> Option<u32> {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_INPUT_MAX_USERS) }.ok()
+  }
+
+  /// Queries whether the frontend wants the core to produce video and audio. Returns
+  /// [`AudioVideoEnable::default`], with both enabled, if the frontend doesn't support this command.
+  fn get_audio_video_enable(&self) -> AudioVideoEnable {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_AUDIO_VIDEO_ENABLE) }
+      .map(AudioVideoEnable::new)
+      .unwrap_or_default()
+  }
+
+  /// Returns true if the frontend is fast-forwarding, in which case the core may skip work that
+  /// isn't needed at high speed.
+  fn get_fastforwarding(&self) -> bool {
+    unsafe { self.get(RETRO_ENVIRONMENT_GET_FASTFORWARDING) }.unwrap_or(false)
+  }
+
+  /// Overrides the frontend's fast-forwarding state, e.g. to speed through loading screens. The
+  /// frontend may ignore the request; this command is experimental upstream.
+  ///
+  /// Returns an error if the frontend doesn't support fast-forwarding overrides, in which case
+  /// the user stays in control of fast-forwarding.
+  fn set_fastforwarding_override(&mut self, over: FastforwardOverride) -> Result<()> {
+    unsafe {
+      self.set(
+        RETRO_ENVIRONMENT_SET_FASTFORWARDING_OVERRIDE,
+        &retro_fastforwarding_override::from(over),
+      )
+    }
+  }
+
+  /// Gets the RetroArch callback that releases the frontend's waits on the core's threads. See
+  /// [`ClearThreadWaits`].
+  ///
+  /// Returns [`None`] if the frontend doesn't provide it, which is the case of every frontend
+  /// but RetroArch, since this is a RetroArch-specific command.
+  fn get_clear_all_thread_waits_callback(&self) -> Option<ClearThreadWaits> {
+    let cb: retro_environment_t =
+      unsafe { self.get(RETRO_ENVIRONMENT_GET_CLEAR_ALL_THREAD_WAITS_CB) }.ok()?;
+    cb.map(ClearThreadWaits)
+  }
+
+  /// Gets access to the frontend's virtual file system, requiring at least the given interface
+  /// `version`. This should be queried in [`crate::retro::Core::set_environment`], so the frontend
+  /// knows the core supports it before handing out any paths.
+  ///
+  /// Returns [`None`] if the frontend doesn't provide the required version.
+  fn get_vfs_interface(&self, version: u32) -> Option<VfsInterface> {
+    let data = retro_vfs_interface_info {
+      required_interface_version: version,
+      iface: core::ptr::null_mut(),
+    };
+    unsafe {
+      let info: retro_vfs_interface_info = self
+        .get_with(RETRO_ENVIRONMENT_GET_VFS_INTERFACE, data)
+        .ok()?;
+      let iface = info.iface.as_ref()?;
+      Some(VfsInterface::new(info.required_interface_version, iface))
+    }
+  }
+
+  /// Queries the context in which the frontend is asking for a save state. Cores may use this
+  /// in [`crate::retro::SaveStateCore`] to skip expensive or non-deterministic state when the
+  /// frontend is doing runahead or rollback.
+  ///
+  /// Returns [`SavestateContext::Normal`] if the frontend doesn't support this experimental
+  /// command.
+  fn get_savestate_context(&self) -> SavestateContext {
+    unsafe { self.get::<_, c_int>(RETRO_ENVIRONMENT_GET_SAVESTATE_CONTEXT) }
+      .map(SavestateContext::from)
+      .unwrap_or_default()
+  }
+
   /// Queries the username associated with the frontend.
   fn get_username(&self) -> Result<Option<&CStr>> {
     unsafe { self.get(RETRO_ENVIRONMENT_GET_USERNAME).unsafe_into() }
@@ -146,8 +535,12 @@ pub trait Environment: Sized {
   /// platforms cannot use stderr for logging. It also allows the frontend to show logging
   /// information in a more suitable way. If this interface is not used, libretro cores should log
   /// to [std::io::Stderr] (via [eprintln], [StderrLogger] or [FallbackLogger]) as desired.
+  /// Fails if the frontend doesn't provide a logger, even if it accepts the command.
   fn get_log_interface(&self) -> Result<PlatformLogger> {
-    unsafe { self.get(RETRO_ENVIRONMENT_GET_LOG_INTERFACE).unsafe_into() }
+    let cb: retro_log_callback = unsafe { self.get(RETRO_ENVIRONMENT_GET_LOG_INTERFACE)? };
+    cb.log
+      .map(PlatformLogger::new)
+      .ok_or_else(CommandError::new)
   }
 }
 
@@ -161,6 +554,43 @@ pub trait SetEnvironment: Environment {
   fn set_support_no_game(&mut self, data: bool) -> Result<()> {
     unsafe { self.set(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME, &data) }
   }
+
+  /// Lets the frontend look up functions exported by the core by name, for extension APIs that
+  /// the core and the frontend agree on. The core's functions are listed in `table`, which
+  /// replaces any table registered before.
+  ///
+  /// The frontend receives each function as a [`retro_proc_address_t`], which erases its
+  /// signature, and must cast it back to the right type before calling it. Calling it with any
+  /// other signature is undefined behavior, so exported functions should be documented as part of
+  /// the extension API.
+  fn set_proc_address_callback(&mut self, table: &'static [ProcAddress]) -> Result<()> {
+    *PROC_ADDRESSES
+      .lock()
+      .unwrap_or_else(std::sync::PoisonError::into_inner) = table;
+    let data = retro_get_proc_address_interface {
+      get_proc_address: Some(get_proc_address),
+    };
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_PROC_ADDRESS_CALLBACK, &data) }
+  }
+
+  /// Overrides the content flags of the [`SystemInfo`] for some extensions, e.g. to only need the
+  /// full path for disc images.
+  ///
+  /// Returns `false` and logs a warning if the frontend doesn't support overrides, in which case
+  /// it uses the flags of the [`SystemInfo`] for every extension, so cores must handle content
+  /// loaded either way.
+  fn set_content_info_override(&mut self, overrides: &[ContentInfoOverride]) -> bool {
+    let array = content_info_override_array(overrides);
+    let data = array.as_slice();
+    if unsafe { self.set(RETRO_ENVIRONMENT_SET_CONTENT_INFO_OVERRIDE, data) }.is_ok() {
+      return true;
+    }
+    crate::retro_warn!(
+      self.get_log_interface().ok(),
+      "the frontend doesn't support content info overrides"
+    );
+    false
+  }
 }
 impl<T: Environment> SetEnvironment for T {}
 
@@ -174,14 +604,71 @@ pub trait Reset: Environment {}
 impl<T: Environment> Reset for T {}
 
 pub trait Run: Environment {
-  /// Requests that the frontend shut down. The frontend can refuse to do this, and return false.
+  /// Requests that the frontend shut down. The frontend can refuse to do this, in which case an
+  /// error is returned.
+  ///
+  /// If the frontend accepts, [`crate::retro::Core::on_shutdown_requested`] is called once
+  /// [`crate::retro::Core::run`] returns, so the core can flush its saves before the frontend
+  /// unloads it.
   fn shutdown(&mut self) -> Result<()> {
-    unsafe { self.cmd(RETRO_ENVIRONMENT_SHUTDOWN, ()) }
+    let result = unsafe { self.cmd(RETRO_ENVIRONMENT_SHUTDOWN, ()) };
+    if result.is_ok() {
+      self.shutdown_accepted();
+    }
+    result
   }
 
   fn set_geometry(&mut self, geometry: &GameGeometry) -> Result<()> {
     unsafe { self.set(RETRO_ENVIRONMENT_SET_GEOMETRY, geometry) }
   }
+
+  /// Sets the minimum audio latency of the frontend in milliseconds. Passing 0 restores the
+  /// frontend's default latency.
+  ///
+  /// This may reinitialize the frontend's audio driver, so it should be called sparingly. Cores
+  /// that skip frames with [`crate::retro::AudioBufferStatusCore`] may get the best results by
+  /// setting this to a high multiple of the expected frame time.
+  fn set_minimum_audio_latency(&mut self, latency_ms: c_uint) -> Result<()> {
+    self.check_run_only("RETRO_ENVIRONMENT_SET_MINIMUM_AUDIO_LATENCY");
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_MINIMUM_AUDIO_LATENCY, &latency_ms) }
+  }
+
+  /// Informs the frontend that the core's audio/video timings have changed.
+  ///
+  /// This is a heavy operation that may reinitialize the frontend's drivers;
+  /// prefer [`Run::set_geometry`] when only the geometry has changed.
+  fn set_system_av_info(&mut self, av_info: &SystemAVInfo) -> EnvResult<()> {
+    self.check_run_only("RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO");
+    unsafe { self.set_checked(RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO, av_info) }
+  }
+
+  /// Gets a framebuffer from the frontend that the core can render the current frame into, which
+  /// saves copying the frame. Returns [`None`] if the frontend doesn't provide one, in which case
+  /// the core should render into its own buffer as usual.
+  ///
+  /// The framebuffer is only valid until the end of the current [`crate::retro::Core::run`].
+  fn get_current_software_framebuffer(
+    &mut self,
+    width: c_uint,
+    height: c_uint,
+    access: FramebufferAccess,
+  ) -> Option<SoftwareFramebuffer<'_>> {
+    let framebuffer = retro_framebuffer {
+      width,
+      height,
+      access_flags: access.into_inner(),
+      ..Default::default()
+    };
+    unsafe {
+      let framebuffer = self
+        .get_with(
+          RETRO_ENVIRONMENT_GET_CURRENT_SOFTWARE_FRAMEBUFFER,
+          framebuffer,
+        )
+        .ok()?;
+      SoftwareFramebuffer::from_raw(framebuffer)
+    }
+  }
 }
 impl<T: Environment> Run for T {}
 
@@ -219,6 +706,28 @@ pub trait LoadGame: Environment {
     }
   }
 
+  fn set_best_pixel_format<F>(
+    &mut self,
+    current_format: Format<F>,
+    preferred: &[PixelFormat],
+  ) -> core::result::Result<AnyFormat, Format<F>> {
+    GetAvInfo::set_best_pixel_format(self, current_format, preferred)
+  }
+
+  /// Tells the frontend whether this core supports achievements. The memory used for
+  /// achievements is the one returned by [`crate::retro::Core::achievement_ram`].
+  ///
+  /// Advertising support is only useful if that memory has a stable layout that achievement sets
+  /// can be written against. Cores whose memory is split in several regions describe it with
+  /// `RETRO_ENVIRONMENT_SET_MEMORY_MAPS` instead (see [`crate::retro::MemoryDescriptorFlags`]).
+  fn set_support_achievements(&mut self, supported: bool) -> Result<()> {
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS, &supported) }
+  }
+
+  fn set_pixel_format(&mut self, format: PixelFormat) -> EnvResult<AnyFormat> {
+    GetAvInfo::set_pixel_format(self, format)
+  }
+
   fn set_pixel_format_0rgb1555<F>(
     &mut self,
     current_format: Format<F>,
@@ -243,6 +752,65 @@ pub trait LoadGame: Environment {
   fn set_hw_render_none(&mut self) -> Result<()>;
 
   fn set_hw_render_gl(&mut self, options: GLOptions) -> Result<GLRenderEnabled>;
+
+  /// Gets extended information about the game being loaded, with one entry per game for
+  /// [`crate::retro::SpecialGameCore::load_game`]. Cores can use it to find files next to the
+  /// game, or to know whether the game data persists (see
+  /// [`SetEnvironment::set_content_info_override`]).
+  ///
+  /// Returns [`None`] if the frontend doesn't support the command.
+  fn get_game_info_ext(&self) -> Option<&[GameInfoExt]> {
+    let info: Option<&retro_game_info_ext> =
+      unsafe { self.get(RETRO_ENVIRONMENT_GET_GAME_INFO_EXT) }.ok()?;
+    let info: *const GameInfoExt = (info? as *const retro_game_info_ext).cast();
+    Some(unsafe { core::slice::from_raw_parts(info, self.game_count()) })
+  }
+
+  /// The number of games being loaded, which is the length of the array returned by
+  /// [`LoadGame::get_game_info_ext`].
+  #[doc(hidden)]
+  fn game_count(&self) -> usize {
+    1
+  }
+
+  /// Asks the frontend to set up a hardware context that's shared with the core, so the core can
+  /// create its own resources in it, e.g. from another thread. This command is experimental
+  /// upstream.
+  ///
+  /// Must be called before [`LoadGame::set_hw_render_gl`], since it only affects contexts that
+  /// are set up afterwards. Returns an error if the frontend doesn't support shared contexts.
+  fn set_hw_shared_context(&mut self) -> Result<()> {
+    unsafe { self.cmd(RETRO_ENVIRONMENT_SET_HW_SHARED_CONTEXT, ()) }
+  }
+
+  /// Gives the core control over the network packets sent during a multiplayer session.
+  /// The frontend will call the [`crate::retro::NetPacketCore`] functions of the core.
+  ///
+  /// If `protocol_version` is set, the frontend uses it instead of the core version
+  /// to decide whether players can communicate.
+  ///
+  /// Should be called in either `retro_init` or `retro_load_game`, but not both.
+  fn set_netpacket_interface(&mut self, protocol_version: Option<&'static CStr>) -> Result<()>;
+
+  /// Asks the frontend to report the occupancy of its audio buffer before every call to
+  /// [`crate::retro::Core::run`]. The frontend will call
+  /// [`crate::retro::AudioBufferStatusCore::audio_buffer_status`] of the core.
+  fn set_audio_buffer_status_callback(&mut self) -> Result<()>;
+
+  /// Stops the frontend from reporting the occupancy of its audio buffer, undoing
+  /// [`LoadGame::set_audio_buffer_status_callback`]. This passes a null callback to the frontend.
+  fn disable_audio_buffer_status_callback(&mut self) -> Result<()> {
+    unsafe {
+      if self.get_ptr()(
+        RETRO_ENVIRONMENT_SET_AUDIO_BUFFER_STATUS_CALLBACK,
+        core::ptr::null_mut(),
+      ) {
+        Ok(())
+      } else {
+        Err(CommandError::new())
+      }
+    }
+  }
 }
 
 #[non_exhaustive]
@@ -253,7 +821,187 @@ pub enum Origin {
   BottomRight,
 }
 
+/// Describes what a save state requested by the frontend will be used for.
+/// See [`Environment::get_savestate_context`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SavestateContext {
+  /// A standard save state written to disk.
+  #[default]
+  Normal,
+  /// The same instance will load the save state, so it may contain internal pointers.
+  RunaheadSameInstance,
+  /// The same binary will load the save state. Anything that slows down saving or loading can
+  /// be skipped, but internal pointers must not be stored.
+  RunaheadSameBinary,
+  /// The save state is used for rollback netplay, so anything that increases bandwidth usage
+  /// unnecessarily should be skipped.
+  Rollback,
+}
+
+impl From<c_int> for SavestateContext {
+  /// Unknown values are treated as [`SavestateContext::Normal`].
+  fn from(value: c_int) -> Self {
+    use retro_savestate_context::*;
+    match value {
+      x if x == RETRO_SAVESTATE_CONTEXT_RUNAHEAD_SAME_INSTANCE as c_int => {
+        Self::RunaheadSameInstance
+      }
+      x if x == RETRO_SAVESTATE_CONTEXT_RUNAHEAD_SAME_BINARY as c_int => Self::RunaheadSameBinary,
+      x if x == RETRO_SAVESTATE_CONTEXT_ROLLBACK_NETPLAY as c_int => Self::Rollback,
+      _ => Self::Normal,
+    }
+  }
+}
+
+/// A fast-forwarding state requested by the core. See [`Environment::set_fastforwarding_override`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FastforwardOverride {
+  /// The speed multiplier to use while fast-forwarding. Values between 0 and 1 uncap the speed,
+  /// and negative values let the frontend choose.
+  pub ratio: f32,
+  /// Whether the frontend should fast-forward.
+  pub fastforward: bool,
+  /// Whether the frontend should notify the user about the change.
+  pub notification: bool,
+  /// If true, the user can't toggle fast-forwarding until the core lifts the override.
+  pub inhibit_toggle: bool,
+}
+
+impl From<FastforwardOverride> for retro_fastforwarding_override {
+  fn from(over: FastforwardOverride) -> Self {
+    Self {
+      ratio: over.ratio,
+      fastforward: over.fastforward,
+      notification: over.notification,
+      inhibit_toggle: over.inhibit_toggle,
+    }
+  }
+}
+
+/// RetroArch's private `RETRO_ENVIRONMENT_GET_CLEAR_ALL_THREAD_WAITS_CB` command, which isn't
+/// part of `libretro.h`.
+const RETRO_ENVIRONMENT_GET_CLEAR_ALL_THREAD_WAITS_CB: c_uint = 0x800000 | 3;
+
+/// A callback that lets threaded cores keep the frontend from deadlocking. See
+/// [`Environment::get_clear_all_thread_waits_callback`].
+///
+/// Threaded cores often block their emulation thread on the frontend, e.g. on its audio driver.
+/// When the frontend pauses or saves a state, those waits never end, and the frontend ends up
+/// waiting on a core thread that waits on it. Cores should call [`ClearThreadWaits::clear`]
+/// before blocking their threads for such operations, and [`ClearThreadWaits::restore`] once
+/// they're done.
+#[derive(Clone, Copy, Debug)]
+pub struct ClearThreadWaits(non_null_retro_environment_t);
+
+impl ClearThreadWaits {
+  /// Releases every wait of the frontend on the core's threads. Returns `false` on failure.
+  pub fn clear(&self) -> bool {
+    unsafe { (self.0)(1, core::ptr::null_mut()) }
+  }
+
+  /// Lets the frontend wait on the core's threads again. Returns `false` on failure.
+  pub fn restore(&self) -> bool {
+    unsafe { (self.0)(0, core::ptr::null_mut()) }
+  }
+}
+
+/// Limitations of the core's save states. See [`Environment::set_serialization_quirks`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SerializationQuirks(u64);
+
+impl SerializationQuirks {
+  /// Save states are incomplete, and only work for rewind or runahead within a session.
+  pub const INCOMPLETE: Self = Self(RETRO_SERIALIZATION_QUIRK_INCOMPLETE as u64);
+
+  /// Save states only work once the core ran at least one frame.
+  pub const MUST_INITIALIZE: Self = Self(RETRO_SERIALIZATION_QUIRK_MUST_INITIALIZE as u64);
+
+  /// The size of save states may change between saves.
+  pub const CORE_VARIABLE_SIZE: Self = Self(RETRO_SERIALIZATION_QUIRK_CORE_VARIABLE_SIZE as u64);
+
+  /// Set by the frontend if it supports [`SerializationQuirks::CORE_VARIABLE_SIZE`].
+  pub const FRONT_VARIABLE_SIZE: Self = Self(RETRO_SERIALIZATION_QUIRK_FRONT_VARIABLE_SIZE as u64);
+
+  /// Save states can't be loaded after the core is restarted.
+  pub const SINGLE_SESSION: Self = Self(RETRO_SERIALIZATION_QUIRK_SINGLE_SESSION as u64);
+
+  /// Save states can't be loaded on a platform with a different endianness.
+  pub const ENDIAN_DEPENDENT: Self = Self(RETRO_SERIALIZATION_QUIRK_ENDIAN_DEPENDENT as u64);
+
+  /// Save states can't be loaded on a different platform, for reasons other than endianness.
+  pub const PLATFORM_DEPENDENT: Self = Self(RETRO_SERIALIZATION_QUIRK_PLATFORM_DEPENDENT as u64);
+
+  pub const fn new(quirks: u64) -> Self {
+    Self(quirks)
+  }
+
+  pub fn into_inner(self) -> u64 {
+    self.0
+  }
+
+  /// Returns true if all the quirks in `other` are set.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for SerializationQuirks {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for SerializationQuirks {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+impl From<SerializationQuirks> for u64 {
+  fn from(quirks: SerializationQuirks) -> Self {
+    quirks.into_inner()
+  }
+}
+
 pub trait GetAvInfo: Environment {
+  /// Sets the pixel format of the frames the core uploads, chosen at runtime, and returns the
+  /// token of the accepted format. Match on it to get the typed [`Format`] that
+  /// [`crate::retro::Callbacks::upload_video_frame`] takes.
+  fn set_pixel_format(&mut self, format: PixelFormat) -> EnvResult<AnyFormat> {
+    unsafe { self.set_checked(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &c_int::from(format)) }?;
+    Ok(AnyFormat::new(format))
+  }
+
+  /// Tries each format of `preferred` in order, and returns the token of the first one the
+  /// frontend accepts, or gives `current_format` back if it rejected all of them.
+  /// [`PixelFormat::FALLBACK_ORDER`] is a good default.
+  ///
+  /// Logs a warning when the frontend rejects the first format, since the core must then convert
+  /// its frames to the returned format.
+  fn set_best_pixel_format<F>(
+    &mut self,
+    current_format: Format<F>,
+    preferred: &[PixelFormat],
+  ) -> core::result::Result<AnyFormat, Format<F>> {
+    let format = preferred
+      .iter()
+      .find_map(|&format| GetAvInfo::set_pixel_format(self, format).ok());
+    let accepted = format.as_ref().map(AnyFormat::pixel_format);
+    if let Some(first) = preferred.first().filter(|&&first| Some(first) != accepted) {
+      crate::retro_warn!(
+        self.get_log_interface().ok(),
+        "the frontend rejected pixel format {:?}, using {:?}",
+        first,
+        accepted
+      );
+    }
+    format.ok_or(current_format)
+  }
+
   #[allow(unused_variables)]
   fn set_pixel_format_0rgb1555<F>(
     &mut self,
@@ -325,7 +1073,7 @@ impl<T: Environment> Deinit for T {}
 unsafe fn with_ref(
   cb: non_null_retro_environment_t,
   cmd: c_uint,
-  data: &impl CommandData,
+  data: &(impl CommandData + ?Sized),
 ) -> Result<()> {
   if cb(cmd, data as *const _ as *mut c_void) {
     Ok(())
@@ -346,6 +1094,79 @@ unsafe fn with_mut(
   }
 }
 
+static OPTION_DISPLAY_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// A function exported through [`SetEnvironment::set_proc_address_callback`], with its name.
+pub type ProcAddress = (&'static CStr, retro_proc_address_t);
+
+static PROC_ADDRESSES: std::sync::Mutex<&'static [ProcAddress]> = std::sync::Mutex::new(&[]);
+
+unsafe extern "C" fn get_proc_address(sym: *const c_char) -> retro_proc_address_t {
+  if sym.is_null() {
+    return None;
+  }
+  let sym = CStr::from_ptr(sym);
+  let table = *PROC_ADDRESSES
+    .lock()
+    .unwrap_or_else(std::sync::PoisonError::into_inner);
+  table
+    .iter()
+    .find(|(name, _)| *name == sym)
+    .and_then(|(_, function)| *function)
+}
+
+/// Builds the `&'static [ProcAddress]` table of [`SetEnvironment::set_proc_address_callback`]
+/// from `"name" => function` pairs, erasing the signature of each `extern "C"` function.
+///
+/// The pairs must be wrapped in `unsafe { ... }`, and each function must be named by its path,
+/// since the macro can't check that it's an `extern "C"` function.
+///
+/// # Safety
+/// Each function must be an `extern "C"` function. The frontend gets the functions as
+/// [`retro_proc_address_t`] and must cast each one back to its real signature before calling it,
+/// so the names and signatures form an API contract between the core and the frontend. Calling a
+/// function through any other signature is undefined behavior.
+///
+/// ```
+/// use libretro_rs::proc_addresses;
+/// use libretro_rs::retro::env::ProcAddress;
+///
+/// extern "C" fn reload_shaders() -> bool {
+///   true
+/// }
+///
+/// // SAFETY: `reload_shaders` is `extern "C"`, and documented as `bool (*)(void)`.
+/// static EXTENSIONS: &[ProcAddress] = proc_addresses![unsafe {
+///   "my_core_reload_shaders" => reload_shaders,
+/// }];
+/// assert_eq!(EXTENSIONS[0].0.to_str(), Ok("my_core_reload_shaders"));
+/// ```
+///
+/// Arbitrary expressions, such as a null pointer, are rejected:
+///
+/// ```compile_fail
+/// use libretro_rs::proc_addresses;
+/// use libretro_rs::retro::env::ProcAddress;
+///
+/// static EXTENSIONS: &[ProcAddress] = proc_addresses![unsafe {
+///   "my_core_null" => core::ptr::null::<u8>(),
+/// }];
+/// ```
+#[macro_export]
+macro_rules! proc_addresses {
+  ( unsafe { $( $name:literal => $function:path ),* $(,)? } ) => {
+    &[ $( (
+      match ::core::ffi::CStr::from_bytes_with_nul(::core::concat!($name, "\0").as_bytes()) {
+        Ok(name) => name,
+        Err(_) => panic!("proc address names can't contain NUL"),
+      },
+      Some(unsafe {
+        ::core::mem::transmute::<*const (), unsafe extern "C" fn()>($function as *const ())
+      }),
+    ) ),* ]
+  };
+}
+
 pub extern "C" fn null_environment(_cmd: c_uint, _data: *mut c_void) -> bool {
   false
 }
@@ -364,15 +1185,416 @@ impl CommandData for () {}
 impl CommandData for bool {}
 impl CommandData for c_int {}
 impl CommandData for c_uint {}
+impl CommandData for u64 {}
 impl CommandData for Option<&c_char> {}
 impl CommandData for Option<&c_void> {}
+impl CommandData for retro_environment_t {}
 impl CommandData for retro_hw_render_callback {}
+impl CommandData for [retro_input_descriptor] {}
+impl CommandData for [retro_system_content_info_override] {}
+impl CommandData for retro_audio_buffer_status_callback {}
+impl CommandData for [retro_controller_info] {}
+impl CommandData for retro_core_option_display {}
+impl CommandData for retro_core_options_intl {}
+impl CommandData for retro_core_options_v2_intl {}
+impl CommandData for retro_fastforwarding_override {}
+impl CommandData for retro_framebuffer {}
 impl CommandData for retro_game_geometry {}
+impl CommandData for Option<&retro_game_info_ext> {}
+impl CommandData for retro_get_proc_address_interface {}
 impl CommandData for GameGeometry {}
 impl CommandData for retro_log_callback {}
 impl CommandData for retro_message {}
+impl CommandData for retro_microphone_interface {}
+impl CommandData for retro_netpacket_callback {}
 impl CommandData for Message {}
+impl CommandData for MessageExt {}
 impl CommandData for retro_pixel_format {}
+impl CommandData for retro_perf_callback {}
+impl CommandData for retro_rumble_interface {}
 impl CommandData for retro_system_av_info {}
 impl CommandData for SystemAVInfo {}
 impl CommandData for retro_variable {}
+impl CommandData for [retro_variable] {}
+impl CommandData for retro_vfs_interface_info {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use core::sync::atomic::AtomicUsize;
+
+  static DISPLAY_CALLS: AtomicUsize = AtomicUsize::new(0);
+  static V0_LOG_QUERIES: AtomicUsize = AtomicUsize::new(0);
+  static PROC_LOOKUP: std::sync::Mutex<retro_get_proc_address_t> = std::sync::Mutex::new(None);
+  static EXTENSION_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn proc_address_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_PROC_ADDRESS_CALLBACK {
+      return false;
+    }
+    let iface = &*(data as *const retro_get_proc_address_interface);
+    *PROC_LOOKUP.lock().unwrap() = iface.get_proc_address;
+    true
+  }
+
+  unsafe extern "C" fn extension_function() {
+    EXTENSION_CALLS.fetch_add(1, Ordering::SeqCst);
+  }
+
+  unsafe extern "C" fn add_one(value: c_int) -> c_int {
+    value + 1
+  }
+
+  #[test]
+  fn test_proc_addresses_macro() {
+    static TABLE: &[ProcAddress] = crate::proc_addresses![unsafe {
+      "example_extension" => extension_function,
+      "example_add_one" => add_one,
+    }];
+    assert_eq!(TABLE.len(), 2);
+    assert_eq!(TABLE[1].0.to_bytes(), b"example_add_one");
+    let add_one: unsafe extern "C" fn(c_int) -> c_int =
+      unsafe { core::mem::transmute(TABLE[1].1.unwrap()) };
+    assert_eq!(unsafe { add_one(41) }, 42);
+  }
+
+  #[test]
+  fn test_proc_address_lookup() {
+    static TABLE: [ProcAddress; 1] = [(
+      unsafe { CStr::from_bytes_with_nul_unchecked(b"example_extension\0") },
+      Some(extension_function),
+    )];
+    let mut env: non_null_retro_environment_t = proc_address_environment;
+    assert!(env.set_proc_address_callback(&TABLE).is_ok());
+    let lookup = PROC_LOOKUP.lock().unwrap().unwrap();
+    unsafe {
+      let function = lookup(b"example_extension\0".as_ptr().cast()).unwrap();
+      function();
+      assert!(lookup(b"missing\0".as_ptr().cast()).is_none());
+      assert!(lookup(core::ptr::null()).is_none());
+    }
+    assert_eq!(EXTENSION_CALLS.load(Ordering::SeqCst), 1);
+  }
+
+  static THREAD_WAITS: std::sync::Mutex<Vec<c_uint>> = std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn clear_thread_waits(clear_threads: c_uint, _data: *mut c_void) -> bool {
+    THREAD_WAITS.lock().unwrap().push(clear_threads);
+    true
+  }
+
+  unsafe extern "C" fn thread_waits_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_GET_CLEAR_ALL_THREAD_WAITS_CB {
+      return false;
+    }
+    *(data as *mut retro_environment_t) = Some(clear_thread_waits);
+    true
+  }
+
+  #[test]
+  fn test_clear_all_thread_waits_callback() {
+    let env: non_null_retro_environment_t = thread_waits_environment;
+    let waits = env.get_clear_all_thread_waits_callback().unwrap();
+    assert!(waits.clear());
+    assert!(waits.restore());
+    assert_eq!(*THREAD_WAITS.lock().unwrap(), [1, 0]);
+    let unsupported: non_null_retro_environment_t = rgb1555_only_environment;
+    assert!(unsupported.get_clear_all_thread_waits_callback().is_none());
+  }
+
+  static FASTFORWARD_OVERRIDES: std::sync::Mutex<Vec<(f32, bool, bool, bool)>> =
+    std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn fastforward_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_FASTFORWARDING_OVERRIDE {
+      return false;
+    }
+    let over = &*(data as *const retro_fastforwarding_override);
+    let fields = (
+      over.ratio,
+      over.fastforward,
+      over.notification,
+      over.inhibit_toggle,
+    );
+    FASTFORWARD_OVERRIDES.lock().unwrap().push(fields);
+    true
+  }
+
+  #[test]
+  fn test_set_fastforwarding_override() {
+    let over = FastforwardOverride {
+      ratio: 4.0,
+      fastforward: true,
+      notification: false,
+      inhibit_toggle: true,
+    };
+    let mut env: non_null_retro_environment_t = fastforward_environment;
+    assert!(env.set_fastforwarding_override(over).is_ok());
+    assert_eq!(
+      *FASTFORWARD_OVERRIDES.lock().unwrap(),
+      [(4.0, true, false, true)]
+    );
+    let mut unsupported: non_null_retro_environment_t = rgb1555_only_environment;
+    assert!(unsupported.set_fastforwarding_override(over).is_err());
+  }
+
+  static VULKAN_INTERFACE: retro_hw_render_interface = retro_hw_render_interface {
+    interface_type: retro_hw_render_interface_type::RETRO_HW_RENDER_INTERFACE_VULKAN,
+    interface_version: 5,
+  };
+
+  unsafe extern "C" fn hw_interface_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_GET_HW_RENDER_INTERFACE {
+      return false;
+    }
+    *(data as *mut *const retro_hw_render_interface) = &VULKAN_INTERFACE;
+    true
+  }
+
+  #[test]
+  fn test_get_hw_render_interface() {
+    let env: non_null_retro_environment_t = hw_interface_environment;
+    let iface = env.get_hw_render_interface().unwrap();
+    assert_eq!(iface.interface_type(), HwRenderInterfaceType::Vulkan);
+    assert_eq!(iface.interface_version(), 5);
+    assert_eq!(iface.as_ptr(), &VULKAN_INTERFACE as *const _);
+    let unsupported: non_null_retro_environment_t = rgb1555_only_environment;
+    assert!(unsupported.get_hw_render_interface().is_none());
+    assert_eq!(
+      HwRenderInterfaceType::from(0x7FFF_FFFF),
+      HwRenderInterfaceType::Unknown(0x7FFF_FFFF)
+    );
+  }
+
+  unsafe extern "C" fn preferred_vulkan_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_GET_PREFERRED_HW_RENDER {
+      return false;
+    }
+    *(data as *mut c_uint) = retro_hw_context_type::RETRO_HW_CONTEXT_VULKAN as c_uint;
+    true
+  }
+
+  #[test]
+  fn test_get_preferred_hw_render() {
+    let env: non_null_retro_environment_t = preferred_vulkan_environment;
+    assert_eq!(env.get_preferred_hw_render(), Some(HwContextType::Vulkan));
+    let unsupported: non_null_retro_environment_t = rgb1555_only_environment;
+    assert_eq!(unsupported.get_preferred_hw_render(), None);
+    assert_eq!(HwContextType::from(1), HwContextType::OpenGL);
+    assert_eq!(HwContextType::from(42), HwContextType::Unknown(42));
+  }
+
+  static CONTENT_OVERRIDES: std::sync::Mutex<Vec<(String, bool, bool)>> =
+    std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn content_override_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_CONTENT_INFO_OVERRIDE {
+      return false;
+    }
+    let mut over = data as *const retro_system_content_info_override;
+    while !(*over).extensions.is_null() {
+      let extensions = CStr::from_ptr((*over).extensions)
+        .to_str()
+        .unwrap()
+        .to_owned();
+      let entry = (extensions, (*over).need_fullpath, (*over).persistent_data);
+      CONTENT_OVERRIDES.lock().unwrap().push(entry);
+      over = over.add(1);
+    }
+    true
+  }
+
+  #[test]
+  fn test_set_content_info_override() {
+    let info = SystemInfo::new(
+      CStr::from_bytes_with_nul(b"core\0").unwrap(),
+      CStr::from_bytes_with_nul(b"1.0\0").unwrap(),
+      Extensions::new(CStr::from_bytes_with_nul(b"cue|chd|sms\0").unwrap()),
+    )
+    .with_need_full_path();
+    let sms = CStr::from_bytes_with_nul(b"sms\0").unwrap();
+    let overrides = [ContentInfoOverride::new(Extensions::new(sms)).with_persistent_data()];
+
+    let mut env: non_null_retro_environment_t = content_override_environment;
+    assert!(env.set_content_info_override(&overrides));
+    assert_eq!(
+      *CONTENT_OVERRIDES.lock().unwrap(),
+      [("sms".to_owned(), false, true)]
+    );
+
+    let mut unsupported: non_null_retro_environment_t = rgb1555_only_environment;
+    let overridden = unsupported.set_content_info_override(&overrides);
+    assert!(!overridden);
+    let need_fullpath = match overridden {
+      true => overrides[0].need_fullpath(),
+      false => info.need_fullpath(),
+    };
+    assert!(
+      need_fullpath,
+      "the core should fall back to the global flags"
+    );
+  }
+
+  static PIXEL_FORMAT_ATTEMPTS: std::sync::Mutex<Vec<c_int>> = std::sync::Mutex::new(Vec::new());
+
+  unsafe extern "C" fn rgb1555_only_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_SET_PIXEL_FORMAT {
+      return false;
+    }
+    let format = *(data as *const c_int);
+    PIXEL_FORMAT_ATTEMPTS.lock().unwrap().push(format);
+    format == c_int::from(PixelFormat::RGB1555)
+  }
+
+  #[test]
+  fn test_set_best_pixel_format() {
+    let mut env: non_null_retro_environment_t = rgb1555_only_environment;
+    let format = Format::<ORGB1555>(PhantomData);
+    let format =
+      GetAvInfo::set_best_pixel_format(&mut env, format, &PixelFormat::FALLBACK_ORDER).unwrap();
+    assert_eq!(format, AnyFormat::ORGB1555(Format(PhantomData)));
+    assert_eq!(format.pixel_format(), PixelFormat::RGB1555);
+    assert_eq!(
+      *PIXEL_FORMAT_ATTEMPTS.lock().unwrap(),
+      PixelFormat::FALLBACK_ORDER.map(c_int::from)
+    );
+    let AnyFormat::ORGB1555(format) = format else {
+      panic!("RGB1555 should be accepted")
+    };
+    let preferred = [PixelFormat::XRGB8888, PixelFormat::RGB565];
+    assert!(GetAvInfo::set_best_pixel_format(&mut env, format, &preferred).is_err());
+  }
+
+  unsafe extern "C" fn no_xrgb8888_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    cmd == RETRO_ENVIRONMENT_SET_PIXEL_FORMAT
+      && *(data as *const c_int) != c_int::from(PixelFormat::XRGB8888)
+  }
+
+  #[test]
+  fn test_typed_pixel_format_fallback() {
+    let mut env: non_null_retro_environment_t = no_xrgb8888_environment;
+    let format = Format::<ORGB1555>(PhantomData);
+    let format = match GetAvInfo::set_pixel_format_xrgb8888(&mut env, format) {
+      Ok(_) => panic!("XRGB8888 should be rejected"),
+      Err(format) => format,
+    };
+    let format = GetAvInfo::set_pixel_format_rgb565(&mut env, format).unwrap();
+    let format =
+      GetAvInfo::set_best_pixel_format(&mut env, format, &PixelFormat::FALLBACK_ORDER).unwrap();
+    assert_eq!(format, AnyFormat::RGB565(Format(PhantomData)));
+  }
+
+  #[test]
+  fn test_set_pixel_format_at_runtime() {
+    fn use_rgb565(env: &mut impl LoadGame) -> EnvResult<AnyFormat> {
+      env.set_pixel_format(PixelFormat::RGB565)
+    }
+    let mut mock = crate::retro::testing::MockEnvironment::new();
+    let format = use_rgb565(&mut mock).unwrap();
+    assert_eq!(format, AnyFormat::RGB565(Format(PhantomData)));
+    assert_eq!(mock.pixel_format(), Some(PixelFormat::RGB565));
+
+    let mut env: non_null_retro_environment_t = no_xrgb8888_environment;
+    assert_eq!(
+      GetAvInfo::set_pixel_format(&mut env, PixelFormat::XRGB8888),
+      Err(EnvError::Failed)
+    );
+  }
+
+  unsafe extern "C" fn v0_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION => {
+        *(data as *mut c_uint) = 0;
+        true
+      }
+      RETRO_ENVIRONMENT_SET_CORE_OPTIONS_DISPLAY => {
+        DISPLAY_CALLS.fetch_add(1, Ordering::SeqCst);
+        true
+      }
+      RETRO_ENVIRONMENT_GET_LOG_INTERFACE => {
+        V0_LOG_QUERIES.fetch_add(1, Ordering::SeqCst);
+        false
+      }
+      _ => false,
+    }
+  }
+
+  #[test]
+  fn test_savestate_context_decode() {
+    use retro_savestate_context::*;
+    let decode = |ctx: retro_savestate_context| SavestateContext::from(ctx as c_int);
+    assert_eq!(
+      decode(RETRO_SAVESTATE_CONTEXT_NORMAL),
+      SavestateContext::Normal
+    );
+    assert_eq!(
+      decode(RETRO_SAVESTATE_CONTEXT_RUNAHEAD_SAME_INSTANCE),
+      SavestateContext::RunaheadSameInstance
+    );
+    assert_eq!(
+      decode(RETRO_SAVESTATE_CONTEXT_RUNAHEAD_SAME_BINARY),
+      SavestateContext::RunaheadSameBinary
+    );
+    assert_eq!(
+      decode(RETRO_SAVESTATE_CONTEXT_ROLLBACK_NETPLAY),
+      SavestateContext::Rollback
+    );
+    assert_eq!(
+      decode(RETRO_SAVESTATE_CONTEXT_UNKNOWN),
+      SavestateContext::Normal
+    );
+    let env: non_null_retro_environment_t = null_environment;
+    assert_eq!(env.get_savestate_context(), SavestateContext::Normal);
+  }
+
+  #[test]
+  fn test_set_option_visible_on_version_0() {
+    let mut env: non_null_retro_environment_t = v0_environment;
+    let key = CStr::from_bytes_with_nul(b"chip8_speed\0").unwrap();
+    assert_eq!(env.get_core_options_version(), 0);
+    assert!(env.set_option_visible(&key, false).is_ok());
+    assert!(env.set_option_visible(&key, true).is_ok());
+    assert_eq!(DISPLAY_CALLS.load(Ordering::SeqCst), 0);
+    assert!(OPTION_DISPLAY_WARNED.load(Ordering::SeqCst));
+    assert_eq!(
+      V0_LOG_QUERIES.load(Ordering::SeqCst),
+      1,
+      "warned once through the frontend's logger"
+    );
+  }
+
+  unsafe extern "C" fn overscan_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_GET_OVERSCAN => {
+        *(data as *mut bool) = true;
+        true
+      }
+      _ => false,
+    }
+  }
+
+  #[test]
+  #[allow(deprecated)]
+  fn test_get_overscan() {
+    let env: non_null_retro_environment_t = overscan_environment;
+    assert!(env.get_overscan());
+    let env: non_null_retro_environment_t = null_environment;
+    assert!(!env.get_overscan());
+  }
+
+  unsafe extern "C" fn disk_control_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    if cmd != RETRO_ENVIRONMENT_GET_DISK_CONTROL_INTERFACE_VERSION {
+      return false;
+    }
+    *(data as *mut c_uint) = 1;
+    true
+  }
+
+  #[test]
+  fn test_get_disk_control_interface_version() {
+    let env: non_null_retro_environment_t = disk_control_environment;
+    assert_eq!(env.get_disk_control_interface_version(), 1);
+    let env: non_null_retro_environment_t = null_environment;
+    assert_eq!(env.get_disk_control_interface_version(), 0);
+  }
+}
@@ -29,7 +29,7 @@ impl<T> Display for LoadGameError<T> {
 
 impl<T> Error for LoadGameError<T> {}
 
-macro_rules! retro_error {
+macro_rules! define_error {
   ($name:ident, $description:expr) => {
     #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
     pub struct $name(());
@@ -56,8 +56,80 @@ macro_rules! retro_error {
   };
 }
 
-retro_error!(CoreError, "a libretro API function call did not succeed");
-retro_error!(
+/// An error returned by a [`crate::retro::Core`] method, optionally with a [`LoadError`] that
+/// explains why a game failed to load.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CoreError(Option<LoadError>);
+
+impl CoreError {
+  pub fn new() -> Self {
+    Self(None)
+  }
+
+  /// The reason a game failed to load, if the core gave one.
+  pub fn load_error(&self) -> Option<LoadError> {
+    self.0
+  }
+}
+
+impl Display for CoreError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    match self.0 {
+      Some(reason) => write!(f, "{reason}"),
+      None => write!(f, "a libretro API function call did not succeed"),
+    }
+  }
+}
+
+impl Error for CoreError {}
+
+impl<T> From<::core::result::Result<T, Box<dyn Error>>> for CoreError {
+  fn from(_value: ::core::result::Result<T, Box<dyn Error>>) -> Self {
+    Self::new()
+  }
+}
+
+/// Why a game failed to load. The frontend shows it to the player when
+/// [`crate::retro::Core::load_game`] returns it as a [`CoreError`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LoadError {
+  /// The game isn't in a format the core supports.
+  UnsupportedFormat,
+  /// The game data is truncated or corrupt.
+  CorruptData,
+  /// A BIOS or firmware file the core needs is missing from the system directory.
+  MissingBios,
+  /// The game file couldn't be read.
+  Io,
+}
+
+impl Display for LoadError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    match self {
+      LoadError::UnsupportedFormat => write!(f, "unsupported game format"),
+      LoadError::CorruptData => write!(f, "the game data is corrupt"),
+      LoadError::MissingBios => write!(f, "a required BIOS file is missing"),
+      LoadError::Io => write!(f, "the game file couldn't be read"),
+    }
+  }
+}
+
+impl Error for LoadError {}
+
+impl From<LoadError> for CoreError {
+  fn from(reason: LoadError) -> Self {
+    Self(Some(reason))
+  }
+}
+
+impl From<std::io::Error> for CoreError {
+  fn from(_value: std::io::Error) -> Self {
+    LoadError::Io.into()
+  }
+}
+
+define_error!(
   CommandError,
   "a libretro environment command did not succeed"
 );
@@ -73,3 +145,112 @@ impl<T> From<crate::retro::av::pixel::Format<T>> for CoreError {
     Self::new()
   }
 }
+
+/// Describes why an environment command failed.
+///
+/// The environment callback only returns `false` on failure, both when the frontend doesn't
+/// recognize a command and when it refuses the request, and most commands have no way to ask
+/// which one applies. So failures are reported as a single [`EnvError::Failed`]. See
+/// [`crate::retro::env::Environment::get_checked`] and
+/// [`crate::retro::env::Environment::set_checked`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EnvError {
+  /// The frontend returned `false`, either because it doesn't recognize the command or because
+  /// it refused the request.
+  Failed,
+}
+
+impl Display for EnvError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    match self {
+      EnvError::Failed => write!(f, "the frontend failed this environment command"),
+    }
+  }
+}
+
+impl Error for EnvError {}
+
+impl From<EnvError> for CommandError {
+  fn from(_value: EnvError) -> Self {
+    Self::new()
+  }
+}
+
+impl From<EnvError> for CoreError {
+  fn from(_value: EnvError) -> Self {
+    Self::new()
+  }
+}
+
+/// Describes why [`crate::retro::NetPacketSender::send`] couldn't send a packet.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NetPacketError {
+  /// The packet is larger than [`crate::retro::NETPACKET_MAX_SIZE`].
+  TooLarge,
+  /// The multiplayer session has stopped, so the frontend's send function isn't valid anymore.
+  Stopped,
+}
+
+impl Display for NetPacketError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    match self {
+      NetPacketError::TooLarge => write!(f, "the packet is larger than 64 KiB"),
+      NetPacketError::Stopped => write!(f, "the multiplayer session has stopped"),
+    }
+  }
+}
+
+impl Error for NetPacketError {}
+
+/// A callback that the frontend must set before running the core. See [`InstanceError`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CallbackKind {
+  VideoRefresh,
+  AudioSample,
+  AudioSampleBatch,
+  InputPoll,
+  InputState,
+}
+
+/// Describes how the frontend misused the `libretro` API, as reported by the `try_*` methods of
+/// [`crate::retro::Instance`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstanceError {
+  /// The frontend didn't call `retro_set_environment`.
+  MissingEnvironment,
+  /// The frontend didn't set the given callback.
+  MissingCallback(CallbackKind),
+  /// The call requires a loaded game, but the frontend didn't load one.
+  NoSystemLoaded,
+  /// The frontend called the API functions in the wrong order, e.g. loading a game before
+  /// `retro_init` or twice in a row.
+  CalledOutOfOrder,
+  /// The frontend passed a buffer of `size` bytes where the core needs `expected` bytes, e.g. to
+  /// `retro_serialize`.
+  BufferTooSmall { size: usize, expected: usize },
+}
+
+impl Display for InstanceError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+    match self {
+      InstanceError::MissingEnvironment => write!(f, "the frontend did not set the environment"),
+      InstanceError::MissingCallback(kind) => {
+        write!(f, "the frontend did not set the {kind:?} callback")
+      }
+      InstanceError::NoSystemLoaded => write!(f, "no game is loaded"),
+      InstanceError::CalledOutOfOrder => {
+        write!(f, "the frontend called a libretro function out of order")
+      }
+      InstanceError::BufferTooSmall { size, expected } => write!(
+        f,
+        "the frontend passed a {size} byte buffer, but {expected} bytes are needed"
+      ),
+    }
+  }
+}
+
+impl Error for InstanceError {}
@@ -1,3 +1,4 @@
+use crate::ffi::retro_system_content_info_override;
 use ::core::ffi::*;
 
 /// A list of file extensions encoded in a pipe-delimited static C string,
@@ -19,8 +20,58 @@ impl<'a> Extensions<'a> {
   pub fn as_ptr(&self) -> *const c_char {
     self.0.as_ptr()
   }
+
+  /// Like [`Extensions::new`], but checks that every extension is non-empty and doesn't start with
+  /// a dot, since libretro expects e.g. `"gb"` and not `".gb"`. Frontends silently refuse to load
+  /// content whose extension doesn't match.
+  pub fn try_new<T: AsRef<CStr> + ?Sized>(str: &'a T) -> Result<Self, InvalidExtensionError> {
+    let extensions = Self::new(str);
+    let text = extensions
+      .0
+      .to_str()
+      .map_err(|_| InvalidExtensionError { index: 0 })?;
+    if text.is_empty() {
+      return Ok(extensions);
+    }
+    match text
+      .split('|')
+      .position(|ext| ext.is_empty() || ext.starts_with('.'))
+    {
+      Some(index) => Err(InvalidExtensionError { index }),
+      None => Ok(extensions),
+    }
+  }
+
+  /// The individual extensions, without the separators.
+  pub fn iter(&self) -> impl Iterator<Item = &'a str> {
+    self
+      .0
+      .to_str()
+      .unwrap_or_default()
+      .split('|')
+      .filter(|ext| !ext.is_empty())
+  }
+}
+
+/// The error returned by [`Extensions::try_new`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InvalidExtensionError {
+  /// The index of the first invalid extension.
+  pub index: usize,
+}
+
+impl core::fmt::Display for InvalidExtensionError {
+  fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+    write!(
+      f,
+      "file extension {} is empty or starts with a dot",
+      self.index
+    )
+  }
 }
 
+impl std::error::Error for InvalidExtensionError {}
+
 impl AsRef<CStr> for Extensions<'_> {
   fn as_ref(&self) -> &CStr {
     self.as_c_str()
@@ -33,6 +84,65 @@ impl From<Extensions<'_>> for *const c_char {
   }
 }
 
+/// Overrides the [`crate::retro::SystemInfo`] content flags for some extensions. See
+/// [`crate::retro::env::SetEnvironment::set_content_info_override`].
+/// Rust interface for [`retro_system_content_info_override`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug)]
+pub struct ContentInfoOverride(retro_system_content_info_override);
+
+impl ContentInfoOverride {
+  /// Creates an override for `extensions` that doesn't need the full path, and doesn't need the
+  /// content to persist after loading.
+  pub fn new(extensions: Extensions<'static>) -> Self {
+    Self(retro_system_content_info_override {
+      extensions: extensions.as_ptr(),
+      need_fullpath: false,
+      persistent_data: false,
+    })
+  }
+
+  pub fn with_need_fullpath(mut self) -> Self {
+    self.0.need_fullpath = true;
+    self
+  }
+
+  /// Asks the frontend to keep the content data in memory until the game is unloaded, so the core
+  /// can use it without copying it. Only applies to content loaded without the full path.
+  pub fn with_persistent_data(mut self) -> Self {
+    self.0.persistent_data = true;
+    self
+  }
+
+  pub fn extensions(&self) -> Extensions<'static> {
+    Extensions::new(unsafe { CStr::from_ptr(self.0.extensions) })
+  }
+
+  pub fn need_fullpath(&self) -> bool {
+    self.0.need_fullpath
+  }
+
+  pub fn persistent_data(&self) -> bool {
+    self.0.persistent_data
+  }
+}
+
+/// Builds the array for [`crate::retro::env::SetEnvironment::set_content_info_override`],
+/// terminated by a null entry.
+pub(crate) fn content_info_override_array(
+  overrides: &[ContentInfoOverride],
+) -> Vec<retro_system_content_info_override> {
+  overrides
+    .iter()
+    .map(|over| over.0)
+    .chain(Some(retro_system_content_info_override {
+      extensions: core::ptr::null(),
+      need_fullpath: false,
+      persistent_data: false,
+    }))
+    .collect()
+}
+
 /// Converts a list of file extension string literals into an [Extensions] value.
 ///
 /// # Examples
@@ -52,3 +162,48 @@ macro_rules! ext {
     Extensions::new(c_utf8!(concat!($head, $("|", $tail),+)))
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_extensions_iter() {
+    let extensions = Extensions::new(CStr::from_bytes_with_nul(b"gb|gbc|sgb\0").unwrap());
+    assert_eq!(extensions.iter().collect::<Vec<_>>(), ["gb", "gbc", "sgb"]);
+    assert_eq!(Extensions::default().iter().count(), 0);
+  }
+
+  #[test]
+  fn test_content_info_override_array_is_terminated() {
+    let cue = Extensions::new(CStr::from_bytes_with_nul(b"cue|chd\0").unwrap());
+    let bin = Extensions::new(CStr::from_bytes_with_nul(b"bin\0").unwrap());
+    let overrides = [
+      ContentInfoOverride::new(cue).with_need_fullpath(),
+      ContentInfoOverride::new(bin).with_persistent_data(),
+    ];
+    let array = content_info_override_array(&overrides);
+    assert_eq!(array.len(), 3);
+    assert_eq!(array[0].extensions, cue.as_ptr());
+    assert!(array[0].need_fullpath && !array[0].persistent_data);
+    assert!(!array[1].need_fullpath && array[1].persistent_data);
+    assert!(array[2].extensions.is_null());
+    assert_eq!(content_info_override_array(&[]).len(), 1);
+  }
+
+  #[test]
+  fn test_extensions_validation() {
+    let try_new = |s: &'static [u8]| Extensions::try_new(CStr::from_bytes_with_nul(s).unwrap());
+    assert!(try_new(b"gb|gbc\0").is_ok());
+    assert!(try_new(b"\0").is_ok());
+    assert_eq!(
+      try_new(b"gb|.gbc\0"),
+      Err(InvalidExtensionError { index: 1 })
+    );
+    assert_eq!(try_new(b".gb\0"), Err(InvalidExtensionError { index: 0 }));
+    assert_eq!(
+      try_new(b"gb||gbc\0"),
+      Err(InvalidExtensionError { index: 1 })
+    );
+  }
+}
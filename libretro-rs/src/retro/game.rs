@@ -1,6 +1,7 @@
 use crate::convert::*;
 use crate::ffi::*;
 use crate::option::Option as _;
+use crate::retro::vfs::{read_file, VfsInterface};
 use c_utf8::CUtf8;
 use core::ffi::*;
 use core::fmt::{Debug, Formatter};
@@ -43,6 +44,16 @@ impl<'a> GameData<'a> {
   pub fn meta(&self) -> Option<&'a CStr> {
     unsafe { self.0.meta.as_ref().unsafe_into() }
   }
+
+  /// Implementation-specific metadata, parsed as `key=value` pairs. See [`meta_pairs`].
+  pub fn meta_pairs(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+    meta_pairs(self.meta())
+  }
+
+  /// The CRC-32 of the game's data. See [`content_crc32`].
+  pub fn crc32(&self) -> u32 {
+    content_crc32(self.data())
+  }
 }
 
 impl Debug for GameData<'_> {
@@ -93,10 +104,48 @@ impl<'a> GamePath<'a> {
     unsafe { (&*self.0.path).unsafe_into() }
   }
 
+  /// The extension of the game file, without the leading dot. See [`path_extension`].
+  pub fn extension(&self) -> Option<&'a str> {
+    path_extension(self.path().as_str())
+  }
+
+  /// Whether the game file has the extension `ext`, ignoring ASCII case.
+  pub fn has_extension(&self, ext: &str) -> bool {
+    has_extension(self.extension(), ext)
+  }
+
   /// Implementation-specific metadata.
   pub fn meta(&self) -> Option<&'a CStr> {
     unsafe { self.0.meta.as_ref().unsafe_into() }
   }
+
+  /// Implementation-specific metadata, parsed as `key=value` pairs. See [`meta_pairs`].
+  pub fn meta_pairs(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+    meta_pairs(self.meta())
+  }
+
+  /// Reads the whole game file.
+  ///
+  /// The file is read through `vfs` if the frontend provides one (see
+  /// [`crate::retro::env::Environment::get_vfs_interface`]), since on sandboxed platforms the path
+  /// may only be accessible through it, and through [`std::fs`] otherwise.
+  pub fn read_to_vec(&self, vfs: Option<VfsInterface>) -> std::io::Result<Vec<u8>> {
+    read_file(self.path().as_c_str(), vfs)
+  }
+
+  /// Memory-maps the game file, giving access to its contents without reading it into memory.
+  ///
+  /// The mapping doesn't borrow from `self`, and stays valid until it's dropped, so a core that
+  /// reads from it across frames must store it.
+  ///
+  /// # Safety
+  /// The game file must not be modified, truncated or removed, by this or any other process, while
+  /// the mapping is alive. See [`memmap2::Mmap::map`].
+  #[cfg(feature = "memmap2")]
+  pub unsafe fn mmap(&self) -> std::io::Result<memmap2::Mmap> {
+    let file = std::fs::File::open(self.path().as_str())?;
+    memmap2::Mmap::map(&file)
+  }
 }
 
 impl Debug for GamePath<'_> {
@@ -172,6 +221,54 @@ impl<'a> GameInfo<'a> {
   pub fn is_path(&self) -> bool {
     !self.is_data()
   }
+
+  /// The game's data, if it was loaded by the frontend.
+  pub fn data(&self) -> Option<&'a [u8]> {
+    self.as_data().map(GameData::data)
+  }
+
+  /// The game's data, either as loaded by the frontend or read from the game file.
+  /// See [`GamePath::read_to_vec`].
+  pub fn read_to_vec(&self, vfs: Option<VfsInterface>) -> std::io::Result<Vec<u8>> {
+    match self.as_ref() {
+      GameInfoKind::Data(data) => Ok(data.data().to_vec()),
+      GameInfoKind::Path(path) => path.read_to_vec(vfs),
+    }
+  }
+
+  /// The absolute path to the game file, if available.
+  pub fn path(&self) -> Option<&'a CUtf8> {
+    unsafe { self.info.path.as_ref().unsafe_into() }
+  }
+
+  /// The extension of the game file, if its path is available. See [`path_extension`].
+  pub fn extension(&self) -> Option<&'a str> {
+    self.path().and_then(|path| path_extension(path.as_str()))
+  }
+
+  /// Whether the game file has the extension `ext`, ignoring ASCII case.
+  pub fn has_extension(&self, ext: &str) -> bool {
+    has_extension(self.extension(), ext)
+  }
+
+  /// Implementation-specific metadata.
+  pub fn meta(&self) -> Option<&'a CStr> {
+    unsafe { self.info.meta.as_ref().unsafe_into() }
+  }
+
+  /// Memory-maps the game file if the frontend only provided its path. See [`GamePath::mmap`].
+  ///
+  /// # Safety
+  /// See [`GamePath::mmap`].
+  #[cfg(feature = "memmap2")]
+  pub unsafe fn mmap(&self) -> Option<std::io::Result<memmap2::Mmap>> {
+    self.as_path().map(|path| path.mmap())
+  }
+
+  /// Implementation-specific metadata, parsed as `key=value` pairs. See [`meta_pairs`].
+  pub fn meta_pairs(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+    meta_pairs(self.meta())
+  }
 }
 
 impl Debug for GameInfo<'_> {
@@ -260,6 +357,40 @@ impl<'a> SpecialGameInfo<'a> {
   pub fn is_none(&self) -> bool {
     unsafe { self.info.data.is_null() && self.info.path.is_null() }
   }
+
+  /// The game's data, if it was loaded by the frontend.
+  pub fn data(&self) -> Option<&'a [u8]> {
+    self.as_data().map(GameData::data)
+  }
+
+  /// The absolute path to the game file, if available.
+  pub fn path(&self) -> Option<&'a CUtf8> {
+    unsafe { self.info.path.as_ref().unsafe_into() }
+  }
+
+  /// The extension of the game file, if its path is available. See [`path_extension`].
+  pub fn extension(&self) -> Option<&'a str> {
+    self.path().and_then(|path| path_extension(path.as_str()))
+  }
+
+  /// Whether the game file has the extension `ext`, ignoring ASCII case.
+  pub fn has_extension(&self, ext: &str) -> bool {
+    has_extension(self.extension(), ext)
+  }
+
+  /// Implementation-specific metadata.
+  pub fn meta(&self) -> Option<&'a CStr> {
+    unsafe { self.info.meta.as_ref().unsafe_into() }
+  }
+
+  /// Memory-maps the game file if the frontend only provided its path. See [`GamePath::mmap`].
+  ///
+  /// # Safety
+  /// See [`GamePath::mmap`].
+  #[cfg(feature = "memmap2")]
+  pub unsafe fn mmap(&self) -> Option<std::io::Result<memmap2::Mmap>> {
+    self.as_path().map(|path| path.mmap())
+  }
 }
 
 impl Debug for SpecialGameInfo<'_> {
@@ -357,3 +488,293 @@ impl From<GameType> for c_uint {
     game_type.into_inner()
   }
 }
+
+/// Extended information about a loaded game, as returned by
+/// [`crate::retro::env::LoadGame::get_game_info_ext`]. Rust interface for
+/// [`retro_game_info_ext`].
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct GameInfoExt(retro_game_info_ext);
+
+impl GameInfoExt {
+  /// The absolute path to the game file, or to the archive containing it. [`None`] if the game
+  /// wasn't loaded from a file.
+  pub fn full_path(&self) -> Option<&CUtf8> {
+    unsafe { self.0.full_path.as_ref().unsafe_into() }
+  }
+
+  /// The absolute path to the archive containing the game, if it was loaded from one.
+  pub fn archive_path(&self) -> Option<&CUtf8> {
+    unsafe { self.0.archive_path.as_ref().unsafe_into() }
+  }
+
+  /// The path of the game file inside [`GameInfoExt::archive_path`].
+  pub fn archive_file(&self) -> Option<&CUtf8> {
+    unsafe { self.0.archive_file.as_ref().unsafe_into() }
+  }
+
+  /// The directory containing the game file, or the archive. Cores can look for sibling files,
+  /// such as `.cue` tracks, in it.
+  pub fn dir(&self) -> Option<&CUtf8> {
+    unsafe { self.0.dir.as_ref().unsafe_into() }
+  }
+
+  /// The name of the game file, without its extension or directory.
+  pub fn name(&self) -> Option<&CUtf8> {
+    unsafe { self.0.name.as_ref().unsafe_into() }
+  }
+
+  /// The lowercase extension of the game file, without the leading dot.
+  pub fn ext(&self) -> Option<&CUtf8> {
+    unsafe { self.0.ext.as_ref().unsafe_into() }
+  }
+
+  /// Implementation-specific metadata.
+  pub fn meta(&self) -> Option<&CStr> {
+    unsafe { self.0.meta.as_ref().unsafe_into() }
+  }
+
+  /// The game's data, if it was loaded by the frontend.
+  pub fn data(&self) -> Option<&[u8]> {
+    if self.0.data.is_null() {
+      None
+    } else {
+      Some(unsafe { slice::from_raw_parts(self.0.data.cast(), self.0.size) })
+    }
+  }
+
+  /// Whether the game file is inside an archive.
+  pub fn file_in_archive(&self) -> bool {
+    self.0.file_in_archive
+  }
+
+  /// Whether [`GameInfoExt::data`] stays valid until the game is unloaded. See
+  /// [`crate::retro::ContentInfoOverride::with_persistent_data`].
+  pub fn persistent_data(&self) -> bool {
+    self.0.persistent_data
+  }
+}
+
+impl Debug for GameInfoExt {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "GameInfoExt({:?})", &self.0)
+  }
+}
+
+/// Computes the CRC-32 (IEEE) of some content, as used by libretro databases and playlists to
+/// identify games.
+///
+/// libretro doesn't let cores report the CRC to the frontend, which computes it itself, but cores
+/// can use it to look up per-game settings or patches.
+pub fn content_crc32(data: &[u8]) -> u32 {
+  const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+      let mut crc = i as u32;
+      let mut bit = 0;
+      while bit < 8 {
+        crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        bit += 1;
+      }
+      table[i] = crc;
+      i += 1;
+    }
+    table
+  };
+  !data.iter().fold(!0, |crc, &byte| {
+    TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+  })
+}
+
+/// The extension of the file at `path`, without the leading dot.
+///
+/// Returns [`None`] if the file name has no extension, including hidden files such as `.config`
+/// whose only dot is the leading one. Both `/` and `\` are treated as path separators.
+pub fn path_extension(path: &str) -> Option<&str> {
+  let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+  match name.rsplit_once('.') {
+    Some((stem, ext)) if !stem.is_empty() && !ext.is_empty() => Some(ext),
+    _ => None,
+  }
+}
+
+fn has_extension(extension: Option<&str>, ext: &str) -> bool {
+  extension.is_some_and(|extension| extension.eq_ignore_ascii_case(ext))
+}
+
+/// Parses implementation-specific metadata using the common `key=value;key=value` convention.
+///
+/// Whitespace around keys and values is trimmed. Entries without a `=` or with an empty key
+/// are skipped, as is metadata that isn't valid UTF-8.
+pub fn meta_pairs(meta: Option<&CStr>) -> impl Iterator<Item = (&str, &str)> {
+  meta
+    .and_then(|meta| meta.to_str().ok())
+    .unwrap_or_default()
+    .split(';')
+    .filter_map(|pair| pair.split_once('='))
+    .map(|(key, value)| (key.trim(), value.trim()))
+    .filter(|(key, _)| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use c_utf8::c_utf8;
+
+  #[test]
+  fn test_content_crc32() {
+    assert_eq!(content_crc32(b""), 0);
+    assert_eq!(content_crc32(b"123456789"), 0xCBF43926);
+    let rom = [0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0x00, 0x00];
+    let data = GameData::new(&rom, None, None);
+    assert_eq!(data.crc32(), 0x5632AD40);
+  }
+
+  #[test]
+  fn test_extension() {
+    let game = GameInfo::from_path(GamePath::new(c_utf8!("/roms/Pong.CH8"), None));
+    assert_eq!(game.extension(), Some("CH8"));
+    assert!(game.has_extension("ch8"));
+    assert!(!game.has_extension("png"));
+
+    let game = SpecialGameInfo::from_path(GamePath::new(c_utf8!("C:\\roms.v2\\pong"), None));
+    assert_eq!(game.extension(), None, "the dot is in a directory name");
+    assert!(!game.has_extension(""));
+
+    assert_eq!(path_extension("/roms/.hidden"), None);
+    assert_eq!(path_extension("/roms/archive.tar.gz"), Some("gz"));
+    assert_eq!(path_extension("/roms/trailing."), None);
+
+    let data = [1u8, 2, 3];
+    let game = GameInfo::from_data(GameData::new(&data, None, None));
+    assert_eq!(game.extension(), None);
+    assert!(SpecialGameInfo::NONE.extension().is_none());
+  }
+
+  #[test]
+  fn test_meta_pairs() {
+    let meta =
+      CStr::from_bytes_with_nul(b"region=pal; mapper = 4;;=orphan;garbage;empty=\0").unwrap();
+    let pairs: Vec<_> = meta_pairs(Some(meta)).collect();
+    assert_eq!(
+      pairs,
+      [("region", "pal"), ("mapper", "4"), ("empty", "")],
+      "malformed entries should be skipped"
+    );
+    assert_eq!(meta_pairs(None).count(), 0);
+  }
+
+  #[test]
+  fn test_special_game_info_accessors() {
+    let data = [1u8, 2, 3];
+    let path = c_utf8!("/roms/game.ch8");
+    let meta = CStr::from_bytes_with_nul(b"region=pal\0").unwrap();
+
+    let none = SpecialGameInfo::NONE;
+    assert!(none.is_none());
+    assert_eq!(none.data(), None);
+    assert_eq!(none.path(), None);
+    assert_eq!(none.meta(), None);
+
+    let game = SpecialGameInfo::from_data(GameData::new(&data, Some(path), Some(meta)));
+    assert!(!game.is_none());
+    assert_eq!(game.data(), Some(&data[..]));
+    assert_eq!(game.path(), Some(path));
+    assert_eq!(game.meta(), Some(meta));
+
+    let game = SpecialGameInfo::from_path(GamePath::new(path, None));
+    assert!(!game.is_none());
+    assert_eq!(game.data(), None);
+    assert_eq!(game.path(), Some(path));
+    assert_eq!(game.meta(), None);
+  }
+
+  #[cfg(feature = "memmap2")]
+  #[test]
+  fn test_mmap_path_only() {
+    let file = std::env::temp_dir().join(format!("libretro-rs-mmap-{}.bin", std::process::id()));
+    std::fs::write(&file, [0xAAu8, 0xBB, 0xCC]).unwrap();
+    let path = format!("{}\0", file.to_str().unwrap());
+    let path = CUtf8::from_str(&path).unwrap();
+
+    let game = SpecialGameInfo::from_path(GamePath::new(path, None));
+    let mmap = unsafe { game.mmap() }.unwrap().unwrap();
+    assert_eq!(&mmap[..], [0xAA, 0xBB, 0xCC]);
+    drop(mmap);
+    std::fs::remove_file(&file).unwrap();
+
+    let game = GameInfo::from_data(GameData::new(&[1, 2, 3], Some(path), None));
+    assert!(unsafe { game.mmap() }.is_none());
+    assert!(unsafe { SpecialGameInfo::NONE.mmap() }.is_none());
+  }
+
+  const ROM: &[u8] = b"rom served through the vfs";
+  static ROM_OFFSET: std::sync::Mutex<usize> = std::sync::Mutex::new(0);
+
+  unsafe extern "C" fn rom_open(
+    path: *const c_char,
+    _mode: c_uint,
+    _hints: c_uint,
+  ) -> *mut retro_vfs_file_handle {
+    assert_eq!(CStr::from_ptr(path).to_bytes(), b"content://roms/game.bin");
+    *ROM_OFFSET.lock().unwrap() = 0;
+    core::ptr::NonNull::dangling().as_ptr()
+  }
+
+  unsafe extern "C" fn rom_close(_stream: *mut retro_vfs_file_handle) -> c_int {
+    0
+  }
+
+  unsafe extern "C" fn rom_size(_stream: *mut retro_vfs_file_handle) -> i64 {
+    ROM.len() as i64
+  }
+
+  unsafe extern "C" fn rom_read(
+    _stream: *mut retro_vfs_file_handle,
+    s: *mut c_void,
+    len: u64,
+  ) -> i64 {
+    let mut offset = ROM_OFFSET.lock().unwrap();
+    let len = (len as usize).min(ROM.len() - *offset);
+    ptr::copy_nonoverlapping(ROM[*offset..].as_ptr(), s.cast(), len);
+    *offset += len;
+    len as i64
+  }
+
+  #[test]
+  fn test_read_to_vec_prefers_vfs() {
+    let iface = Box::leak(Box::new(retro_vfs_interface {
+      open: Some(rom_open),
+      close: Some(rom_close),
+      size: Some(rom_size),
+      read: Some(rom_read),
+      ..Default::default()
+    }));
+    let vfs = VfsInterface::new(1, iface);
+    let game = GameInfo::from_path(GamePath::new(c_utf8!("content://roms/game.bin"), None));
+    assert_eq!(game.read_to_vec(Some(vfs)).unwrap(), ROM);
+    assert!(
+      game.read_to_vec(None).is_err(),
+      "the path only exists in the vfs"
+    );
+
+    let data = [1u8, 2, 3];
+    let game = GameInfo::from_data(GameData::new(&data, None, None));
+    assert_eq!(game.read_to_vec(Some(vfs)).unwrap(), data);
+  }
+
+  #[test]
+  fn test_game_info_accessors() {
+    let data = [1u8, 2, 3];
+    let path = c_utf8!("/roms/game.ch8");
+
+    let game = GameInfo::from_data(GameData::new(&data, None, None));
+    assert_eq!(game.data(), Some(&data[..]));
+    assert_eq!(game.path(), None);
+
+    let game = GameInfo::from_path(GamePath::new(path, None));
+    assert_eq!(game.data(), None);
+    assert_eq!(game.path(), Some(path));
+  }
+}
@@ -1,12 +1,35 @@
 use crate::ffi::retro_log_level::*;
 use crate::ffi::*;
 use c_utf8::*;
+use core::fmt;
 
 /// Trait for types that provide safe access to [`retro_log_printf_t`].
 pub trait LogInterface {
   fn log(&mut self, level: retro_log_level, message: &CUtf8);
 }
 
+impl<T> LogInterface for &mut T
+where
+  T: LogInterface + ?Sized,
+{
+  fn log(&mut self, level: retro_log_level, message: &CUtf8) {
+    (**self).log(level, message);
+  }
+}
+
+/// Logs to [StderrLogger] when no logger is available.
+impl<T> LogInterface for Option<T>
+where
+  T: LogInterface,
+{
+  fn log(&mut self, level: retro_log_level, message: &CUtf8) {
+    match self {
+      Some(logger) => logger.log(level, message),
+      None => StderrLogger.log(level, message),
+    }
+  }
+}
+
 /// Trait for types that offer idiomatic logging methods.
 pub trait Logger {
   /// Logs a debugging message.
@@ -59,6 +82,13 @@ impl LogInterface for PlatformLogger {
   }
 }
 
+impl LogInterface for &PlatformLogger {
+  fn log(&mut self, level: retro_log_level, message: &CUtf8) {
+    let mut logger = **self;
+    logger.log(level, message);
+  }
+}
+
 /// A [Logger] that logs to [std::io::Stderr].
 /// Primarily used as a fallback when [PlatformLogger] isn't available.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -142,3 +172,92 @@ pub struct NullLogger;
 impl LogInterface for NullLogger {
   fn log(&mut self, _level: retro_log_level, _message: &CUtf8) {}
 }
+
+/// Formats `args` into a C string and logs it. Used by [retro_log!] and friends.
+#[doc(hidden)]
+pub fn log_fmt(mut logger: impl LogInterface, level: retro_log_level, args: fmt::Arguments) {
+  let mut message = CUtf8Buf::new();
+  let _ = fmt::Write::write_fmt(&mut message, args);
+  logger.log(level, &message);
+}
+
+/// Logs a formatted message at the given [retro_log_level].
+///
+/// The first argument is any [LogInterface], such as `&PlatformLogger`, `&mut FallbackLogger<T>`
+/// or `Option<PlatformLogger>`. An `Option` that is `None` logs to [StderrLogger] instead.
+///
+/// # Examples
+/// ```
+/// use libretro_rs::ffi::retro_log_level::RETRO_LOG_INFO;
+/// use libretro_rs::retro::PlatformLogger;
+/// use libretro_rs::{retro_log, retro_warn};
+/// let logger: Option<PlatformLogger> = None;
+/// retro_log!(logger, RETRO_LOG_INFO, "loaded {} bytes", 4096);
+/// retro_warn!(logger, "falling back to {}", "stderr");
+/// ```
+#[macro_export]
+macro_rules! retro_log {
+  ($logger:expr, $level:expr, $($arg:tt)+) => {
+    $crate::retro::log_fmt($logger, $level, ::core::format_args!($($arg)+))
+  };
+}
+
+/// Logs a formatted debugging message. See [retro_log!].
+#[macro_export]
+macro_rules! retro_debug {
+  ($logger:expr, $($arg:tt)+) => {
+    $crate::retro_log!($logger, $crate::ffi::retro_log_level::RETRO_LOG_DEBUG, $($arg)+)
+  };
+}
+
+/// Logs a formatted informational message. See [retro_log!].
+#[macro_export]
+macro_rules! retro_info {
+  ($logger:expr, $($arg:tt)+) => {
+    $crate::retro_log!($logger, $crate::ffi::retro_log_level::RETRO_LOG_INFO, $($arg)+)
+  };
+}
+
+/// Logs a formatted warning message. See [retro_log!].
+#[macro_export]
+macro_rules! retro_warn {
+  ($logger:expr, $($arg:tt)+) => {
+    $crate::retro_log!($logger, $crate::ffi::retro_log_level::RETRO_LOG_WARN, $($arg)+)
+  };
+}
+
+/// Logs a formatted error message. See [retro_log!].
+#[macro_export]
+macro_rules! retro_error {
+  ($logger:expr, $($arg:tt)+) => {
+    $crate::retro_log!($logger, $crate::ffi::retro_log_level::RETRO_LOG_ERROR, $($arg)+)
+  };
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Default)]
+  struct StubLogger(Vec<(retro_log_level, String)>);
+
+  impl LogInterface for StubLogger {
+    fn log(&mut self, level: retro_log_level, message: &CUtf8) {
+      self.0.push((level, message.as_str().to_owned()));
+    }
+  }
+
+  #[test]
+  fn test_log_macros() {
+    let mut logger = StubLogger::default();
+    crate::retro_info!(&mut logger, "loaded {} bytes", 4096);
+    crate::retro_error!(&mut logger, "bad checksum: {:#06x}", 0xbeefu16);
+    assert_eq!(
+      logger.0,
+      [
+        (RETRO_LOG_INFO, "loaded 4096 bytes".to_owned()),
+        (RETRO_LOG_ERROR, "bad checksum: 0xbeef".to_owned()),
+      ]
+    );
+  }
+}
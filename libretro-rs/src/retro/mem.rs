@@ -1,5 +1,7 @@
+use crate::ffi::*;
 use ::core::convert::Infallible;
 use ::core::fmt::{Debug, Display, Formatter};
+use ::core::ops::{BitOr, BitOrAssign};
 use std::error::Error;
 use std::ffi::c_uint;
 
@@ -101,3 +103,122 @@ impl From<Infallible> for TryFromRetroMemoryTypeError {
     match x {}
   }
 }
+
+/// Flags for a `retro_memory_descriptor`, as passed to `RETRO_ENVIRONMENT_SET_MEMORY_MAPS`.
+///
+/// At most one of the `MIN_ALIGN_*` constants and one of the `MIN_SIZE_*` constants should be
+/// set, since they share bits.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemoryDescriptorFlags(u64);
+
+impl MemoryDescriptorFlags {
+  /// The frontend will never change this memory area once it's loaded.
+  pub const CONST: Self = Self(RETRO_MEMDESC_CONST as u64);
+
+  /// The memory area contains big endian data.
+  pub const BIGENDIAN: Self = Self(RETRO_MEMDESC_BIGENDIAN as u64);
+
+  /// The memory area is system RAM, the main RAM of the emulated hardware.
+  pub const SYSTEM_RAM: Self = Self(RETRO_MEMDESC_SYSTEM_RAM as u64);
+
+  /// The memory area is save RAM, usually battery-backed and saved to disk.
+  pub const SAVE_RAM: Self = Self(RETRO_MEMDESC_SAVE_RAM as u64);
+
+  /// The memory area is video RAM.
+  pub const VIDEO_RAM: Self = Self(RETRO_MEMDESC_VIDEO_RAM as u64);
+
+  /// All memory accesses are aligned to 2 bytes.
+  pub const MIN_ALIGN_2: Self = Self(RETRO_MEMDESC_ALIGN_2 as u64);
+
+  /// All memory accesses are aligned to 4 bytes.
+  pub const MIN_ALIGN_4: Self = Self(RETRO_MEMDESC_ALIGN_4 as u64);
+
+  /// All memory accesses are aligned to 8 bytes.
+  pub const MIN_ALIGN_8: Self = Self(RETRO_MEMDESC_ALIGN_8 as u64);
+
+  /// All memory accesses are at least 2 bytes wide.
+  pub const MIN_SIZE_2: Self = Self(RETRO_MEMDESC_MINSIZE_2 as u64);
+
+  /// All memory accesses are at least 4 bytes wide.
+  pub const MIN_SIZE_4: Self = Self(RETRO_MEMDESC_MINSIZE_4 as u64);
+
+  /// All memory accesses are at least 8 bytes wide.
+  pub const MIN_SIZE_8: Self = Self(RETRO_MEMDESC_MINSIZE_8 as u64);
+
+  pub fn new(flags: u64) -> Self {
+    Self(flags)
+  }
+
+  pub fn into_inner(self) -> u64 {
+    self.0
+  }
+
+  /// Returns true if all the flags in `other` are set.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for MemoryDescriptorFlags {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for MemoryDescriptorFlags {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+impl From<MemoryDescriptorFlags> for u64 {
+  fn from(flags: MemoryDescriptorFlags) -> Self {
+    flags.into_inner()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_memory_descriptor_flags_pack() {
+    let flags = MemoryDescriptorFlags::SYSTEM_RAM
+      | MemoryDescriptorFlags::BIGENDIAN
+      | MemoryDescriptorFlags::MIN_ALIGN_4
+      | MemoryDescriptorFlags::MIN_SIZE_2;
+    assert_eq!(
+      u64::from(flags),
+      (1 << 2) | (1 << 1) | (2 << 16) | (1 << 24)
+    );
+    assert!(flags.contains(MemoryDescriptorFlags::SYSTEM_RAM | MemoryDescriptorFlags::BIGENDIAN));
+    assert!(!flags.contains(MemoryDescriptorFlags::CONST));
+
+    let mut flags = MemoryDescriptorFlags::CONST;
+    flags |= MemoryDescriptorFlags::MIN_ALIGN_8;
+    assert_eq!(flags.into_inner(), 1 | (3 << 16));
+  }
+
+  #[test]
+  fn test_memory_descriptor_flags_match_header() {
+    let flags = [
+      (MemoryDescriptorFlags::CONST, RETRO_MEMDESC_CONST),
+      (MemoryDescriptorFlags::BIGENDIAN, RETRO_MEMDESC_BIGENDIAN),
+      (MemoryDescriptorFlags::SYSTEM_RAM, RETRO_MEMDESC_SYSTEM_RAM),
+      (MemoryDescriptorFlags::SAVE_RAM, RETRO_MEMDESC_SAVE_RAM),
+      (MemoryDescriptorFlags::VIDEO_RAM, RETRO_MEMDESC_VIDEO_RAM),
+      (MemoryDescriptorFlags::MIN_ALIGN_2, RETRO_MEMDESC_ALIGN_2),
+      (MemoryDescriptorFlags::MIN_ALIGN_4, RETRO_MEMDESC_ALIGN_4),
+      (MemoryDescriptorFlags::MIN_ALIGN_8, RETRO_MEMDESC_ALIGN_8),
+      (MemoryDescriptorFlags::MIN_SIZE_2, RETRO_MEMDESC_MINSIZE_2),
+      (MemoryDescriptorFlags::MIN_SIZE_4, RETRO_MEMDESC_MINSIZE_4),
+      (MemoryDescriptorFlags::MIN_SIZE_8, RETRO_MEMDESC_MINSIZE_8),
+    ];
+    for (flag, bits) in flags {
+      assert_eq!(u64::from(flag), u64::from(bits), "{flag:?}");
+    }
+  }
+}
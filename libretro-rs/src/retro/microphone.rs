@@ -0,0 +1,227 @@
+//! Microphone input.
+//!
+//! Cores get a [`MicrophoneInterface`] from [`Environment::get_microphone_interface`], and use it
+//! to open each [`Microphone`] they need, e.g. to emulate a handheld's built-in microphone.
+//!
+//! This interface is experimental upstream, so frontends may not support it, and it may change.
+//!
+//! [`Environment::get_microphone_interface`]: crate::retro::env::Environment::get_microphone_interface
+
+use crate::ffi::*;
+use core::ptr::NonNull;
+
+/// Rust interface for [`retro_microphone_params`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MicrophoneParams(retro_microphone_params);
+
+impl MicrophoneParams {
+  /// Requests input at the given sample rate in Hz. The frontend resamples the input as needed.
+  /// If `rate` is 0, the frontend picks a default.
+  pub fn new(rate: c_uint) -> Self {
+    Self(retro_microphone_params { rate })
+  }
+
+  pub fn rate(&self) -> c_uint {
+    self.0.rate
+  }
+}
+
+/// Rust interface for [`retro_microphone_interface`].
+#[derive(Clone, Copy, Debug)]
+pub struct MicrophoneInterface {
+  version: c_uint,
+  fns: MicrophoneFns,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct MicrophoneFns {
+  open_mic: non_null_retro_open_mic_t,
+  close_mic: non_null_retro_close_mic_t,
+  get_params: non_null_retro_get_mic_params_t,
+  set_mic_state: non_null_retro_set_mic_state_t,
+  get_mic_state: non_null_retro_get_mic_state_t,
+  read_mic: non_null_retro_read_mic_t,
+}
+
+impl MicrophoneInterface {
+  /// Returns [`None`] if any of the functions of `interface` is missing.
+  pub fn new(interface: retro_microphone_interface) -> Option<Self> {
+    Some(Self {
+      version: interface.interface_version,
+      fns: MicrophoneFns {
+        open_mic: interface.open_mic?,
+        close_mic: interface.close_mic?,
+        get_params: interface.get_params?,
+        set_mic_state: interface.set_mic_state?,
+        get_mic_state: interface.get_mic_state?,
+        read_mic: interface.read_mic?,
+      },
+    })
+  }
+
+  /// The interface version negotiated with the frontend.
+  pub fn version(&self) -> c_uint {
+    self.version
+  }
+
+  /// Opens a new microphone, which is disabled until [`Microphone::set_state`] is called.
+  ///
+  /// Returns [`None`] if the frontend couldn't open a microphone.
+  pub fn open_mic(&self, params: &MicrophoneParams) -> Option<Microphone> {
+    let handle = unsafe { (self.fns.open_mic)(&params.0) };
+    NonNull::new(handle).map(|handle| Microphone {
+      handle,
+      fns: self.fns,
+    })
+  }
+}
+
+/// A microphone opened with [`MicrophoneInterface::open_mic`]. It's closed when dropped.
+#[derive(Debug)]
+pub struct Microphone {
+  handle: NonNull<retro_microphone_t>,
+  fns: MicrophoneFns,
+}
+
+impl Microphone {
+  /// Enables or disables the microphone. Disabled microphones don't process any input.
+  ///
+  /// Returns `false` if the state couldn't be changed.
+  pub fn set_state(&mut self, enabled: bool) -> bool {
+    unsafe { (self.fns.set_mic_state)(self.handle.as_ptr(), enabled) }
+  }
+
+  /// Returns `true` if the microphone is enabled, even if the frontend's driver is paused.
+  pub fn get_state(&self) -> bool {
+    unsafe { (self.fns.get_mic_state)(self.handle.as_ptr()) }
+  }
+
+  /// Reads the input received since the last call into `samples`. An enabled microphone must be
+  /// read every frame.
+  ///
+  /// Returns the number of samples read, or [`None`] if there was an error.
+  pub fn read(&mut self, samples: &mut [i16]) -> Option<usize> {
+    let read =
+      unsafe { (self.fns.read_mic)(self.handle.as_ptr(), samples.as_mut_ptr(), samples.len()) };
+    usize::try_from(read).ok()
+  }
+
+  /// Returns the parameters the microphone was actually configured with, which may differ from
+  /// the requested ones.
+  pub fn get_params(&self) -> Option<MicrophoneParams> {
+    let mut params = MicrophoneParams::default();
+    unsafe { (self.fns.get_params)(self.handle.as_ptr(), &mut params.0) }.then_some(params)
+  }
+}
+
+impl Drop for Microphone {
+  fn drop(&mut self) {
+    unsafe { (self.fns.close_mic)(self.handle.as_ptr()) }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use core::sync::atomic::{AtomicUsize, Ordering};
+
+  struct FakeMic {
+    enabled: bool,
+    rate: c_uint,
+  }
+
+  static CLOSED: AtomicUsize = AtomicUsize::new(0);
+
+  unsafe extern "C" fn open_mic(params: *const retro_microphone_params) -> *mut retro_microphone_t {
+    match (*params).rate {
+      // Pretend the frontend can't open microphones at this rate.
+      1 => core::ptr::null_mut(),
+      rate => Box::into_raw(Box::new(FakeMic {
+        enabled: false,
+        rate: if rate == 0 { 44100 } else { rate },
+      }))
+      .cast(),
+    }
+  }
+
+  unsafe extern "C" fn close_mic(mic: *mut retro_microphone_t) {
+    drop(Box::from_raw(mic.cast::<FakeMic>()));
+    CLOSED.fetch_add(1, Ordering::SeqCst);
+  }
+
+  unsafe extern "C" fn get_params(
+    mic: *const retro_microphone_t,
+    params: *mut retro_microphone_params,
+  ) -> bool {
+    (*params).rate = (*mic.cast::<FakeMic>()).rate;
+    true
+  }
+
+  unsafe extern "C" fn set_mic_state(mic: *mut retro_microphone_t, state: bool) -> bool {
+    (*mic.cast::<FakeMic>()).enabled = state;
+    true
+  }
+
+  unsafe extern "C" fn get_mic_state(mic: *const retro_microphone_t) -> bool {
+    (*mic.cast::<FakeMic>()).enabled
+  }
+
+  unsafe extern "C" fn read_mic(
+    mic: *mut retro_microphone_t,
+    samples: *mut i16,
+    len: usize,
+  ) -> c_int {
+    if !(*mic.cast::<FakeMic>()).enabled {
+      return -1;
+    }
+    let read = len.min(4);
+    for i in 0..read {
+      *samples.add(i) = i as i16 + 1;
+    }
+    read as c_int
+  }
+
+  fn interface() -> retro_microphone_interface {
+    retro_microphone_interface {
+      interface_version: RETRO_MICROPHONE_INTERFACE_VERSION,
+      open_mic: Some(open_mic),
+      close_mic: Some(close_mic),
+      get_params: Some(get_params),
+      set_mic_state: Some(set_mic_state),
+      get_mic_state: Some(get_mic_state),
+      read_mic: Some(read_mic),
+    }
+  }
+
+  #[test]
+  fn test_interface_requires_every_function() {
+    assert!(MicrophoneInterface::new(interface()).is_some());
+    let missing = retro_microphone_interface {
+      read_mic: None,
+      ..interface()
+    };
+    assert!(MicrophoneInterface::new(missing).is_none());
+  }
+
+  #[test]
+  fn test_microphone_lifecycle() {
+    let interface = MicrophoneInterface::new(interface()).unwrap();
+    assert_eq!(interface.version(), RETRO_MICROPHONE_INTERFACE_VERSION);
+    assert!(interface.open_mic(&MicrophoneParams::new(1)).is_none());
+
+    let mut mic = interface.open_mic(&MicrophoneParams::new(0)).unwrap();
+    assert_eq!(mic.get_params().map(|params| params.rate()), Some(44100));
+    assert!(!mic.get_state());
+    let mut samples = [0; 8];
+    assert_eq!(mic.read(&mut samples), None, "disabled microphones fail");
+
+    assert!(mic.set_state(true));
+    assert!(mic.get_state());
+    assert_eq!(mic.read(&mut samples), Some(4));
+    assert_eq!(samples, [1, 2, 3, 4, 0, 0, 0, 0]);
+
+    drop(mic);
+    assert_eq!(CLOSED.load(Ordering::SeqCst), 1);
+  }
+}
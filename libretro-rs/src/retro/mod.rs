@@ -1,4 +1,5 @@
 pub mod av;
+pub mod cheat;
 pub mod cores;
 pub mod device;
 pub mod env;
@@ -7,9 +8,19 @@ pub mod fs;
 pub mod game;
 pub mod log;
 pub mod mem;
+pub mod microphone;
+pub mod netpacket;
+pub mod options;
+pub mod perf;
+pub mod rumble;
+pub mod saves;
 pub mod str;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod vfs;
 
 pub use self::av::*;
+pub use self::cheat::*;
 pub use self::cores::*;
 pub use self::device::*;
 // env deliberately omitted
@@ -18,4 +29,11 @@ pub use self::fs::*;
 pub use self::game::*;
 pub use self::log::*;
 pub use self::mem::*;
+pub use self::microphone::*;
+pub use self::netpacket::*;
+pub use self::options::*;
+pub use self::perf::*;
+pub use self::rumble::*;
+pub use self::saves::*;
 pub use self::str::*;
+pub use self::vfs::*;
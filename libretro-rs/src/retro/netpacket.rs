@@ -0,0 +1,282 @@
+//! Types for exchanging custom network packets during a multiplayer session.
+//!
+//! Cores that emulate linked consoles or networked computers can implement
+//! [`NetPacketCore`] and call [`LoadGame::set_netpacket_interface`] to take
+//! control over the packets sent between players. The frontend still takes
+//! care of connecting players and managing the session.
+//!
+//! [`NetPacketCore`]: crate::retro::NetPacketCore
+//! [`LoadGame::set_netpacket_interface`]: crate::retro::env::LoadGame::set_netpacket_interface
+
+use crate::ffi::*;
+use crate::retro::NetPacketError;
+use core::cell::RefCell;
+use core::ffi::*;
+use core::ops::{BitOr, BitOrAssign};
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The client id that addresses every connected player. See [`NetPacketSender::send`].
+pub const NETPACKET_BROADCAST: u16 = RETRO_NETPACKET_BROADCAST as u16;
+
+/// The largest packet [`NetPacketSender::send`] accepts, in bytes.
+pub const NETPACKET_MAX_SIZE: usize = 64 * 1024;
+
+/// Flags for [`NetPacketSender::send`].
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NetPacketFlags(c_int);
+
+impl NetPacketFlags {
+  /// Depending on network quality, the packet might not arrive.
+  ///
+  /// Frontends aren't required to support unreliable packets, and may send them reliably instead.
+  pub const UNRELIABLE: Self = Self(RETRO_NETPACKET_UNRELIABLE as c_int);
+
+  /// The packet is guaranteed to arrive, in the order it was sent.
+  pub const RELIABLE: Self = Self(RETRO_NETPACKET_RELIABLE as c_int);
+
+  /// The packet isn't sequenced with other packets, and may arrive out of order.
+  /// Can't be combined with [`NetPacketFlags::RELIABLE`].
+  pub const UNSEQUENCED: Self = Self(RETRO_NETPACKET_UNSEQUENCED as c_int);
+
+  /// Sends the packet, and any previously buffered packets, immediately.
+  pub const FLUSH_HINT: Self = Self(RETRO_NETPACKET_FLUSH_HINT as c_int);
+
+  pub fn new(flags: c_int) -> Self {
+    Self(flags)
+  }
+
+  pub fn into_inner(self) -> c_int {
+    self.0
+  }
+
+  /// Returns true if all the flags in `other` are set.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for NetPacketFlags {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for NetPacketFlags {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+impl From<NetPacketFlags> for c_int {
+  fn from(flags: NetPacketFlags) -> Self {
+    flags.into_inner()
+  }
+}
+
+/// A callback the frontend made during [`NetPacketSender::poll_receive`], while [`Core::run`]
+/// still borrows the core. The [`crate::retro::Instance`] replays it once `Core::run` returns.
+///
+/// [`Core::run`]: crate::retro::Core::run
+#[derive(Debug)]
+pub(crate) enum DeferredNetPacket {
+  Receive(Vec<u8>, u16),
+  Stop,
+}
+
+thread_local! {
+  /// The callbacks deferred during the current `retro_run`, or `None` outside of it.
+  static DEFERRED: RefCell<Option<Vec<DeferredNetPacket>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, which calls [`Core::run`], and returns the receive and stop callbacks the frontend
+/// made in the meantime, in order.
+///
+/// [`Core::run`]: crate::retro::Core::run
+pub(crate) fn deferring_callbacks(f: impl FnOnce()) -> Vec<DeferredNetPacket> {
+  struct Reset;
+
+  impl Drop for Reset {
+    fn drop(&mut self) {
+      DEFERRED.set(None);
+    }
+  }
+
+  DEFERRED.set(Some(Vec::new()));
+  let _reset = Reset;
+  f();
+  DEFERRED.take().unwrap_or_default()
+}
+
+fn defer(callback: impl FnOnce() -> DeferredNetPacket) -> bool {
+  DEFERRED.with_borrow_mut(|deferred| match deferred {
+    Some(deferred) => {
+      deferred.push(callback());
+      true
+    }
+    None => false,
+  })
+}
+
+/// Queues a packet received during `retro_run` for delivery once [`Core::run`] returns. Returns
+/// false outside of `retro_run`, where the packet must be delivered right away.
+///
+/// # Safety
+/// `buf` must be null or point to `len` readable bytes.
+///
+/// [`Core::run`]: crate::retro::Core::run
+#[doc(hidden)]
+pub unsafe fn defer_netpacket_receive(buf: *const c_void, len: usize, client_id: u16) -> bool {
+  defer(|| {
+    let buf = if buf.is_null() {
+      Vec::new()
+    } else {
+      core::slice::from_raw_parts(buf as *const u8, len).to_vec()
+    };
+    DeferredNetPacket::Receive(buf, client_id)
+  })
+}
+
+/// Like [`defer_netpacket_receive`], for the end of the session.
+#[doc(hidden)]
+pub fn defer_netpacket_stop() -> bool {
+  defer(|| DeferredNetPacket::Stop)
+}
+
+/// Returns true if the session stopped during the current `retro_run`, before the core was told.
+fn stop_deferred() -> bool {
+  DEFERRED.with_borrow(|deferred| {
+    deferred
+      .iter()
+      .flatten()
+      .any(|callback| matches!(callback, DeferredNetPacket::Stop))
+  })
+}
+
+/// Sends packets to the other players in a multiplayer session.
+///
+/// A sender is provided to [`NetPacketCore::start`], and the frontend's functions it wraps are
+/// only valid until [`NetPacketCore::stop`] is called. After that, [`NetPacketSender::send`]
+/// fails with [`NetPacketError::Stopped`] and [`NetPacketSender::poll_receive`] does nothing.
+/// Its methods must only be called during [`Core::run`] or one of the [`NetPacketCore`]
+/// callbacks.
+///
+/// [`Core::run`]: crate::retro::Core::run
+/// [`NetPacketCore`]: crate::retro::NetPacketCore
+/// [`NetPacketCore::start`]: crate::retro::NetPacketCore::start
+/// [`NetPacketCore::stop`]: crate::retro::NetPacketCore::stop
+#[derive(Debug)]
+pub struct NetPacketSender {
+  send_fn: non_null_retro_netpacket_send_t,
+  poll_receive_fn: retro_netpacket_poll_receive_t,
+  /// Cleared by the [`crate::retro::Instance`] when the session stops.
+  active: Arc<AtomicBool>,
+}
+
+impl NetPacketSender {
+  /// # Safety
+  /// The function pointers must be the ones provided by the frontend to
+  /// `retro_netpacket_start_t`, and the session must not have stopped.
+  pub unsafe fn new(
+    send_fn: non_null_retro_netpacket_send_t,
+    poll_receive_fn: retro_netpacket_poll_receive_t,
+  ) -> Self {
+    Self {
+      send_fn,
+      poll_receive_fn,
+      active: Arc::new(AtomicBool::new(true)),
+    }
+  }
+
+  /// The flag the [`crate::retro::Instance`] clears when the session stops.
+  pub(crate) fn session(&self) -> Arc<AtomicBool> {
+    self.active.clone()
+  }
+
+  /// Returns true until the multiplayer session stops.
+  pub fn is_active(&self) -> bool {
+    self.active.load(Ordering::Acquire) && !stop_deferred()
+  }
+
+  /// Sends `buf` to the player identified by `client_id`, or to every player
+  /// if `client_id` is [`NETPACKET_BROADCAST`]. A packet can contain up to
+  /// [`NETPACKET_MAX_SIZE`] bytes.
+  pub fn send(
+    &self,
+    flags: NetPacketFlags,
+    buf: &[u8],
+    client_id: u16,
+  ) -> Result<(), NetPacketError> {
+    if buf.len() > NETPACKET_MAX_SIZE {
+      return Err(NetPacketError::TooLarge);
+    }
+    if !self.is_active() {
+      return Err(NetPacketError::Stopped);
+    }
+    unsafe { (self.send_fn)(flags.into(), buf.as_ptr().cast(), buf.len(), client_id) }
+    Ok(())
+  }
+
+  /// Sends `buf` to every connected player.
+  pub fn broadcast(&self, flags: NetPacketFlags, buf: &[u8]) -> Result<(), NetPacketError> {
+    self.send(flags, buf, NETPACKET_BROADCAST)
+  }
+
+  /// Reads any incoming packets without waiting for the end of the frame.
+  ///
+  /// The core is still borrowed by [`Core::run`], so the packets are passed to
+  /// [`NetPacketCore::receive`] once it returns, followed by [`NetPacketCore::stop`] if the
+  /// session ended in the meantime. Does nothing outside of `Core::run`, if the frontend doesn't
+  /// support polling, or if the session has stopped.
+  ///
+  /// [`Core::run`]: crate::retro::Core::run
+  /// [`NetPacketCore::receive`]: crate::retro::NetPacketCore::receive
+  /// [`NetPacketCore::stop`]: crate::retro::NetPacketCore::stop
+  pub fn poll_receive(&self) {
+    let running = DEFERRED.with_borrow(Option::is_some);
+    match self.poll_receive_fn {
+      Some(poll_receive) if running && self.is_active() => unsafe { poll_receive() },
+      _ => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Mutex;
+
+  static SENT: Mutex<Vec<(c_int, usize, u16)>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn record_send(flags: c_int, _buf: *const c_void, len: usize, client_id: u16) {
+    SENT.lock().unwrap().push((flags, len, client_id));
+  }
+
+  #[test]
+  fn test_sender_checks_size_and_session() {
+    let sender = unsafe { NetPacketSender::new(record_send, None) };
+    let reliable = NetPacketFlags::RELIABLE;
+    assert_eq!(sender.send(reliable, &[0; 16], 2), Ok(()));
+    assert_eq!(sender.broadcast(reliable, &[0; NETPACKET_MAX_SIZE]), Ok(()));
+    assert_eq!(
+      sender.send(reliable, &[0; NETPACKET_MAX_SIZE + 1], 2),
+      Err(NetPacketError::TooLarge)
+    );
+
+    sender.session().store(false, Ordering::Release);
+    assert!(!sender.is_active());
+    assert_eq!(
+      sender.send(reliable, &[0; 16], 2),
+      Err(NetPacketError::Stopped)
+    );
+    sender.poll_receive();
+
+    assert_eq!(
+      *SENT.lock().unwrap(),
+      [(1, 16, 2), (1, NETPACKET_MAX_SIZE, NETPACKET_BROADCAST)]
+    );
+  }
+}
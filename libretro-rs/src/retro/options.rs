@@ -0,0 +1,319 @@
+//! Types for declaring core options.
+//!
+//! The frontend presents core options to the player, and the core reads the selected values back
+//! with [`Environment::get_variable`]. Options are declared once, with
+//! [`Environment::declare_options`] or [`Environment::declare_options_intl`], which pick the
+//! newest core options interface the frontend supports.
+//!
+//! [`Environment::get_variable`]: crate::retro::env::Environment::get_variable
+//! [`Environment::declare_options`]: crate::retro::env::Environment::declare_options
+//! [`Environment::declare_options_intl`]: crate::retro::env::Environment::declare_options_intl
+
+use crate::ffi::*;
+use crate::retro::log::StderrLogger;
+use c_utf8::CUtf8;
+use core::ffi::CStr;
+use core::ptr;
+use std::ffi::CString;
+
+/// A value that can be selected for a [`CoreOption`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CoreOptionValue {
+  /// The value returned by [`crate::retro::env::Environment::get_variable`].
+  pub value: &'static CUtf8,
+  /// The label shown by the frontend. If [`None`], the value itself is shown.
+  pub label: Option<&'static CUtf8>,
+}
+
+impl CoreOptionValue {
+  pub const fn new(value: &'static CUtf8) -> Self {
+    Self { value, label: None }
+  }
+
+  pub const fn with_label(value: &'static CUtf8, label: &'static CUtf8) -> Self {
+    Self {
+      value,
+      label: Some(label),
+    }
+  }
+}
+
+/// A core option in the format of `RETRO_ENVIRONMENT_SET_VARIABLES`, the oldest interface, which
+/// every frontend supports. See [`crate::retro::env::Environment::set_variables`].
+///
+/// [`CoreOption`] should be preferred, since
+/// [`crate::retro::env::Environment::declare_options`] falls back to this interface on frontends
+/// that don't support newer ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Variable<'a> {
+  /// The key to query with [`crate::retro::env::Environment::get_variable`].
+  pub key: &'a CStr,
+  /// The description and the possible values, formatted as `"Description; default|other|..."`:
+  /// the description is followed by a semicolon and a space, then the values separated by `|`,
+  /// the first of which is the default.
+  pub value: &'a CStr,
+}
+
+impl<'a> Variable<'a> {
+  pub const fn new(key: &'a CStr, value: &'a CStr) -> Self {
+    Self { key, value }
+  }
+}
+
+/// A core option, as shown in the frontend's menu.
+///
+/// Frontends support at most `RETRO_NUM_CORE_OPTION_VALUES_MAX - 1` values per option; any
+/// further values are dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CoreOption<'a> {
+  /// The key to query with [`crate::retro::env::Environment::get_variable`].
+  pub key: &'static CUtf8,
+  /// The label shown in the frontend's menu.
+  pub desc: &'static CUtf8,
+  /// The sublabel shown in the frontend's menu.
+  pub info: Option<&'static CUtf8>,
+  pub values: &'a [CoreOptionValue],
+  /// The value selected by default. Must be one of `values`; if [`None`], the first value is
+  /// the default.
+  pub default_value: Option<&'static CUtf8>,
+}
+
+impl<'a> CoreOption<'a> {
+  pub const fn new(
+    key: &'static CUtf8,
+    desc: &'static CUtf8,
+    values: &'a [CoreOptionValue],
+  ) -> Self {
+    Self {
+      key,
+      desc,
+      info: None,
+      values,
+      default_value: None,
+    }
+  }
+
+  fn value_array(&self) -> [retro_core_option_value; RETRO_NUM_CORE_OPTION_VALUES_MAX as usize] {
+    const MAX_VALUES: usize = RETRO_NUM_CORE_OPTION_VALUES_MAX as usize - 1;
+    if self.values.len() > MAX_VALUES {
+      crate::retro_warn!(
+        StderrLogger,
+        "core option {} has more than {} values; ignoring the rest",
+        self.key,
+        MAX_VALUES
+      );
+    }
+    let mut values =
+      [retro_core_option_value::default(); RETRO_NUM_CORE_OPTION_VALUES_MAX as usize];
+    for (value, option) in values.iter_mut().zip(self.values.iter().take(MAX_VALUES)) {
+      *value = retro_core_option_value {
+        value: option.value.as_ptr(),
+        label: option.label.map_or(ptr::null(), |label| label.as_ptr()),
+      };
+    }
+    values
+  }
+
+  /// The `"desc; default|other|..."` string that `RETRO_ENVIRONMENT_SET_VARIABLES` expects,
+  /// where the default value comes first. See [`Variable::value`].
+  pub(crate) fn variable_value(&self) -> CString {
+    let default = self.default_value.or(self.values.first().map(|v| v.value));
+    let others = self
+      .values
+      .iter()
+      .map(|v| v.value)
+      .filter(|v| Some(*v) != default);
+    let values: Vec<&str> = default
+      .into_iter()
+      .chain(others)
+      .map(CUtf8::as_str)
+      .collect();
+    let value = format!("{}; {}", self.desc.as_str(), values.join("|"));
+    // Neither part can contain a nul byte, since both come from `CUtf8`s.
+    CString::new(value).unwrap()
+  }
+}
+
+impl From<&CoreOption<'_>> for retro_core_option_definition {
+  fn from(option: &CoreOption) -> Self {
+    Self {
+      key: option.key.as_ptr(),
+      desc: option.desc.as_ptr(),
+      info: option.info.map_or(ptr::null(), |info| info.as_ptr()),
+      values: option.value_array(),
+      default_value: option.default_value.map_or(ptr::null(), |v| v.as_ptr()),
+    }
+  }
+}
+
+impl From<&CoreOption<'_>> for retro_core_option_v2_definition {
+  fn from(option: &CoreOption) -> Self {
+    Self {
+      key: option.key.as_ptr(),
+      desc: option.desc.as_ptr(),
+      desc_categorized: ptr::null(),
+      info: option.info.map_or(ptr::null(), |info| info.as_ptr()),
+      info_categorized: ptr::null(),
+      category_key: ptr::null(),
+      values: option.value_array(),
+      default_value: option.default_value.map_or(ptr::null(), |v| v.as_ptr()),
+    }
+  }
+}
+
+pub(crate) fn definition_array(options: &[CoreOption]) -> Vec<retro_core_option_definition> {
+  options
+    .iter()
+    .map(retro_core_option_definition::from)
+    .chain(Some(retro_core_option_definition::default()))
+    .collect()
+}
+
+pub(crate) fn v2_definition_array(options: &[CoreOption]) -> Vec<retro_core_option_v2_definition> {
+  options
+    .iter()
+    .map(retro_core_option_v2_definition::from)
+    .chain(Some(retro_core_option_v2_definition::default()))
+    .collect()
+}
+
+/// The `RETRO_ENVIRONMENT_SET_VARIABLES` array for `variables`, terminated by a null entry.
+pub(crate) fn variable_array(variables: &[Variable]) -> Vec<retro_variable> {
+  variables
+    .iter()
+    .map(|variable| retro_variable {
+      key: variable.key.as_ptr(),
+      value: variable.value.as_ptr(),
+    })
+    .chain(Some(retro_variable::default()))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::retro::env::Environment;
+  use c_utf8::c_utf8;
+  use core::ffi::{c_uint, c_void, CStr};
+  use core::sync::atomic::{AtomicU32, Ordering};
+  use std::sync::Mutex;
+
+  const SPEED_VALUES: [CoreOptionValue; 3] = [
+    CoreOptionValue::new(c_utf8!("1x")),
+    CoreOptionValue::new(c_utf8!("2x")),
+    CoreOptionValue::with_label(c_utf8!("4x"), c_utf8!("Fastest")),
+  ];
+
+  fn speed_option() -> CoreOption<'static> {
+    CoreOption {
+      default_value: Some(c_utf8!("2x")),
+      ..CoreOption::new(c_utf8!("chip8_speed"), c_utf8!("Speed"), &SPEED_VALUES)
+    }
+  }
+
+  #[test]
+  fn test_variable_value_puts_default_first() {
+    let value = speed_option().variable_value();
+    assert_eq!(value.as_bytes(), b"Speed; 2x|1x|4x");
+    let key = speed_option().key.as_c_str();
+    let variables = variable_array(&[Variable::new(key, &value)]);
+    assert_eq!(variables.len(), 2);
+    assert_eq!(variables[0].value, value.as_ptr());
+    assert!(variables[1].key.is_null());
+    assert!(variables[1].value.is_null());
+    assert_eq!(variable_array(&[]).len(), 1);
+  }
+
+  #[test]
+  fn test_definitions_keep_declaration_order() {
+    let keys = [
+      c_utf8!("chip8_zeta"),
+      c_utf8!("chip8_alpha"),
+      c_utf8!("chip8_mid"),
+    ];
+    let options = keys.map(|key| CoreOption::new(key, c_utf8!("Option"), &SPEED_VALUES));
+    let emitted: Vec<&[u8]> = v2_definition_array(&options)
+      .iter()
+      .take_while(|d| !d.key.is_null())
+      .map(|d| unsafe { CStr::from_ptr(d.key) }.to_bytes())
+      .collect();
+    let expected: Vec<&[u8]> = keys.iter().map(|key| key.as_str().as_bytes()).collect();
+    assert_eq!(emitted, expected);
+    let emitted: Vec<&[u8]> = definition_array(&options)
+      .iter()
+      .take_while(|d| !d.key.is_null())
+      .map(|d| unsafe { CStr::from_ptr(d.key) }.to_bytes())
+      .collect();
+    assert_eq!(emitted, expected);
+  }
+
+  static VERSION: AtomicU32 = AtomicU32::new(0);
+  static REJECTED: AtomicU32 = AtomicU32::new(0);
+  static COMMANDS: Mutex<Vec<c_uint>> = Mutex::new(Vec::new());
+
+  unsafe extern "C" fn options_environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+      RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION => {
+        *(data as *mut c_uint) = VERSION.load(Ordering::SeqCst);
+        true
+      }
+      RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL => {
+        let intl = &*(data as *const retro_core_options_v2_intl);
+        let us = &*intl.us;
+        assert_eq!(
+          CStr::from_ptr((*us.definitions).key).to_bytes(),
+          b"chip8_speed"
+        );
+        assert!((*us.definitions.add(1)).key.is_null());
+        assert!(!intl.local.is_null());
+        COMMANDS.lock().unwrap().push(cmd);
+        cmd != REJECTED.load(Ordering::SeqCst)
+      }
+      RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL => {
+        let intl = &*(data as *const retro_core_options_intl);
+        let us = &*intl.us;
+        assert_eq!(CStr::from_ptr(us.values[2].label).to_bytes(), b"Fastest");
+        assert!(us.values[3].value.is_null());
+        COMMANDS.lock().unwrap().push(cmd);
+        cmd != REJECTED.load(Ordering::SeqCst)
+      }
+      RETRO_ENVIRONMENT_SET_VARIABLES => {
+        let variable = &*(data as *const retro_variable);
+        assert_eq!(
+          CStr::from_ptr(variable.value).to_bytes(),
+          b"Speed; 2x|1x|4x"
+        );
+        COMMANDS.lock().unwrap().push(cmd);
+        true
+      }
+      _ => false,
+    }
+  }
+
+  #[test]
+  fn test_declare_options_intl_per_version() {
+    let mut env: non_null_retro_environment_t = options_environment;
+    let us = [speed_option()];
+    let local = [CoreOption {
+      desc: c_utf8!("Vitesse"),
+      ..speed_option()
+    }];
+    let mut declare = |version, rejected| {
+      VERSION.store(version, Ordering::SeqCst);
+      REJECTED.store(rejected, Ordering::SeqCst);
+      COMMANDS.lock().unwrap().clear();
+      assert!(env.declare_options_intl(&us, Some(&local)).is_ok());
+      COMMANDS.lock().unwrap().clone()
+    };
+    assert_eq!(declare(0, 0), [RETRO_ENVIRONMENT_SET_VARIABLES]);
+    assert_eq!(declare(1, 0), [RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL]);
+    assert_eq!(declare(2, 0), [RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL]);
+    assert_eq!(
+      declare(2, RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL),
+      [
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_V2_INTL,
+        RETRO_ENVIRONMENT_SET_CORE_OPTIONS_INTL
+      ]
+    );
+  }
+}
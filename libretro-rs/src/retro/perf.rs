@@ -0,0 +1,143 @@
+//! Timing and CPU feature detection.
+//!
+//! Cores get a [`PerfInterface`] from [`Environment::get_perf_interface`], and can check which
+//! SIMD instruction sets the CPU supports with [`CpuFeatures::detect`].
+//!
+//! [`Environment::get_perf_interface`]: crate::retro::env::Environment::get_perf_interface
+
+use crate::ffi::*;
+use core::ops::{BitOr, BitOrAssign};
+
+/// Rust interface for [`retro_perf_callback`].
+#[derive(Clone, Copy, Debug)]
+pub struct PerfInterface(retro_perf_callback);
+
+impl PerfInterface {
+  pub fn new(callback: retro_perf_callback) -> Self {
+    Self(callback)
+  }
+
+  /// The current time in microseconds, or [`None`] if the frontend doesn't provide it.
+  pub fn get_time_usec(&self) -> Option<i64> {
+    self
+      .0
+      .get_time_usec
+      .map(|get_time_usec| unsafe { get_time_usec() })
+  }
+
+  /// A raw, high-resolution CPU tick counter, or [`None`] if the frontend doesn't provide it.
+  pub fn get_perf_counter(&self) -> Option<u64> {
+    self
+      .0
+      .get_perf_counter
+      .map(|get_counter| unsafe { get_counter() })
+  }
+}
+
+/// The SIMD instruction sets and CPU features reported by [`PerfInterface`], as
+/// `RETRO_SIMD_*` flags.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CpuFeatures(u64);
+
+impl CpuFeatures {
+  pub const SSE: Self = Self(RETRO_SIMD_SSE as u64);
+  pub const SSE2: Self = Self(RETRO_SIMD_SSE2 as u64);
+  pub const VMX: Self = Self(RETRO_SIMD_VMX as u64);
+  pub const VMX128: Self = Self(RETRO_SIMD_VMX128 as u64);
+  pub const AVX: Self = Self(RETRO_SIMD_AVX as u64);
+  pub const NEON: Self = Self(RETRO_SIMD_NEON as u64);
+  pub const SSE3: Self = Self(RETRO_SIMD_SSE3 as u64);
+  pub const SSSE3: Self = Self(RETRO_SIMD_SSSE3 as u64);
+  pub const MMX: Self = Self(RETRO_SIMD_MMX as u64);
+  pub const MMXEXT: Self = Self(RETRO_SIMD_MMXEXT as u64);
+  pub const SSE4: Self = Self(RETRO_SIMD_SSE4 as u64);
+  pub const SSE42: Self = Self(RETRO_SIMD_SSE42 as u64);
+  pub const AVX2: Self = Self(RETRO_SIMD_AVX2 as u64);
+  pub const VFPU: Self = Self(RETRO_SIMD_VFPU as u64);
+  pub const PS: Self = Self(RETRO_SIMD_PS as u64);
+  pub const AES: Self = Self(RETRO_SIMD_AES as u64);
+  pub const VFPV3: Self = Self(RETRO_SIMD_VFPV3 as u64);
+  pub const VFPV4: Self = Self(RETRO_SIMD_VFPV4 as u64);
+  pub const POPCNT: Self = Self(RETRO_SIMD_POPCNT as u64);
+  pub const MOVBE: Self = Self(RETRO_SIMD_MOVBE as u64);
+  pub const CMOV: Self = Self(RETRO_SIMD_CMOV as u64);
+  pub const ASIMD: Self = Self(RETRO_SIMD_ASIMD as u64);
+
+  pub fn new(flags: u64) -> Self {
+    Self(flags)
+  }
+
+  /// Asks the frontend for the features of the CPU. Returns no features if the frontend doesn't
+  /// provide them.
+  pub fn detect(perf: &PerfInterface) -> Self {
+    Self(perf.0.get_cpu_features.map_or(0, |get| unsafe { get() }))
+  }
+
+  pub fn into_inner(self) -> u64 {
+    self.0
+  }
+
+  /// Returns true if all the features in `other` are supported.
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for CpuFeatures {
+  type Output = Self;
+
+  fn bitor(self, rhs: Self) -> Self::Output {
+    Self(self.0 | rhs.0)
+  }
+}
+
+impl BitOrAssign for CpuFeatures {
+  fn bitor_assign(&mut self, rhs: Self) {
+    self.0 |= rhs.0
+  }
+}
+
+impl From<CpuFeatures> for u64 {
+  fn from(features: CpuFeatures) -> Self {
+    features.into_inner()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  unsafe extern "C" fn x86_64_features() -> u64 {
+    (RETRO_SIMD_SSE | RETRO_SIMD_SSE2 | RETRO_SIMD_SSE42 | RETRO_SIMD_AVX2 | RETRO_SIMD_CMOV) as u64
+  }
+
+  unsafe extern "C" fn time_usec() -> i64 {
+    1_000_000
+  }
+
+  #[test]
+  fn test_detect_cpu_features() {
+    let perf = PerfInterface::new(retro_perf_callback {
+      get_cpu_features: Some(x86_64_features),
+      get_time_usec: Some(time_usec),
+      ..Default::default()
+    });
+    let features = CpuFeatures::detect(&perf);
+    assert!(features.contains(CpuFeatures::SSE2 | CpuFeatures::AVX2));
+    assert!(features.contains(CpuFeatures::SSE42));
+    assert!(!features.contains(CpuFeatures::NEON));
+    assert!(!features.contains(CpuFeatures::AVX2 | CpuFeatures::AES));
+    assert_eq!(
+      u64::from(features),
+      1 | 2 | (1 << 11) | (1 << 12) | (1 << 20)
+    );
+    assert_eq!(perf.get_time_usec(), Some(1_000_000));
+    assert_eq!(perf.get_perf_counter(), None);
+
+    let arm = CpuFeatures::new((RETRO_SIMD_NEON | RETRO_SIMD_ASIMD) as u64);
+    assert!(arm.contains(CpuFeatures::NEON) && !arm.contains(CpuFeatures::SSE));
+    let none = CpuFeatures::detect(&PerfInterface::new(retro_perf_callback::default()));
+    assert_eq!(none, CpuFeatures::default());
+  }
+}
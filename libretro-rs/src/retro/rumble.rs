@@ -0,0 +1,179 @@
+//! Controller rumble.
+//!
+//! Cores get a [`RumbleInterface`] from [`Environment::get_rumble_interface`], and can either set
+//! the strength of each motor directly, or let a [`RumbleEnvelope`] ramp it over several frames.
+//!
+//! [`Environment::get_rumble_interface`]: crate::retro::env::Environment::get_rumble_interface
+
+use crate::ffi::*;
+use crate::retro::DevicePort;
+
+/// The rumble motors of a controller, which can be controlled independently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RumbleEffect {
+  #[default]
+  Strong = 0,
+  Weak = 1,
+}
+
+impl From<RumbleEffect> for retro_rumble_effect {
+  fn from(effect: RumbleEffect) -> Self {
+    match effect {
+      RumbleEffect::Strong => retro_rumble_effect::RETRO_RUMBLE_STRONG,
+      RumbleEffect::Weak => retro_rumble_effect::RETRO_RUMBLE_WEAK,
+    }
+  }
+}
+
+/// Rust interface for [`retro_rumble_interface`].
+#[derive(Clone, Copy, Debug)]
+pub struct RumbleInterface(non_null_retro_set_rumble_state_t);
+
+impl RumbleInterface {
+  pub fn new(set_rumble_state: non_null_retro_set_rumble_state_t) -> Self {
+    Self(set_rumble_state)
+  }
+
+  /// Sets the strength of a rumble motor of the controller in `port`. A `strength` of 0 turns the
+  /// motor off.
+  ///
+  /// Returns `false` if the frontend couldn't apply the change.
+  pub fn set_rumble(&self, port: DevicePort, effect: RumbleEffect, strength: u16) -> bool {
+    unsafe { self.0(port.into_inner(), effect.into(), strength) }
+  }
+}
+
+/// Ramps rumble strength over several frames, so cores can express effects like short pulses or
+/// fading rumble without tracking the strength of every frame themselves.
+///
+/// The strength rises to `peak` over the `attack` frames, stays there for the `sustain` frames,
+/// and falls back to 0 over the `decay` frames. Call [`RumbleEnvelope::drive`] (or
+/// [`RumbleEnvelope::step`]) once per [`crate::retro::Core::run`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RumbleEnvelope {
+  peak: u16,
+  attack: u32,
+  sustain: u32,
+  decay: u32,
+  /// Counted in `u64`, as the total length of the envelope may not fit in a `u32`.
+  frame: u64,
+}
+
+impl RumbleEnvelope {
+  /// Creates an envelope that starts on the next frame.
+  pub const fn new(peak: u16, attack: u32, sustain: u32, decay: u32) -> Self {
+    Self {
+      peak,
+      attack,
+      sustain,
+      decay,
+      frame: 0,
+    }
+  }
+
+  /// Creates an envelope that rumbles at `strength` for `frames` frames, then stops.
+  pub const fn pulse(strength: u16, frames: u32) -> Self {
+    Self::new(strength, 0, frames, 0)
+  }
+
+  /// Creates an envelope that starts at `strength` and fades out over `frames` frames.
+  pub const fn fade(strength: u16, frames: u32) -> Self {
+    Self::new(strength, 0, 1, frames)
+  }
+
+  /// Restarts the envelope from its first frame.
+  pub fn trigger(&mut self) {
+    self.frame = 0;
+  }
+
+  /// Skips the rest of the envelope, so the strength drops to 0 on the next frame.
+  pub fn stop(&mut self) {
+    self.frame = self.len();
+  }
+
+  /// Returns `true` once every frame of the envelope has been stepped through.
+  pub fn is_finished(&self) -> bool {
+    self.frame >= self.len()
+  }
+
+  /// The strength of the current frame.
+  pub fn strength(&self) -> u16 {
+    // A `u16` peak times a `u32` frame count can't overflow a `u64`.
+    let peak = self.peak as u64;
+    let (attack, sustain, decay) = (self.attack as u64, self.sustain as u64, self.decay as u64);
+    let frame = self.frame;
+    let strength = if frame < attack {
+      peak * (frame + 1) / (attack + 1)
+    } else if frame < attack + sustain {
+      peak
+    } else if frame < self.len() {
+      let frame = frame - attack - sustain;
+      peak * (decay - frame) / (decay + 1)
+    } else {
+      0
+    };
+    strength as u16
+  }
+
+  /// Returns the strength of the current frame, and advances to the next one.
+  pub fn step(&mut self) -> u16 {
+    let strength = self.strength();
+    if !self.is_finished() {
+      self.frame += 1;
+    }
+    strength
+  }
+
+  /// Steps the envelope, and sets the strength of the `effect` motor in `port` accordingly.
+  ///
+  /// Returns the result of [`RumbleInterface::set_rumble`].
+  pub fn drive(
+    &mut self,
+    rumble: &RumbleInterface,
+    port: DevicePort,
+    effect: RumbleEffect,
+  ) -> bool {
+    rumble.set_rumble(port, effect, self.step())
+  }
+
+  fn len(&self) -> u64 {
+    self.attack as u64 + self.sustain as u64 + self.decay as u64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_envelope_steps() {
+    let mut envelope = RumbleEnvelope::new(0x8000, 3, 2, 3);
+    let strengths: Vec<u16> = (0..10).map(|_| envelope.step()).collect();
+    assert_eq!(
+      strengths,
+      [0x2000, 0x4000, 0x6000, 0x8000, 0x8000, 0x6000, 0x4000, 0x2000, 0, 0]
+    );
+    assert!(envelope.is_finished());
+
+    envelope.trigger();
+    assert!(!envelope.is_finished());
+    assert_eq!(envelope.step(), 0x2000);
+    envelope.stop();
+    assert_eq!(envelope.step(), 0);
+  }
+
+  #[test]
+  fn test_envelope_long_durations() {
+    let mut envelope = RumbleEnvelope::new(u16::MAX, u32::MAX, u32::MAX, u32::MAX);
+    assert_eq!(envelope.step(), 0);
+    assert!(!envelope.is_finished());
+
+    envelope.frame = u32::MAX as u64 - 1;
+    assert_eq!(envelope.strength(), u16::MAX - 1);
+    envelope.frame = 2 * u32::MAX as u64 + 1;
+    assert_eq!(envelope.strength(), u16::MAX - 1);
+    envelope.stop();
+    assert_eq!(envelope.frame, 3 * u32::MAX as u64);
+    assert_eq!(envelope.step(), 0);
+  }
+}
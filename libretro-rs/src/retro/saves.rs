@@ -0,0 +1,101 @@
+//! Save RAM files in the frontend's save directory.
+//!
+//! Most cores persist their [`StandardMemoryType::SaveRam`] the same way: read
+//! `<save directory>/<content name>.srm` when a game is loaded, and write it back when it's
+//! unloaded. [`SaveFile`] does both, through the frontend's [`VfsInterface`] when it provides one.
+//!
+//! [`StandardMemoryType::SaveRam`]: crate::retro::StandardMemoryType::SaveRam
+//! [`VfsInterface`]: crate::retro::VfsInterface
+
+use crate::retro::env::Environment;
+use crate::retro::StateFile;
+use std::ffi::CString;
+use std::io;
+
+/// The extension of save RAM files, shared with other `libretro` cores and RetroArch.
+const SAVE_RAM_EXTENSION: &str = "srm";
+
+/// The save RAM file of a game, named after its content, usually the game file name without its
+/// extension.
+pub struct SaveFile;
+
+impl SaveFile {
+  /// The path of the save RAM file for `name` in the frontend's save directory.
+  ///
+  /// Fails with [`io::ErrorKind::NotFound`] if the frontend has no save directory.
+  pub fn path(env: &impl Environment, name: &str) -> io::Result<CString> {
+    let dir = env
+      .get_save_directory()
+      .ok()
+      .flatten()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no save directory"))?;
+    // The directory may not be UTF-8, so the path is built from its bytes.
+    let dir = dir.to_bytes();
+    let end = dir
+      .iter()
+      .rposition(|&byte| !matches!(byte, b'/' | b'\\'))
+      .map_or(0, |last| last + 1);
+    let mut path = dir[..end].to_vec();
+    path.push(b'/');
+    path.extend_from_slice(format!("{name}.{SAVE_RAM_EXTENSION}").as_bytes());
+    CString::new(path).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+  }
+
+  /// Reads the save RAM file for `name`, e.g. from [`crate::retro::Core::load_game`].
+  pub fn load(env: &impl Environment, name: &str) -> io::Result<Vec<u8>> {
+    let path = Self::path(env, name)?;
+    StateFile::new(&path, env.get_vfs_interface(1)).load()
+  }
+
+  /// Writes `data` to the save RAM file for `name`, e.g. from
+  /// [`crate::retro::Core::unload_game`].
+  pub fn store(env: &impl Environment, name: &str, data: &[u8]) -> io::Result<()> {
+    let path = Self::path(env, name)?;
+    StateFile::new(&path, env.get_vfs_interface(1)).save(data)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::retro::testing::MockEnvironment;
+  use std::ffi::CStr;
+
+  fn save_directory() -> CString {
+    let dir = std::env::temp_dir().join(format!("libretro-rs-saves-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    CString::new(format!("{}/", dir.display())).unwrap()
+  }
+
+  #[test]
+  fn test_save_file_round_trip() {
+    let dir = save_directory();
+    let env = MockEnvironment::new().with_save_directory(&dir);
+    let path = SaveFile::path(&env, "Tetris (World)").unwrap();
+    let dir = dir.to_str().unwrap();
+    assert_eq!(path.to_str().unwrap(), format!("{dir}Tetris (World).srm"));
+
+    assert!(SaveFile::load(&env, "Tetris (World)").is_err());
+    SaveFile::store(&env, "Tetris (World)", &[1, 2, 3, 4]).unwrap();
+    assert_eq!(
+      SaveFile::load(&env, "Tetris (World)").unwrap(),
+      [1, 2, 3, 4]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_save_file_path_keeps_non_utf8_directory() {
+    let dir = CStr::from_bytes_with_nul(b"/saves/\xff\\\0").unwrap();
+    let env = MockEnvironment::new().with_save_directory(dir);
+    let path = SaveFile::path(&env, "Tetris").unwrap();
+    assert_eq!(path.as_bytes(), b"/saves/\xff/Tetris.srm");
+  }
+
+  #[test]
+  fn test_save_file_without_save_directory() {
+    let env = MockEnvironment::new();
+    let error = SaveFile::store(&env, "Tetris", &[0]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+  }
+}
@@ -0,0 +1,571 @@
+//! Test doubles for unit-testing cores without a frontend. Requires the `testing` feature.
+//!
+//! [`MockEnvironment`] stands in for the frontend's environment callback, and records the
+//! commands a core issues; [`MockCallbacks`] feeds the core programmable input and captures the
+//! audio and video it produces. Together, they let a test call a core's [`Core::load_game`] and
+//! [`Core::run`] directly:
+//!
+//! ```ignore
+//! let mut env = MockEnvironment::new().with_variable("chip8_speed", "2x");
+//! let core = MyCore::load_game(&game, env.load_game_args(&mut ())).unwrap();
+//! assert_eq!(env.pixel_format(), Some(PixelFormat::XRGB8888));
+//!
+//! let mut callbacks = MockCallbacks::new();
+//! callbacks.set_joypad(DevicePort::new(0), JoypadState::from_bits(1));
+//! core.run(&mut env, &mut callbacks);
+//! assert_eq!(callbacks.frames.len(), 1);
+//! ```
+
+use crate::ffi::*;
+use crate::retro::env::{self, Environment};
+use crate::retro::pixel::Format;
+use crate::retro::*;
+use core::cell::{Cell, RefCell};
+use core::ffi::*;
+use core::marker::PhantomData;
+use core::{mem, slice};
+use std::ffi::CString;
+use std::rc::{Rc, Weak};
+
+thread_local! {
+  /// The mock that last handed out its environment callback on this thread. The callback fails
+  /// once the mock is dropped.
+  static ACTIVE: RefCell<Weak<MockState>> = const { RefCell::new(Weak::new()) };
+}
+
+/// An [`Environment`] that answers commands like a minimal frontend, and records them.
+///
+/// The mock accepts the commands that only inform the frontend, like
+/// `RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS`, and answers queries for core options, frame duping
+/// and input bitmasks. Every other command is recorded and rejected, so cores see the mock as a
+/// frontend without hardware rendering, netplay or audio buffer status callbacks.
+#[derive(Debug, Default)]
+pub struct MockEnvironment {
+  state: Rc<MockState>,
+}
+
+/// The state of a [`MockEnvironment`], shared with its environment callback.
+#[derive(Debug, Default)]
+struct MockState {
+  core_options_version: Cell<c_uint>,
+  save_directory: RefCell<Option<CString>>,
+  variables: RefCell<Vec<(CString, CString)>>,
+  variables_updated: Cell<bool>,
+  /// Every command issued, with its scalar payload. See [`MockEnvironment::last_payload`].
+  commands: RefCell<Vec<(c_uint, Option<c_uint>)>>,
+  pixel_format: Cell<Option<PixelFormat>>,
+  messages: RefCell<Vec<(String, c_uint)>>,
+  queried_variables: RefCell<Vec<CString>>,
+}
+
+impl MockEnvironment {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the version returned for `RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION`. Defaults to 0.
+  pub fn with_core_options_version(self, version: c_uint) -> Self {
+    self.state.core_options_version.set(version);
+    self
+  }
+
+  /// Sets the directory returned by [`Environment::get_save_directory`]. The mock has none by
+  /// default.
+  pub fn with_save_directory(self, dir: &CStr) -> Self {
+    self.state.save_directory.replace(Some(dir.to_owned()));
+    self
+  }
+
+  /// Sets the value returned by [`Environment::get_variable`] for `key`.
+  ///
+  /// # Panics
+  /// Panics if `key` or `value` contain a nul byte.
+  pub fn with_variable(mut self, key: &str, value: &str) -> Self {
+    self.update_variable(key, value);
+    self.state.variables_updated.set(false);
+    self
+  }
+
+  /// Changes the value of `key`, as if the player changed it in the frontend's menu, so the next
+  /// [`Environment::get_variable_update`] returns true.
+  ///
+  /// # Panics
+  /// Panics if `key` or `value` contain a nul byte.
+  pub fn update_variable(&mut self, key: &str, value: &str) {
+    let key = CString::new(key).expect("key should not contain a nul byte");
+    let value = CString::new(value).expect("value should not contain a nul byte");
+    let mut variables = self.state.variables.borrow_mut();
+    match variables.iter_mut().find(|(k, _)| *k == key) {
+      Some((_, v)) => *v = value,
+      None => variables.push((key, value)),
+    }
+    self.state.variables_updated.set(true);
+  }
+
+  /// The arguments for [`Core::load_game`] and [`Core::load_without_content`], as the
+  /// [`Instance`] passes them.
+  pub fn load_game_args<'init, I>(
+    &mut self,
+    init_state: &'init mut I,
+  ) -> LoadGameExtraArgs<'init, '_, Self, I> {
+    LoadGameExtraArgs {
+      env: self,
+      init_state,
+      rendering_mode: SoftwareRenderEnabled(()),
+      pixel_format: Format(PhantomData),
+    }
+  }
+
+  /// Every command issued so far, in order.
+  pub fn commands(&self) -> Vec<c_uint> {
+    self
+      .state
+      .commands
+      .borrow()
+      .iter()
+      .map(|(cmd, _)| *cmd)
+      .collect()
+  }
+
+  /// The number of times `cmd` was issued.
+  pub fn command_count(&self, cmd: c_uint) -> usize {
+    self
+      .state
+      .commands
+      .borrow()
+      .iter()
+      .filter(|(c, _)| *c == cmd)
+      .count()
+  }
+
+  /// Same as [`MockEnvironment::command_count`].
+  pub fn env_call_count(&self, cmd: c_uint) -> usize {
+    self.command_count(cmd)
+  }
+
+  /// The value passed with the last `cmd`, for commands whose data is a single number or flag,
+  /// like `RETRO_ENVIRONMENT_SET_PIXEL_FORMAT` or `RETRO_ENVIRONMENT_SET_ROTATION`. Flags are
+  /// returned as 0 or 1. Returns `None` if `cmd` was never issued or takes other data.
+  pub fn last_payload(&self, cmd: c_uint) -> Option<c_uint> {
+    let commands = self.state.commands.borrow();
+    commands.iter().rev().find(|(c, _)| *c == cmd)?.1
+  }
+
+  /// Asserts that `cmd` was issued at least once.
+  ///
+  /// # Panics
+  /// Panics if `cmd` was never issued.
+  #[track_caller]
+  pub fn assert_called(&self, cmd: c_uint) {
+    assert!(
+      self.command_count(cmd) > 0,
+      "environment command {cmd} was never issued"
+    );
+  }
+
+  /// Same as [`MockEnvironment::assert_called`].
+  #[track_caller]
+  pub fn assert_env_called(&self, cmd: c_uint) {
+    self.assert_called(cmd);
+  }
+
+  /// The last pixel format set by the core, if any.
+  pub fn pixel_format(&self) -> Option<PixelFormat> {
+    self.state.pixel_format.get()
+  }
+
+  /// The messages sent with [`Environment::set_message`], with their duration in frames.
+  pub fn messages(&self) -> Vec<(String, c_uint)> {
+    self.state.messages.borrow().clone()
+  }
+
+  /// The keys queried with [`Environment::get_variable`], in order, including unknown keys.
+  pub fn queried_variables(&self) -> Vec<CString> {
+    self.state.queried_variables.borrow().clone()
+  }
+}
+
+impl MockState {
+  unsafe fn handle(&self, cmd: c_uint, data: *mut c_void) -> bool {
+    let payload = match cmd {
+      RETRO_ENVIRONMENT_SET_PIXEL_FORMAT
+      | RETRO_ENVIRONMENT_SET_ROTATION
+      | RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL
+      | RETRO_ENVIRONMENT_SET_MINIMUM_AUDIO_LATENCY => Some(*(data as *const c_uint)),
+      RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME | RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS => {
+        Some(*(data as *const bool) as c_uint)
+      }
+      _ => None,
+    };
+    self.commands.borrow_mut().push((cmd, payload));
+    match cmd {
+      RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+        let format = match *(data as *const c_int) {
+          0 => PixelFormat::RGB1555,
+          1 => PixelFormat::XRGB8888,
+          2 => PixelFormat::RGB565,
+          _ => return false,
+        };
+        self.pixel_format.set(Some(format));
+        true
+      }
+      RETRO_ENVIRONMENT_SET_MESSAGE => {
+        let message = &*(data as *const retro_message);
+        let msg = CStr::from_ptr(message.msg).to_string_lossy().into_owned();
+        self.messages.borrow_mut().push((msg, message.frames));
+        true
+      }
+      RETRO_ENVIRONMENT_GET_VARIABLE => {
+        let variable = &mut *(data as *mut retro_variable);
+        let key = CStr::from_ptr(variable.key);
+        self.queried_variables.borrow_mut().push(key.to_owned());
+        match self
+          .variables
+          .borrow()
+          .iter()
+          .find(|(k, _)| k.as_c_str() == key)
+        {
+          Some((_, value)) => {
+            variable.value = value.as_ptr();
+            true
+          }
+          None => false,
+        }
+      }
+      RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE => {
+        *(data as *mut bool) = self.variables_updated.replace(false);
+        true
+      }
+      RETRO_ENVIRONMENT_GET_SAVE_DIRECTORY => match &*self.save_directory.borrow() {
+        Some(dir) => {
+          *(data as *mut *const c_char) = dir.as_ptr();
+          true
+        }
+        None => false,
+      },
+      RETRO_ENVIRONMENT_GET_CORE_OPTIONS_VERSION => {
+        *(data as *mut c_uint) = self.core_options_version.get();
+        true
+      }
+      RETRO_ENVIRONMENT_GET_CAN_DUPE | RETRO_ENVIRONMENT_GET_INPUT_BITMASKS => {
+        *(data as *mut bool) = true;
+        true
+      }
+      RETRO_ENVIRONMENT_SET_HW_RENDER => {
+        let hw_render = &*(data as *const retro_hw_render_callback);
+        hw_render.context_type == retro_hw_context_type::RETRO_HW_CONTEXT_NONE
+      }
+      RETRO_ENVIRONMENT_SET_ROTATION
+      | RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL
+      | RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS
+      | RETRO_ENVIRONMENT_SET_VARIABLES
+      | RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME
+      | RETRO_ENVIRONMENT_SET_SYSTEM_AV_INFO
+      | RETRO_ENVIRONMENT_SET_CONTROLLER_INFO
+      | RETRO_ENVIRONMENT_SET_GEOMETRY
+      | RETRO_ENVIRONMENT_SET_SUPPORT_ACHIEVEMENTS
+      | RETRO_ENVIRONMENT_SET_MINIMUM_AUDIO_LATENCY => true,
+      _ => false,
+    }
+  }
+}
+
+unsafe extern "C" fn mock_environment(cmd: c_uint, data: *mut c_void) -> bool {
+  match ACTIVE.with_borrow(Weak::upgrade) {
+    Some(mock) => mock.handle(cmd, data),
+    None => false,
+  }
+}
+
+impl Environment for MockEnvironment {
+  fn get_ptr(&self) -> non_null_retro_environment_t {
+    ACTIVE.set(Rc::downgrade(&self.state));
+    mock_environment
+  }
+}
+
+impl env::LoadGame for MockEnvironment {
+  fn set_hw_render_none(&mut self) -> env::Result<()> {
+    let data = retro_hw_render_callback::default();
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_HW_RENDER, &data) }
+  }
+
+  fn set_hw_render_gl(&mut self, options: GLOptions) -> env::Result<GLRenderEnabled> {
+    let data: retro_hw_render_callback = options.into();
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_HW_RENDER, &data) }.map(|_| GLRenderEnabled(()))
+  }
+
+  fn set_netpacket_interface(&mut self, _: Option<&'static CStr>) -> env::Result<()> {
+    let data = retro_netpacket_callback::default();
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_NETPACKET_INTERFACE, &data) }
+  }
+
+  fn set_audio_buffer_status_callback(&mut self) -> env::Result<()> {
+    let data = retro_audio_buffer_status_callback::default();
+    unsafe { self.set(RETRO_ENVIRONMENT_SET_AUDIO_BUFFER_STATUS_CALLBACK, &data) }
+  }
+}
+
+/// A video frame captured by [`MockCallbacks`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MockFrame {
+  /// A software frame, as raw bytes. `pitch` is the length of a line in bytes.
+  Software {
+    width: c_uint,
+    height: c_uint,
+    pitch: usize,
+    data: Vec<u8>,
+  },
+  /// A repeat of the previous frame, sent with [`Callbacks::repeat_video_frame`] or
+  /// [`Callbacks::dup_video_frame`].
+  Dupe { width: c_uint, height: c_uint },
+  /// A frame rendered with hardware rendering.
+  Hardware { width: c_uint, height: c_uint },
+}
+
+/// [`Callbacks`] with programmable input, which capture the audio and video sent by the core.
+#[derive(Clone, Debug, Default)]
+pub struct MockCallbacks {
+  /// Returned by [`Callbacks::capabilities`].
+  pub capabilities: RunCapabilities,
+  /// The device read from each port. Ports that aren't listed read as a released joypad.
+  pub inputs: Vec<(DevicePort, DeviceInput)>,
+  /// The values returned by [`Callbacks::input_state`], keyed by port, device, index and id.
+  /// Queries that aren't listed return 0.
+  pub raw_inputs: Vec<((DevicePort, c_uint, c_uint, c_uint), i16)>,
+  /// Every audio sample sent, interleaved as left and right.
+  pub audio: Vec<i16>,
+  /// Every video frame sent, in order.
+  pub frames: Vec<MockFrame>,
+  /// The number of calls to [`Callbacks::poll_inputs`].
+  pub polls: usize,
+}
+
+impl MockCallbacks {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the device read from `port`.
+  pub fn set_input(&mut self, port: DevicePort, input: DeviceInput) {
+    match self.inputs.iter_mut().find(|(p, _)| *p == port) {
+      Some((_, i)) => *i = input,
+      None => self.inputs.push((port, input)),
+    }
+  }
+
+  /// Plugs a joypad with the given buttons pressed into `port`.
+  pub fn set_joypad(&mut self, port: DevicePort, state: JoypadState) {
+    self.set_input(port, DeviceInput::Joypad(state))
+  }
+}
+
+impl Callbacks for MockCallbacks {
+  fn upload_audio_frame(&mut self, frame: &[i16]) -> usize {
+    self.audio.extend_from_slice(frame);
+    frame.len() / 2
+  }
+
+  fn upload_audio_sample(&mut self, left: i16, right: i16) {
+    self.audio.extend_from_slice(&[left, right]);
+  }
+
+  fn upload_video_frame<P>(
+    &mut self,
+    _enabled: &SoftwareRenderEnabled,
+    _pixel_format: &Format<P>,
+    framebuffer: &Frame<'_, P>,
+  ) {
+    let data = framebuffer.data();
+    let bytes =
+      unsafe { slice::from_raw_parts(data.as_ptr().cast::<u8>(), mem::size_of_val(data)) };
+    self.frames.push(MockFrame::Software {
+      width: framebuffer.width(),
+      height: framebuffer.height(),
+      pitch: framebuffer.pitch() as usize * mem::size_of::<P>(),
+      data: bytes.to_vec(),
+    });
+  }
+
+  fn upload_software_framebuffer(
+    &mut self,
+    _enabled: &SoftwareRenderEnabled,
+    framebuffer: SoftwareFramebuffer<'_>,
+  ) {
+    self.frames.push(MockFrame::Software {
+      width: framebuffer.width(),
+      height: framebuffer.height(),
+      pitch: framebuffer.pitch(),
+      data: framebuffer.data().to_vec(),
+    });
+  }
+
+  fn repeat_video_frame(&mut self) {
+    self.dup_video_frame(0, 0)
+  }
+
+  fn dup_video_frame(&mut self, width: c_uint, height: c_uint) {
+    self.frames.push(MockFrame::Dupe { width, height });
+  }
+
+  fn use_hardware_frame_buffer(
+    &mut self,
+    _enabled: &impl HWRenderEnabled,
+    width: c_uint,
+    height: c_uint,
+  ) {
+    self.frames.push(MockFrame::Hardware { width, height });
+  }
+
+  fn poll_inputs(&mut self) -> InputsPolled {
+    self.polls += 1;
+    InputsPolled(())
+  }
+
+  fn is_joypad_button_pressed(&self, port: DevicePort, btn: JoypadButton) -> bool {
+    self.joypad_bitmask(port).contains(btn)
+  }
+
+  fn joypad_bitmask(&self, port: DevicePort) -> JoypadState {
+    match self.read_device(port) {
+      DeviceInput::Joypad(state) => state,
+      _ => JoypadState::from_bits(0),
+    }
+  }
+
+  fn read_device(&self, port: DevicePort) -> DeviceInput {
+    self.inputs.iter().find(|(p, _)| *p == port).map_or(
+      DeviceInput::Joypad(JoypadState::from_bits(0)),
+      |(_, input)| *input,
+    )
+  }
+
+  fn input_state(&self, port: DevicePort, device: c_uint, index: c_uint, id: c_uint) -> i16 {
+    let key = (port, device, index, id);
+    self
+      .raw_inputs
+      .iter()
+      .find(|(k, _)| *k == key)
+      .map_or(0, |(_, value)| *value)
+  }
+
+  fn capabilities(&self) -> RunCapabilities {
+    self.capabilities
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::retro::pixel::XRGB8888;
+
+  struct MockedCore {
+    pixel_format: Format<XRGB8888>,
+    rendering_mode: SoftwareRenderEnabled,
+    frame: [XRGB8888; 2],
+  }
+
+  impl<'a> Core<'a> for MockedCore {
+    type Init = ();
+
+    fn get_system_info() -> SystemInfo {
+      unimplemented!()
+    }
+
+    fn init(_env: &mut impl env::Init) -> Self::Init {}
+
+    fn load_game<E: env::LoadGame>(
+      _game: &GameInfo,
+      args: LoadGameExtraArgs<'a, '_, E, Self::Init>,
+    ) -> Result<Self, CoreError> {
+      let LoadGameExtraArgs {
+        env,
+        pixel_format,
+        rendering_mode,
+        ..
+      } = args;
+      let pixel_format = env.set_pixel_format_xrgb8888(pixel_format)?;
+      let key = CStr::from_bytes_with_nul(b"mocked_speed\0").unwrap();
+      if env.get_variable(&key)?.map(CStr::to_bytes) == Some(b"2x") {
+        let msg = CStr::from_bytes_with_nul(b"Running at 2x\0").unwrap();
+        env.set_message(&Message::new(msg, 60))?;
+      }
+      Ok(Self {
+        pixel_format,
+        rendering_mode,
+        frame: [XRGB8888::DEFAULT; 2],
+      })
+    }
+
+    fn get_system_av_info(&self, _env: &mut impl env::GetAvInfo) -> SystemAVInfo {
+      unimplemented!()
+    }
+
+    fn run(&mut self, _env: &mut impl env::Run, callbacks: &mut impl Callbacks) -> InputsPolled {
+      let polled = callbacks.poll_inputs();
+      let pressed = callbacks.is_joypad_button_pressed(DevicePort::new(0), JoypadButton::A);
+      self.frame[0] = XRGB8888::new_with_raw_value(pressed as u32);
+      let frame = Frame::new(&self.frame, 2, 1);
+      callbacks.upload_video_frame(&self.rendering_mode, &self.pixel_format, &frame);
+      callbacks.upload_audio_sample(1, -1);
+      polled
+    }
+
+    fn reset(&mut self, _env: &mut impl env::Reset) {}
+
+    fn unload_game(self, _env: &mut impl env::UnloadGame) -> Self::Init {}
+  }
+
+  #[test]
+  fn test_mocked_load_game_and_run() {
+    let mut env = MockEnvironment::new().with_variable("mocked_speed", "2x");
+    let data = [0u8; 4];
+    let game = GameInfo::from_data(GameData::new(&data, None, None));
+    let mut core = MockedCore::load_game(&game, env.load_game_args(&mut ())).unwrap();
+    assert_eq!(env.pixel_format(), Some(PixelFormat::XRGB8888));
+    assert_eq!(env.env_call_count(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT), 1);
+    assert_eq!(
+      env.last_payload(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT),
+      Some(1)
+    );
+    assert_eq!(env.last_payload(RETRO_ENVIRONMENT_SET_MESSAGE), None);
+    assert_eq!(env.messages(), [("Running at 2x".to_owned(), 60)]);
+    assert_eq!(env.queried_variables().len(), 1);
+    assert!(!env.get_variable_update());
+
+    let mut callbacks = MockCallbacks::new();
+    let mut pressed = JoypadState::from_bits(0);
+    pressed.set(JoypadButton::A, true);
+    callbacks.set_joypad(DevicePort::new(0), pressed);
+    core.run(&mut env, &mut callbacks);
+    assert_eq!(callbacks.polls, 1);
+    assert_eq!(callbacks.audio, [1, -1]);
+    assert_eq!(
+      callbacks.frames,
+      [MockFrame::Software {
+        width: 2,
+        height: 1,
+        pitch: 8,
+        data: 1u32.to_ne_bytes().into_iter().chain([0; 4]).collect(),
+      }]
+    );
+
+    env.update_variable("mocked_speed", "1x");
+    assert!(env.get_variable_update());
+    let key = CString::new("mocked_speed").unwrap();
+    let value = env.get_variable(&key).unwrap().map(CStr::to_bytes);
+    assert_eq!(value, Some(&b"1x"[..]));
+  }
+
+  #[test]
+  fn test_callback_fails_once_mock_is_dropped() {
+    let mock = MockEnvironment::new();
+    let cb = mock.get_ptr();
+    let mut rotation: c_uint = 1;
+    let data = &mut rotation as *mut c_uint as *mut c_void;
+    assert!(unsafe { cb(RETRO_ENVIRONMENT_SET_ROTATION, data) });
+    assert_eq!(mock.last_payload(RETRO_ENVIRONMENT_SET_ROTATION), Some(1));
+    drop(mock);
+    assert!(!unsafe { cb(RETRO_ENVIRONMENT_SET_ROTATION, data) });
+  }
+}
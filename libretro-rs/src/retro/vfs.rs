@@ -0,0 +1,305 @@
+//! File access through the frontend's virtual file system.
+//!
+//! Cores get a [`VfsInterface`] from [`Environment::get_vfs_interface`], which lets them access
+//! files on platforms where the frontend sandboxes the file system. [`StateFile`] uses it to
+//! persist save states that the core manages itself, and [`GamePath::read_to_vec`] to read games
+//! loaded by path.
+//!
+//! [`Environment::get_vfs_interface`]: crate::retro::env::Environment::get_vfs_interface
+//! [`GamePath::read_to_vec`]: crate::retro::GamePath::read_to_vec
+
+use crate::ffi::*;
+use core::ptr::NonNull;
+use std::io;
+
+/// Rust interface for [`retro_vfs_interface`].
+#[derive(Clone, Copy, Debug)]
+pub struct VfsInterface {
+  version: u32,
+  iface: &'static retro_vfs_interface,
+}
+
+impl VfsInterface {
+  pub fn new(version: u32, iface: &'static retro_vfs_interface) -> Self {
+    Self { version, iface }
+  }
+
+  /// The interface version provided by the frontend.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Opens the file at `path` with the given `RETRO_VFS_FILE_ACCESS_*` mode.
+  ///
+  /// Returns [`None`] if the file couldn't be opened.
+  pub fn open(&self, path: &CStr, mode: c_uint) -> Option<VfsFile> {
+    let open = self.iface.open?;
+    let handle = unsafe { open(path.as_ptr(), mode, RETRO_VFS_FILE_ACCESS_HINT_NONE) };
+    NonNull::new(handle).map(|handle| VfsFile {
+      version: self.version,
+      iface: self.iface,
+      handle,
+    })
+  }
+}
+
+/// A file opened with [`VfsInterface::open`]. It's closed when dropped.
+#[derive(Debug)]
+pub struct VfsFile {
+  version: u32,
+  iface: &'static retro_vfs_interface,
+  handle: NonNull<retro_vfs_file_handle>,
+}
+
+impl VfsFile {
+  /// Returns the size of the file in bytes.
+  pub fn size(&self) -> Option<u64> {
+    let size = unsafe { self.iface.size?(self.handle.as_ptr()) };
+    u64::try_from(size).ok()
+  }
+
+  /// Reads into `buf`, returning the number of bytes read.
+  pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+    let len = buf.len() as u64;
+    let read = unsafe { self.iface.read?(self.handle.as_ptr(), buf.as_mut_ptr().cast(), len) };
+    usize::try_from(read).ok()
+  }
+
+  /// Writes from `buf`, returning the number of bytes written.
+  pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
+    let len = buf.len() as u64;
+    let written = unsafe { self.iface.write?(self.handle.as_ptr(), buf.as_ptr().cast(), len) };
+    usize::try_from(written).ok()
+  }
+
+  /// Flushes pending writes to the underlying file, so they survive the frontend exiting.
+  /// Returns `false` on failure.
+  ///
+  /// Flushing was added in version 1 of the interface; with older frontends, this does nothing.
+  pub fn flush(&mut self) -> bool {
+    match self.iface.flush {
+      Some(flush) if self.version >= 1 => unsafe { flush(self.handle.as_ptr()) == 0 },
+      _ => true,
+    }
+  }
+}
+
+impl io::Write for VfsFile {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    VfsFile::write(self, buf).ok_or_else(|| vfs_error("couldn't write file"))
+  }
+
+  /// Flushes pending writes through [`VfsFile::flush`].
+  fn flush(&mut self) -> io::Result<()> {
+    if VfsFile::flush(self) {
+      Ok(())
+    } else {
+      Err(vfs_error("couldn't flush file"))
+    }
+  }
+}
+
+impl Drop for VfsFile {
+  fn drop(&mut self) {
+    if let Some(close) = self.iface.close {
+      unsafe { close(self.handle.as_ptr()) };
+    }
+  }
+}
+
+/// A save state file managed by the core itself, instead of through `retro_serialize`.
+///
+/// Reads and writes go through the [`VfsInterface`] when one is available, so they work on
+/// platforms where the frontend sandboxes the file system, and through [`std::fs`] otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct StateFile<'a> {
+  path: &'a CStr,
+  vfs: Option<VfsInterface>,
+}
+
+impl<'a> StateFile<'a> {
+  pub fn new(path: &'a CStr, vfs: Option<VfsInterface>) -> Self {
+    Self { path, vfs }
+  }
+
+  /// Writes `data` to the file, replacing its contents.
+  pub fn save(&self, data: &[u8]) -> io::Result<()> {
+    let Some(vfs) = self.vfs else {
+      return std::fs::write(self.std_path()?, data);
+    };
+    let mut file = vfs
+      .open(self.path, RETRO_VFS_FILE_ACCESS_WRITE)
+      .ok_or_else(|| vfs_error("couldn't open state file for writing"))?;
+    let mut data = data;
+    while !data.is_empty() {
+      match file.write(data) {
+        Some(0) | None => return Err(vfs_error("couldn't write state file")),
+        Some(written) => data = &data[written..],
+      }
+    }
+    if file.flush() {
+      Ok(())
+    } else {
+      Err(vfs_error("couldn't flush state file"))
+    }
+  }
+
+  /// Reads the whole file.
+  pub fn load(&self) -> io::Result<Vec<u8>> {
+    read_file(self.path, self.vfs)
+  }
+
+  fn std_path(&self) -> io::Result<&'a str> {
+    std_path(self.path)
+  }
+}
+
+/// Reads the whole file at `path`, through `vfs` if available and through [`std::fs`] otherwise.
+pub(crate) fn read_file(path: &CStr, vfs: Option<VfsInterface>) -> io::Result<Vec<u8>> {
+  let Some(vfs) = vfs else {
+    return std::fs::read(std_path(path)?);
+  };
+  let mut file = vfs
+    .open(path, RETRO_VFS_FILE_ACCESS_READ)
+    .ok_or_else(|| vfs_error("couldn't open file for reading"))?;
+  let size = file
+    .size()
+    .and_then(|size| usize::try_from(size).ok())
+    .ok_or_else(|| vfs_error("couldn't get file size"))?;
+  let mut data = vec![0; size];
+  let mut filled = 0;
+  while filled < size {
+    match file.read(&mut data[filled..]) {
+      Some(0) => break,
+      Some(read) => filled += read,
+      None => return Err(vfs_error("couldn't read file")),
+    }
+  }
+  data.truncate(filled);
+  Ok(data)
+}
+
+fn std_path(path: &CStr) -> io::Result<&str> {
+  path
+    .to_str()
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
+
+fn vfs_error(message: &'static str) -> io::Error {
+  io::Error::other(message)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Mutex;
+
+  static FILE: Mutex<(Vec<u8>, usize)> = Mutex::new((Vec::new(), 0));
+
+  unsafe extern "C" fn open(
+    _path: *const c_char,
+    mode: c_uint,
+    _hints: c_uint,
+  ) -> *mut retro_vfs_file_handle {
+    let mut file = FILE.lock().unwrap();
+    if mode & RETRO_VFS_FILE_ACCESS_WRITE != 0 {
+      file.0.clear();
+    }
+    file.1 = 0;
+    NonNull::dangling().as_ptr()
+  }
+
+  unsafe extern "C" fn close(_stream: *mut retro_vfs_file_handle) -> c_int {
+    0
+  }
+
+  unsafe extern "C" fn size(_stream: *mut retro_vfs_file_handle) -> i64 {
+    FILE.lock().unwrap().0.len() as i64
+  }
+
+  unsafe extern "C" fn read(_stream: *mut retro_vfs_file_handle, s: *mut c_void, len: u64) -> i64 {
+    let mut file = FILE.lock().unwrap();
+    // Hand out at most 3 bytes at a time to exercise partial reads.
+    let len = (len as usize).min(file.0.len() - file.1).min(3);
+    core::ptr::copy_nonoverlapping(file.0[file.1..].as_ptr(), s.cast(), len);
+    file.1 += len;
+    len as i64
+  }
+
+  unsafe extern "C" fn write(
+    _stream: *mut retro_vfs_file_handle,
+    s: *const c_void,
+    len: u64,
+  ) -> i64 {
+    let len = (len as usize).min(3);
+    let data = core::slice::from_raw_parts(s.cast::<u8>(), len);
+    FILE.lock().unwrap().0.extend_from_slice(data);
+    len as i64
+  }
+
+  static FLUSHES: Mutex<usize> = Mutex::new(0);
+
+  unsafe extern "C" fn open_scratch(
+    _path: *const c_char,
+    _mode: c_uint,
+    _hints: c_uint,
+  ) -> *mut retro_vfs_file_handle {
+    NonNull::dangling().as_ptr()
+  }
+
+  unsafe extern "C" fn discard(
+    _stream: *mut retro_vfs_file_handle,
+    _s: *const c_void,
+    len: u64,
+  ) -> i64 {
+    len as i64
+  }
+
+  unsafe extern "C" fn flush(_stream: *mut retro_vfs_file_handle) -> c_int {
+    *FLUSHES.lock().unwrap() += 1;
+    0
+  }
+
+  #[test]
+  fn test_write_flush_is_forwarded() {
+    use std::io::Write;
+    let iface = Box::leak(Box::new(retro_vfs_interface {
+      open: Some(open_scratch),
+      close: Some(close),
+      write: Some(discard),
+      flush: Some(flush),
+      ..Default::default()
+    }));
+    let path = CStr::from_bytes_with_nul(b"/saves/game.srm\0").unwrap();
+    let mut file = VfsInterface::new(1, iface)
+      .open(path, RETRO_VFS_FILE_ACCESS_WRITE)
+      .unwrap();
+    file.write_all(b"save data").unwrap();
+    Write::flush(&mut file).unwrap();
+    assert_eq!(*FLUSHES.lock().unwrap(), 1);
+
+    let mut file = VfsInterface::new(0, iface)
+      .open(path, RETRO_VFS_FILE_ACCESS_WRITE)
+      .unwrap();
+    Write::flush(&mut file).unwrap();
+    assert_eq!(*FLUSHES.lock().unwrap(), 1, "version 0 has no flush");
+  }
+
+  #[test]
+  fn test_state_file_vfs_round_trip() {
+    let iface = Box::leak(Box::new(retro_vfs_interface {
+      open: Some(open),
+      close: Some(close),
+      size: Some(size),
+      read: Some(read),
+      write: Some(write),
+      ..Default::default()
+    }));
+    let path = CStr::from_bytes_with_nul(b"/saves/game.state\0").unwrap();
+    let state = StateFile::new(path, Some(VfsInterface::new(1, iface)));
+    let blob: Vec<u8> = (0..32).collect();
+    state.save(&blob).unwrap();
+    assert_eq!(FILE.lock().unwrap().0, blob);
+    assert_eq!(state.load().unwrap(), blob);
+  }
+}